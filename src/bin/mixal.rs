@@ -0,0 +1,129 @@
+extern crate knoxide;
+
+use knoxide::arch_util;
+use knoxide::mix_deck;
+use knoxide::mixal_asm;
+use knoxide::mixal_listing;
+use std::io::prelude::*;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let mut flag_h = false;
+    let mut flag_c = false;
+    let mut infile: Option<&String> = None;
+    let mut outfile: Option<&String> = None;
+    let mut listfile: Option<&String> = None;
+
+    // Poor man's getopt
+    let mut args_iter = args[1..].iter();
+    while let Some(arg) = args_iter.next() {
+        if arg == "-h" || arg == "--help" {
+            flag_h = true;
+        }
+        else if arg == "-c" || arg == "--deck" {
+            flag_c = true;
+        }
+        else if arg == "-l" || arg == "--listing" {
+            listfile = args_iter.next();
+            if listfile.is_none() {
+                eprintln!("mixal: {} needs a file name!", arg);
+                std::process::exit(1);
+            }
+        }
+        else if infile.is_none() {
+            infile = Some(arg);
+        } else if outfile.is_none() {
+            outfile = Some(arg);
+        } else {
+            eprintln!("mixal: Too many arguments!");
+            std::process::exit(1);
+        }
+    }
+
+    if flag_h {
+        println!("Usage: mixal [-c] [-l listfile] [infile] [outfile]");
+        println!("Assemble MIXAL source into a binary memory image.");
+        println!("-c: punch an object deck instead, led by the card loader, \
+                  to boot with mixrun -g");
+        println!("-l: also write an assembly listing to listfile");
+        std::process::exit(2);
+    }
+
+    // If '-' is passed as infile or outfile, use stdin/stdout instead.
+    if let Some(s) = infile {
+        if s == "-" {
+            infile = None;
+        }
+    }
+    if let Some(s) = outfile {
+        if s == "-" {
+            outfile = None;
+        }
+    }
+
+    let mut src = String::new();
+    match infile {
+        Some(s) => {
+            let mut f = std::fs::File::open(s).expect(
+                "mixal: Failed to open file!");
+            if let Err(e) = f.read_to_string(&mut src) {
+                eprintln!("mixal: read failed! {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => {
+            if let Err(e) = std::io::stdin().read_to_string(&mut src) {
+                eprintln!("mixal: read failed! {}", e);
+                std::process::exit(1);
+            }
+        },
+    };
+
+    let prog = match mixal_asm::assemble(&src) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("mixal: {}: {}", infile.map_or("<stdin>", |s| s), e);
+            std::process::exit(1);
+        },
+    };
+
+    if let Some(s) = listfile {
+        let listing = mixal_listing::fmt_listing(&src, &prog);
+        let mut f = std::fs::File::create(s).expect(
+            "mixal: Failed to open file!");
+        if let Err(e) = write!(f, "{}", listing) {
+            eprintln!("mixal: write failed! {}", e);
+        }
+    }
+
+    let mut out_bytes: Vec<u8> = vec![];
+    if flag_c {
+        let words: Vec<_> = prog.words.iter().map(|w| (w.addr, w.word)).collect();
+        match mix_deck::punch_deck(&words, prog.start) {
+            Ok(deck) => out_bytes.extend(deck.as_bytes()),
+            Err(e) => {
+                eprintln!("mixal: {}", e);
+                std::process::exit(1);
+            },
+        }
+    } else {
+        for w in prog.memory.iter() {
+            out_bytes.extend(arch_util::u32to8s(*w).iter());
+        }
+    }
+
+    match outfile {
+        Some(s) => {
+            let mut f = std::fs::File::create(s).expect(
+                "mixal: Failed to open file!");
+            if let Err(e) = f.write_all(&out_bytes) {
+                eprintln!("mixal: write failed! {}", e);
+            }
+        },
+        None => {
+            if let Err(e) = std::io::stdout().write_all(&out_bytes) {
+                eprintln!("mixal: write failed! {}", e);
+            }
+        },
+    };
+}
@@ -1,75 +1,685 @@
 extern crate rustyline;
 extern crate knoxide;
 
+use std::collections::HashMap;
+
 use knoxide::mix_core;
+use knoxide::mix_expr;
+use knoxide::mix_fmt;
+use knoxide::mix_image;
+use knoxide::mix_image::MixImage;
+use knoxide::mix_load;
+use knoxide::mix_load::{DebugInfo, ProgramFormat};
+use knoxide::mix_mdk;
+use knoxide::mix_snapshot;
+use knoxide::mix_trace;
 use knoxide::mix_types::*;
+use knoxide::mix_util;
+use knoxide::mixdb_command;
+use knoxide::mixdb_command::{Command, Location, PrintFormat};
 
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 
-fn handle_input(line: String, st: &mut MixState) -> () {
-    let words = line.trim().split(" ").collect::<Vec<_>>();
-    // For now, ignore all bad commands.
-    match words.len() {
-        0 => {},
-        1 => {
-            if words[0] == "run" || words[0] == "r" {
-                mix_core::do_mix_run(st);
-            } else if words[0] == "step" || words[0] == "s" {
-                mix_core::do_mix_step(st);
-            } else  {
-                println!("Bad command");
-            }
-        },
-        2 => {
-            if words[0] == "show" {
-                if words[1] == "registers" || words[1] == "r" {
-                    println!("{:#?}", st.r);
-                } else if words[1] == "state" || words[1] == "st" {
-                    // TODO make it printable
-                    // println!("{:#?}", st);
-                } else if words[1] == "instructions" || words[1] == "i" {
-                    // TODO: nice disassembled instructions
-                    println!("{:#?}", st.pc);
-                    let lb = std::cmp::max(0, st.pc as usize - 10);
-                    let ub = std::cmp::min(MEM_SIZE - 1, st.pc as usize + 10);
-                    println!("{:#?}", &st.memory[lb .. ub]);
+// Debugger state kept alongside the machine.
+struct Session {
+    // Symbols of the loaded program, for breakpoints by label and for
+    // expressions.
+    symbols: HashMap<String, i32>,
+    // The text of each breakpoint's condition, as the user wrote it.
+    conditions: HashMap<usize, String>,
+    // The machine as it was after the last load, for reload.
+    pristine: Option<MixImage>,
+    // Where the loaded program came from, if it was MIXAL source.
+    debug: Option<DebugInfo>,
+}
+
+// Lines of context shown around the current line when execution stops,
+// and by list.
+const STOP_CONTEXT: usize = 2;
+const LIST_CONTEXT: usize = 5;
+
+// The most instructions of data a step goes over before giving up on
+// reaching an instruction.
+const STEP_DATA_LIMIT: usize = MEM_SIZE;
+
+fn handle_input(line: String, st: &mut MixState, session: &mut Session) {
+    let cmd = match mixdb_command::parse_command(&line) {
+        Ok(Some(c)) => c,
+        Ok(None) => return,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        },
+    };
+    match cmd {
+        Command::Help => {
+            for (syntax, what) in mixdb_command::USAGE {
+                if syntax.len() > 34 {
+                    println!("  {}\n  {:34} {}", syntax, "", what);
+                } else {
+                    println!("  {:34} {}", syntax, what);
                 }
-                else {
-                    println!("Bad command");
+            }
+        },
+        Command::Run | Command::Continue => {
+            if cmd == Command::Run {
+                if !reload(st, session) {
+                    return;
                 }
+                println!("Starting the program at {:04}", st.pc);
+            }
+            let outcome = mix_core::do_mix_run(st, &StopCondition::default());
+            show_stop(st, &outcome.reason);
+            show_current_line(st, session);
+        },
+        Command::Step => {
+            step(st, session);
+            show_watch_hit(st);
+            show_current_line(st, session);
+        },
+        Command::Next => {
+            let instr = st.memory[st.pc as usize];
+            let is_jmp = mix_util::get_bytes(instr, 5, 5) == 39 &&
+                mix_util::get_bytes(instr, 4, 4) == 0;
+            if is_jmp {
+                // Run until the call returns, or until it turns out not to
+                // be a call because the way back is lost.
+                let depth = st.calls.len();
+                let outcome = mix_core::do_mix_run(st, &StopCondition {
+                    done: Some(&|st| st.calls.len() <= depth),
+                    ..Default::default()
+                });
+                show_stop(st, &outcome.reason);
             } else {
-                println!("Bad command");
+                step(st, session);
+                show_watch_hit(st);
+            }
+            show_current_line(st, session);
+        },
+        Command::Finish => {
+            let depth = st.calls.len();
+            let entry = match st.calls.last() {
+                Some(frame) => frame.entry,
+                None => {
+                    println!("Not in a subroutine");
+                    return;
+                },
+            };
+            println!("Run till exit from {}", describe(entry, session));
+            let outcome = mix_core::do_mix_run(st, &StopCondition {
+                done: Some(&|st| st.calls.len() < depth),
+                ..Default::default()
+            });
+            match outcome.reason {
+                StopReason::Done => println!("Returned to {}", describe(st.pc, session)),
+                reason => show_stop(st, &reason),
+            }
+            show_current_line(st, session);
+        },
+        Command::ReverseStep => {
+            // As with step, stop on an instruction rather than on data.
+            loop {
+                if let Err(msg) = mix_core::step_back(st) {
+                    println!("{}", msg);
+                    break;
+                }
+                match &session.debug {
+                    Some(debug) if debug.is_data(st.pc) => {},
+                    _ => break,
+                }
+            }
+            show_current_line(st, session);
+        },
+        Command::ReverseContinue => {
+            match mix_core::do_mix_reverse_run(st) {
+                Ok(reason) => show_stop(st, &reason),
+                Err(msg) => println!("{}", msg),
+            }
+            show_current_line(st, session);
+        },
+        Command::Goto(n) => {
+            if let Err(msg) = mix_core::goto_instruction(st, n) {
+                println!("{}", msg);
+            }
+            println!("At instruction {}", st.instructions);
+            show_current_line(st, session);
+        },
+        Command::TraceOn(format, ranges, file) => {
+            let out: Box<dyn std::io::Write> = match &file {
+                Some(path) => match std::fs::File::create(path) {
+                    Ok(f) => Box::new(std::io::BufWriter::new(f)),
+                    Err(e) => {
+                        println!("Failed to open {}: {}", path, e);
+                        return;
+                    },
+                },
+                None => Box::new(std::io::stdout()),
+            };
+            let ranges = ranges.iter()
+                .map(|(first, last)| (*first as MixAddr, *last as MixAddr))
+                .collect();
+            st.tracer = Some(mix_trace::Tracer::new(format, ranges, out));
+            match file {
+                Some(path) => println!("Tracing to {}", path),
+                None => println!("Tracing"),
+            }
+        },
+        Command::TraceOff => {
+            // Dropping the tracer flushes what it has written.
+            st.tracer = None;
+            println!("Tracing off");
+        },
+        Command::Record(true) => {
+            if st.history.is_none() {
+                mix_core::start_recording(st);
+            }
+            println!("Recording from instruction {}", st.instructions);
+        },
+        Command::Record(false) => {
+            mix_core::stop_recording(st);
+            println!("Recording off");
+        },
+        Command::Backtrace => {
+            // The innermost frame is where execution is; each other is
+            // where the next one in was called from.
+            println!("#0  {}", describe(st.pc, session));
+            for (k, frame) in st.calls.iter().rev().enumerate() {
+                println!("#{:<2} {}{}", k + 1, describe(frame.call, session),
+                         if frame.saved { "" } else { " [rJ not yet saved]" });
+            }
+        },
+        Command::Break(loc, cond) => {
+            let addr = match resolve(&loc, session) {
+                Some(a) => a,
+                None => return,
+            };
+            let condition = match &cond {
+                Some(c) => match mix_expr::parse_expr(c, &session.symbols) {
+                    Ok(e) => Some(e),
+                    Err(e) => {
+                        println!("Bad condition: {}", e);
+                        return;
+                    },
+                },
+                None => None,
+            };
+            let id = mix_core::add_breakpoint(st, addr);
+            if let Some(b) = mix_core::find_breakpoint(st, id) {
+                b.condition = condition;
+            }
+            if let Some(c) = cond {
+                session.conditions.insert(id, c);
+            }
+            println!("Breakpoint {} at {:04}", id, addr);
+        },
+        Command::Print(format, text) => {
+            let e = match mix_expr::parse_expr(&text, &session.symbols) {
+                Ok(e) => e,
+                Err(e) => {
+                    println!("{}", e);
+                    return;
+                },
+            };
+            let shown = match format {
+                PrintFormat::Integer => mix_expr::eval(&e, st)
+                    .map(|v| v.to_string()),
+                PrintFormat::Word => mix_expr::eval_word(&e, st)
+                    .map(mix_fmt::fmt_word),
+                PrintFormat::Text => mix_expr::eval_word(&e, st)
+                    .map(|w| format!("\"{}\"", mix_fmt::fmt_text(w))),
+            };
+            match shown {
+                Ok(s) => println!("{}", s),
+                Err(e) => println!("{}", e),
+            }
+        },
+        Command::Watch(kind, loc, f) => {
+            let addr = match resolve(&loc, session) {
+                Some(a) => a,
+                None => return,
+            };
+            let id = mix_core::add_watchpoint(st, addr, f, kind);
+            println!("Watchpoint {} on {:04}({}:{})", id, addr, f / 8, f % 8);
+        },
+        Command::Delete(None) => {
+            st.breakpoints.clear();
+            st.watchpoints.clear();
+            session.conditions.clear();
+        },
+        Command::Delete(Some(id)) => {
+            if mix_core::find_breakpoint(st, id).is_some() ||
+                mix_core::find_watchpoint(st, id).is_some() {
+                st.breakpoints.retain(|b| b.id != id);
+                st.watchpoints.retain(|w| w.id != id);
+                session.conditions.remove(&id);
+            } else {
+                println!("No breakpoint number {}", id);
+            }
+        },
+        Command::Disable(id) | Command::Enable(id) => {
+            let enable = cmd == Command::Enable(id);
+            if let Some(b) = mix_core::find_breakpoint(st, id) {
+                b.enabled = enable;
+            } else if let Some(w) = mix_core::find_watchpoint(st, id) {
+                w.enabled = enable;
+            } else {
+                println!("No breakpoint number {}", id);
+            }
+        },
+        Command::Ignore(id, count) => {
+            match mix_core::find_breakpoint(st, id) {
+                Some(b) => {
+                    b.ignore = count;
+                    println!("Will ignore next {} crossings of breakpoint {}",
+                             count, id);
+                },
+                None => println!("No breakpoint number {}", id),
+            }
+        },
+        Command::InfoBreakpoints => {
+            if st.breakpoints.is_empty() && st.watchpoints.is_empty() {
+                println!("No breakpoints or watchpoints");
+                return;
+            }
+            println!("Num  Type        Enb  Where        Hits  Ignore");
+            for b in &st.breakpoints {
+                println!("{:<4} {:<11} {:<4} {:04}         {:<5} {}", b.id,
+                         "breakpoint", if b.enabled { "y" } else { "n" },
+                         b.addr, b.hits, b.ignore);
+                if let Some(c) = session.conditions.get(&b.id) {
+                    println!("     stop only if {}", c);
+                }
+            }
+            for w in &st.watchpoints {
+                let kind = match w.kind {
+                    WatchKind::Write => "watch",
+                    WatchKind::Read => "rwatch",
+                    WatchKind::Access => "awatch",
+                };
+                println!("{:<4} {:<11} {:<4} {:04}({}:{})    {}", w.id, kind,
+                         if w.enabled { "y" } else { "n" }, w.addr,
+                         w.field / 8, w.field % 8, w.hits);
+            }
+        },
+        Command::List(line) => {
+            let debug = match &session.debug {
+                Some(d) => d,
+                None => {
+                    println!("No source is loaded");
+                    return;
+                },
+            };
+            let center = match line.or_else(|| debug.loc(st.pc).map(|l| l.line)) {
+                Some(l) => l,
+                None => {
+                    println!("No source for address {:04}", st.pc);
+                    return;
+                },
+            };
+            let mark = debug.loc(st.pc).map(|l| l.line);
+            show_source(debug, center, LIST_CONTEXT, mark);
+        },
+        Command::Load(path, at) => load_program(&path, at, st, session),
+        Command::Reload => if reload(st, session) {
+            println!("Reloaded the program");
+        },
+        Command::Dump(path) => dump_memory(&path, st),
+        Command::Write(path) => write_image(&path, st),
+        Command::Save(path) => {
+            let snap = mix_snapshot::take_snapshot(st);
+            match std::fs::write(&path, mix_snapshot::write_snapshot(&snap)) {
+                Ok(()) => println!("Saved the machine to {}", path),
+                Err(e) => println!("Failed to write {}: {}", path, e),
+            }
+        },
+        Command::Restore(path) => {
+            let snap = std::fs::read(&path).map_err(|e| e.to_string())
+                .and_then(|b| mix_snapshot::read_snapshot(&b)
+                          .map_err(|e| e.to_string()));
+            match snap {
+                Ok(snap) => {
+                    mix_snapshot::restore_snapshot(st, &snap);
+                    println!("Restored the machine from {}", path);
+                    show_current_line(st, session);
+                },
+                Err(e) => println!("Failed to restore {}: {}", path, e),
+            }
+        },
+        Command::ShowRegisters => print!("{}", mix_fmt::fmt_registers(&st.r)),
+        Command::ShowState => print!("{}", mix_fmt::fmt_state(st)),
+        Command::ShowInstructions => {
+            let pc = st.pc as usize;
+            show_memory(st, pc.saturating_sub(10),
+                        std::cmp::min(MEM_SIZE - 1, pc + 10));
+        },
+        Command::ShowMemory(first, last) => show_memory(st, first, last),
+        Command::SetRegister(reg, w) => {
+            // The parser has checked that index and J values fit.
+            let a = mixdb_command::word_to_addr(w).unwrap_or(0);
+            match reg {
+                Register::A => st.r.a = w,
+                Register::X => st.r.x = w,
+                Register::I(n) => st.r.i[n - 1] = a,
+                Register::J => st.r.j = a,
+            }
+            restart_history(st);
+        },
+        Command::SetMemory(addr, w) => {
+            st.memory[addr] = w;
+            restart_history(st);
+        },
+        Command::SetOverflow(on) => {
+            st.overflow = on;
+            restart_history(st);
+        },
+        Command::SetComparison(c) => {
+            st.comparison = c;
+            restart_history(st);
+        },
+        Command::SetPc(addr) => {
+            st.pc = addr;
+            restart_history(st);
+        },
+    }
+}
+
+// Start the history again after changing the machine by hand, since
+// running forward from a checkpoint wouldn't repeat the change.
+fn restart_history(st: &mut MixState) {
+    if st.history.is_some() {
+        mix_core::start_recording(st);
+    }
+}
+
+// Find the address of a location, printing an error if it's an unknown
+// label or one whose value isn't an address.
+fn resolve(loc: &Location, session: &Session) -> Option<MixAddr> {
+    match loc {
+        Location::Address(a) => Some(*a as MixAddr),
+        Location::Line(file, line) => {
+            let debug = match &session.debug {
+                Some(d) => d,
+                None => {
+                    println!("No source is loaded");
+                    return None;
+                },
+            };
+            if !debug.is_file(file) {
+                println!("No source file {}; the program is from {}", file,
+                         debug.file);
+                return None;
             }
+            let a = debug.line_addr(*line);
+            if a.is_none() {
+                println!("No instructions at or after {}:{}", file, line);
+            }
+            a
+        },
+        Location::Label(l) => match session.symbols.get(l) {
+            Some(a) if (0 .. MEM_SIZE as i32).contains(a) => Some(*a as MixAddr),
+            Some(a) => {
+                println!("{} is {}, which is outside memory", l, a);
+                None
+            },
+            None => {
+                println!("No label {} in the loaded program", l);
+                None
+            },
+        },
+    }
+}
+
+// Say why a run stopped. A run stopping where the command wanted needs no
+// saying.
+fn show_stop(st: &MixState, reason: &StopReason) {
+    match reason {
+        StopReason::Halted => println!("Halted!"),
+        StopReason::Fault(msg) => println!("Panicked! {}", msg),
+        StopReason::Breakpoint(addr) => match st.breakpoint_hit {
+            Some(id) => println!("Breakpoint {} at {:04}", id, addr),
+            None => println!("Stopped at {:04}", addr),
+        },
+        StopReason::Watchpoint(_) => show_watch_hit(st),
+        StopReason::LimitReached => println!("Stopped at the limit"),
+        StopReason::WaitingForInput(unit) => println!("Waiting for input on unit {}", unit),
+        StopReason::Done => {},
+    }
+}
+
+// Show the access which triggered a watchpoint during the last step or run,
+// with the instruction that made it.
+fn show_watch_hit(st: &MixState) {
+    if let Some(hit) = &st.watch_hit {
+        let instr = st.memory[hit.pc as usize];
+        println!("Watchpoint {}: {:04} {} by {:04}: {}", hit.id, hit.addr,
+                 if hit.write { "written" } else { "read" }, hit.pc,
+                 mix_fmt::disasm_word(instr));
+        if hit.write {
+            println!("Old: {}", mix_fmt::fmt_word(hit.old));
+            println!("New: {}", mix_fmt::fmt_word(hit.new));
+        } else {
+            println!("Value: {}", mix_fmt::fmt_word(hit.new));
         }
-        3 => {} // TODO: set registers, set memory, show memory,
-                // load from file into memory starting at position.
-        4 => {} // TODO: show memory range
-        _ => println!("Bad command"),
     }
-    // TODO: fancier commands: breakpoints and watchpoints.
-    // (can check in memory get, memory set, and step/jump)
+}
+
+// Execute an instruction. A step stops on an instruction, not on CON or ALF
+// data, even if the program jumps into some.
+fn step(st: &mut MixState, session: &Session) {
+    mix_core::do_mix_step(st);
+    if let Some(debug) = &session.debug {
+        let mut n = 0;
+        while debug.is_data(st.pc) && st.panic_msg.is_none() &&
+            st.watch_hit.is_none() && st.waiting.is_none() && n < STEP_DATA_LIMIT {
+            mix_core::do_mix_step(st);
+            n += 1;
+        }
+    }
+    if let Some(msg) = &st.panic_msg {
+        println!("Panicked! {}", msg);
+    }
+    if let Some(unit) = st.waiting {
+        println!("Waiting for input on unit {}", unit);
+    }
+}
+
+// Describe a location: its address, the nearest label before it in the
+// source, and its source line.
+fn describe(addr: MixAddr, session: &Session) -> String {
+    let debug = match &session.debug {
+        Some(d) => d,
+        None => return format!("{:04}", addr),
+    };
+    let mut desc = format!("{:04}", addr);
+    // Look back through the words assembled without a gap for a label.
+    let mut a = addr;
+    while let Some(loc) = debug.loc(a) {
+        if let Some(label) = &loc.label {
+            desc.push_str(&format!(" {}", label));
+            if a != addr {
+                desc.push_str(&format!("+{}", addr - a));
+            }
+            break;
+        }
+        if a == 0 {
+            break;
+        }
+        a -= 1;
+    }
+    if let Some(loc) = debug.loc(addr) {
+        desc.push_str(&format!(" ({}:{})", debug.file, loc.line));
+    }
+    desc
+}
+
+// Show lines of source around a line, numbered, marking the line under
+// the program counter.
+fn show_source(debug: &DebugInfo, center: usize, context: usize,
+               mark: Option<usize>) {
+    let first = std::cmp::max(1, center.saturating_sub(context));
+    let last = std::cmp::min(debug.lines.len(), center + context);
+    for line in first ..= last {
+        println!("{} {:4}  {}", if Some(line) == mark { "=>" } else { "  " },
+                 line, debug.lines[line - 1]);
+    }
+}
+
+// Show where execution has stopped in the source, if there is any.
+fn show_current_line(st: &MixState, session: &Session) {
+    if let Some(debug) = &session.debug {
+        if let Some(loc) = debug.loc(st.pc) {
+            match &loc.label {
+                Some(label) => println!("{}:{} ({})", debug.file, loc.line, label),
+                None => println!("{}:{}", debug.file, loc.line),
+            }
+            show_source(debug, loc.line, STOP_CONTEXT, Some(loc.line));
+        }
+    }
+}
+
+// Show a range of memory, with each word disassembled, marking the word
+// under the program counter.
+fn show_memory(st: &MixState, first: usize, last: usize) {
+    for addr in first ..= last {
+        let w = st.memory[addr];
+        println!("{} {:04}: {}  {}",
+                 if addr == st.pc as usize { "=>" } else { "  " }, addr,
+                 mix_fmt::fmt_word(w), mix_fmt::disasm_word(w));
+    }
+}
+
+// Load a program from a file, and keep the machine as it then is so that
+// reload can go back to it. Loading over the whole machine replaces the
+// symbols with those of the program, if any.
+fn load_program(path: &str, at: Option<usize>, st: &mut MixState,
+                session: &mut Session) {
+    let bytes = match std::fs::read(path) {
+        Ok(b) => b,
+        Err(e) => {
+            println!("Failed to read {}: {}", path, e);
+            return;
+        },
+    };
+    let prog = match mix_load::read_program(path, &bytes) {
+        Ok(p) => p,
+        Err(e) => {
+            for line in e.message.lines() {
+                println!("{}: {}", path, line);
+            }
+            return;
+        },
+    };
+    if at.is_some() && prog.format == ProgramFormat::Mixal {
+        println!("{}: MIXAL source is placed by its ORIG lines, not at an \
+                  address", path);
+        return;
+    }
+    if let Err(e) = mix_load::load_image(st, &prog.image, at.map(|a| a as MixAddr)) {
+        println!("{}: {}", path, e);
+        return;
+    }
+    if at.is_none() {
+        session.symbols = prog.symbols;
+        session.debug = prog.debug;
+    } else {
+        restart_history(st);
+    }
+    session.pristine = Some(mix_load::save_image(st));
+    println!("Loaded {} words of {} from {}", prog.size, prog.format.name(), path);
+    if at.is_none() {
+        println!("Start address {:04}", prog.image.start);
+    }
+}
+
+// Put the machine back as it was after the last load. Returns false, having
+// said so, if nothing has been loaded.
+fn reload(st: &mut MixState, session: &Session) -> bool {
+    match &session.pristine {
+        Some(image) => {
+            // A saved image always fits.
+            mix_load::load_image(st, image, None).unwrap_or(());
+            true
+        },
+        None => {
+            println!("No program has been loaded");
+            false
+        },
+    }
+}
+
+// Write memory, the registers and the program counter (as the start
+// address) to a file as a portable image. A file named *.mix is written as
+// a GNU MDK program instead, which has no registers.
+fn write_image(path: &str, st: &MixState) {
+    let image = mix_load::save_image(st);
+    let bytes = if path.ends_with(".mix") {
+        mix_mdk::write_mdk(&image)
+    } else {
+        mix_image::write_image(&image)
+    };
+    match std::fs::write(path, bytes) {
+        Ok(()) => println!("Wrote image to {}", path),
+        Err(e) => println!("Failed to write {}: {}", path, e),
+    }
+}
+
+// Write memory to a file as an addressed dump.
+fn dump_memory(path: &str, st: &MixState) {
+    let dump = mix_fmt::fmt_memory(&st.memory[..], false);
+    match std::fs::write(path, dump) {
+        Ok(()) => println!("Dumped memory to {}", path),
+        Err(e) => println!("Failed to write {}: {}", path, e),
+    }
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let mut infile: Option<&String> = None;
+    let mut flag_n = false;
+
+    // Poor man's getopt
+    for arg in args[1..].iter() {
+        if arg == "-h" || arg == "--help" {
+            println!("Usage: mixdb [-n] [file]");
+            println!("Debug a MIX program. The file, if given, is loaded as by \
+                      the load command:");
+            println!("a portable image, an MDK program, a raw image, a dump or \
+                      MIXAL source.");
+            println!("-n: don't record execution until 'record on'");
+            return;
+        }
+        else if arg == "-n" || arg == "--no-record" {
+            flag_n = true;
+        }
+        else if arg.starts_with('-') {
+            eprintln!("mixdb: Unknown option '{}'!", arg);
+            std::process::exit(1);
+        }
+        else if infile.is_none() {
+            infile = Some(arg);
+        }
+        else {
+            eprintln!("mixdb: Too many arguments!");
+            std::process::exit(1);
+        }
+    }
+
     // Initialize MIX state
-    // let mut mem: MixMemory = [0; MEM_SIZE];
-    let mem: MixMemory = [0; MEM_SIZE];
-    let mut st = MixState {
-        r: MixRegisters {
-            a: 0,
-            x: 0,
-            i: [0, 0, 0, 0, 0, 0],
-            j: 0,
-        },
-        comparison: MixCompare::Equal,
-        overflow: false,
-        memory: &mem,
-        io: (),
-        pc: 0,
-        is_running: false,
-        panic_msg: None,
+    let mut mem: MixMemory = [0; MEM_SIZE];
+    let mut st = MixState::new(&mut mem);
+    if !flag_n {
+        mix_core::start_recording(&mut st);
+    }
+    let mut session = Session {
+        symbols: HashMap::new(),
+        conditions: HashMap::new(),
+        pristine: None,
+        debug: None,
     };
+    if let Some(path) = infile {
+        load_program(path, None, &mut st, &mut session);
+    }
 
     // Readline loop
 
@@ -82,7 +692,7 @@ fn main() {
         match readline {
             Ok(line) => {
                 rl.add_history_entry(line.as_ref());
-                handle_input(line, &mut st);
+                handle_input(line, &mut st, &mut session);
             },
             Err(ReadlineError::Interrupted) => {
                 println!("CTRL-C");
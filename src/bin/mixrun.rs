@@ -0,0 +1,202 @@
+extern crate knoxide;
+
+use std::io::prelude::*;
+
+use knoxide::mix_batch;
+use knoxide::mix_batch::Limits;
+use knoxide::mix_core;
+use knoxide::mix_fmt;
+use knoxide::mix_io;
+use knoxide::mix_load;
+use knoxide::mix_types::*;
+
+// Exit statuses. Anything wrong before the program runs is 1.
+const EXIT_HALTED: i32 = 0;
+const EXIT_FAULT: i32 = 2;
+const EXIT_LIMIT: i32 = 3;
+
+// The printer's unit, which writes to standard output unless given a file.
+const PRINTER: u8 = 18;
+
+fn usage_error(msg: &str) -> ! {
+    eprintln!("mixrun: {}", msg);
+    std::process::exit(1);
+}
+
+// Parse the argument of -i or -o: a unit, '=' and a file. The unit must
+// be able to read, for input, or write, for output.
+fn parse_attachment(arg: Option<&String>, flag: &str, output: bool)
+    -> (u8, String) {
+    let arg = match arg {
+        Some(a) => a,
+        None => usage_error(&format!("{} needs a unit and a file!", flag)),
+    };
+    let (unit, file) = match arg.find('=') {
+        Some(ix) => (&arg[.. ix], &arg[ix + 1 ..]),
+        None => usage_error(&format!("{} takes unit=file, not '{}'!", flag, arg)),
+    };
+    let unit = match unit.parse::<u8>() {
+        Ok(u) if !file.is_empty() => u,
+        _ => usage_error(&format!("Bad unit and file '{}'!", arg)),
+    };
+    match mix_io::check_attachment(unit, output) {
+        Ok(_) => (unit, file.to_string()),
+        Err(e) => usage_error(&format!("{}!", e)),
+    }
+}
+
+fn parse_count(arg: Option<&String>, flag: &str) -> u64 {
+    match arg.map(|a| a.parse::<u64>()) {
+        Some(Ok(n)) => n,
+        _ => usage_error(&format!("{} needs a number!", flag)),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let mut flag_q = false;
+    let mut flag_g = false;
+    let mut infile: Option<&String> = None;
+    let mut inputs: Vec<(u8, String)> = vec![];
+    let mut outputs: Vec<(u8, String)> = vec![];
+    let mut limit = mix_batch::DEFAULT_INSTRUCTION_LIMIT;
+    let mut time_limit = 0;
+
+    // Poor man's getopt
+    let mut args_iter = args[1..].iter();
+    while let Some(arg) = args_iter.next() {
+        if arg == "-h" || arg == "--help" {
+            println!("Usage: mixrun [-q] [-l count] [-t time] [-i unit=file]... \
+                      [-o unit=file]... file");
+            println!("       mixrun -g -i 16=deck [options]");
+            println!("Run a MIX program until it halts. The file is loaded as by \
+                      mixdb: a portable");
+            println!("image, a raw image, a dump or MIXAL source.");
+            println!("-g: press GO instead, booting from the deck in the card \
+                      reader (see mixal -c)");
+            println!("-i: attach a device to the unit, with its medium read from \
+                      the file");
+            println!("-o: attach a device to the unit, and write its medium to \
+                      the file at the end");
+            println!("    ('-' for standard output). The printer, unit 18, writes \
+                      to standard output");
+            println!("    unless given a file.");
+            println!("-l: stop after count instructions (default {}; 0 for no \
+                      limit)", mix_batch::DEFAULT_INSTRUCTION_LIMIT);
+            println!("-t: stop after time units of time (default no limit)");
+            println!("-q: don't print the registers and time to standard error \
+                      at the end");
+            println!("Exit status: {} if the program halts, {} if it faults, {} \
+                      if it reaches a limit,", EXIT_HALTED, EXIT_FAULT, EXIT_LIMIT);
+            println!("and 1 if it can't be run.");
+            return;
+        }
+        else if arg == "-q" || arg == "--quiet" {
+            flag_q = true;
+        }
+        else if arg == "-g" || arg == "--go" {
+            flag_g = true;
+        }
+        else if arg == "-i" || arg == "--input" {
+            inputs.push(parse_attachment(args_iter.next(), arg, false));
+        }
+        else if arg == "-o" || arg == "--output" {
+            outputs.push(parse_attachment(args_iter.next(), arg, true));
+        }
+        else if arg == "-l" || arg == "--limit" {
+            limit = parse_count(args_iter.next(), arg);
+        }
+        else if arg == "-t" || arg == "--time" {
+            time_limit = parse_count(args_iter.next(), arg);
+        }
+        else if arg.starts_with('-') {
+            usage_error(&format!("Unknown option '{}'!", arg));
+        }
+        else if infile.is_none() {
+            infile = Some(arg);
+        }
+        else {
+            usage_error("Too many arguments!");
+        }
+    }
+    let mut mem: MixMemory = [0; MEM_SIZE];
+    let mut st = MixState::new(&mut mem);
+    match (infile, flag_g) {
+        (Some(_), true) => usage_error("-g boots from the card reader, not a file!"),
+        (None, false) => usage_error("No program given!"),
+        (None, true) => {},
+        (Some(infile), false) => {
+            let prog = std::fs::read(infile).map_err(|e| e.to_string())
+                .and_then(|b| mix_load::read_program(infile, &b).map_err(|e| e.to_string()));
+            match prog {
+                Ok(prog) => {
+                    if let Err(e) = mix_load::load_image(&mut st, &prog.image, None) {
+                        usage_error(&format!("{}: {}", infile, e));
+                    }
+                },
+                Err(e) => usage_error(&format!("{}: {}", infile, e)),
+            }
+        },
+    }
+
+    // Devices: those read from files, then those only written.
+    for (unit, file) in &inputs {
+        let dev = std::fs::read(file).map_err(|e| e.to_string())
+            .and_then(|b| mix_io::read_medium(*unit, &b).map_err(|e| e.to_string()));
+        match dev {
+            Ok(dev) => mix_io::attach(&mut st, dev),
+            Err(e) => usage_error(&format!("{}: {}", file, e)),
+        }
+    }
+    if !outputs.iter().any(|(unit, _)| *unit == PRINTER) {
+        outputs.push((PRINTER, "-".to_string()));
+    }
+    for (unit, _) in &outputs {
+        if !inputs.iter().any(|(u, _)| u == unit) {
+            mix_io::attach(&mut st, mix_io::new_device(*unit).unwrap());
+        }
+    }
+
+    if flag_g {
+        if let Err(e) = mix_core::go_button(&mut st) {
+            usage_error(&e);
+        }
+    }
+
+    let limits = Limits { instructions: limit, time: time_limit };
+    let outcome = mix_batch::run_batch(&mut st, limits);
+
+    // Output written so far is kept, however the run ended.
+    for (unit, file) in &outputs {
+        let dev = st.io.iter().find(|d| d.unit == *unit).unwrap();
+        let bytes = mix_io::write_medium(dev);
+        let written = if file == "-" {
+            std::io::stdout().write_all(&bytes)
+        } else {
+            std::fs::write(file, &bytes)
+        };
+        if let Err(e) = written {
+            eprintln!("mixrun: Failed to write {}: {}", file, e);
+        }
+    }
+
+    if !flag_q {
+        match &outcome.reason {
+            StopReason::Halted => eprintln!("Halted."),
+            StopReason::Fault(msg) =>
+                eprintln!("Faulted at instruction {}: {}", st.instructions, msg),
+            // Nothing more will be attached, so this is the end.
+            StopReason::WaitingForInput(unit) =>
+                eprintln!("Faulted: no more input on unit {}.", unit),
+            _ if limits.out_of_time(&outcome.stats) =>
+                eprintln!("Stopped: limit of {} units of time reached.", time_limit),
+            _ => eprintln!("Stopped: limit of {} instructions reached.", limit),
+        }
+        eprint!("{}", mix_fmt::fmt_state(&st));
+    }
+    std::process::exit(match outcome.reason {
+        StopReason::Halted => EXIT_HALTED,
+        StopReason::Fault(_) | StopReason::WaitingForInput(_) => EXIT_FAULT,
+        _ => EXIT_LIMIT,
+    });
+}
@@ -0,0 +1,211 @@
+extern crate knoxide;
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+
+use knoxide::mix_load;
+use knoxide::mix_load::Program;
+use knoxide::mix_spec;
+use knoxide::mix_spec::{Case, CaseResult};
+use knoxide::mix_types::{RunStats, StopReason};
+
+// Exit statuses: every case passed, the tests couldn't be run, or some
+// case failed.
+const EXIT_PASSED: i32 = 0;
+const EXIT_ERROR: i32 = 1;
+const EXIT_FAILED: i32 = 2;
+
+fn usage_error(msg: &str) -> ! {
+    eprintln!("mixtest: {}", msg);
+    std::process::exit(EXIT_ERROR);
+}
+
+fn read_file(path: &str) -> Vec<u8> {
+    match std::fs::read(path) {
+        Ok(b) => b,
+        Err(e) => usage_error(&format!("Failed to read {}: {}", path, e)),
+    }
+}
+
+fn end_name(case: &Case, result: &CaseResult) -> &'static str {
+    match result.end {
+        StopReason::Halted => "halted",
+        StopReason::Fault(_) => "fault",
+        StopReason::WaitingForInput(_) => "input",
+        StopReason::LimitReached if case.limits.out_of_time(&result.stats) => "timeout",
+        _ => "limit",
+    }
+}
+
+// Run a case, so that a panic fails just that case, with what it said.
+fn run_case(case: &Case, prog: &Program, files: &HashMap<String, Vec<u8>>)
+    -> CaseResult {
+    let run = std::panic::catch_unwind(|| mix_spec::run_case(case, prog, files));
+    run.unwrap_or_else(|e| {
+        let msg = match (e.downcast_ref::<&str>(), e.downcast_ref::<String>()) {
+            (Some(s), _) => s.to_string(),
+            (None, Some(s)) => s.clone(),
+            (None, None) => "no message".to_string(),
+        };
+        CaseResult {
+            end: StopReason::Fault(msg.clone()),
+            stats: RunStats::default(),
+            failures: vec![format!("The simulator panicked: {}", msg)],
+        }
+    })
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let mut specfile: Option<&String> = None;
+    let mut program: Option<&String> = None;
+    let mut jobs = std::thread::available_parallelism().map_or(1, |n| n.get());
+
+    // Poor man's getopt
+    let mut args_iter = args[1..].iter();
+    while let Some(arg) = args_iter.next() {
+        if arg == "-h" || arg == "--help" {
+            println!("Usage: mixtest [-p program] [-j jobs] spec");
+            println!("Run the cases of a spec file, each on a fresh machine, and \
+                      report which pass.");
+            println!("A spec is a line per setting; those before the first case \
+                      apply to all:");
+            println!("  program <file>, input <unit> <file>, output <unit> <file> \
+                      (the medium");
+            println!("  expected), check <expr> (as mixdb prints), limit \
+                      <instructions>, time <units>,");
+            println!("  case <name>. Files are named relative to the spec.");
+            println!("-p: run this program in every case, instead of the one the \
+                      spec names");
+            println!("-j: run this many cases at once (default the number of \
+                      processors)");
+            println!("Exit status: {} if every case passes, {} if any fails, {} \
+                      if they can't be run.", EXIT_PASSED, EXIT_FAILED, EXIT_ERROR);
+            return;
+        }
+        else if arg == "-p" || arg == "--program" {
+            program = args_iter.next();
+            if program.is_none() {
+                usage_error(&format!("{} needs a file name!", arg));
+            }
+        }
+        else if arg == "-j" || arg == "--jobs" {
+            jobs = match args_iter.next().map(|n| n.parse::<usize>()) {
+                Some(Ok(n)) if n > 0 => n,
+                _ => usage_error(&format!("{} needs a number!", arg)),
+            };
+        }
+        else if arg.starts_with('-') {
+            usage_error(&format!("Unknown option '{}'!", arg));
+        }
+        else if specfile.is_none() {
+            specfile = Some(arg);
+        }
+        else {
+            usage_error("Too many arguments!");
+        }
+    }
+    let specfile = match specfile {
+        Some(f) => f,
+        None => usage_error("No spec file given!"),
+    };
+
+    let text = String::from_utf8_lossy(&read_file(specfile)).into_owned();
+    let mut cases = match mix_spec::parse_spec(&text) {
+        Ok(c) => c,
+        Err(e) => usage_error(&format!("{}: {}", specfile, e)),
+    };
+
+    // Files are named relative to the spec, except a program given by -p.
+    // Read them all, and load the programs, before running anything.
+    let dir = Path::new(specfile).parent().unwrap_or_else(|| Path::new(""));
+    let resolve = |f: &String| dir.join(f).to_string_lossy().into_owned();
+    let mut programs: HashMap<String, Program> = HashMap::new();
+    let mut files: HashMap<String, Vec<u8>> = HashMap::new();
+    for case in cases.iter_mut() {
+        let path = match (program, &case.program) {
+            (Some(p), _) => p.clone(),
+            (None, Some(p)) => resolve(p),
+            (None, None) => usage_error(&format!(
+                "Case '{}' on line {} has no program!", case.name, case.line)),
+        };
+        if !programs.contains_key(&path) {
+            let prog = match mix_load::read_program(&path, &read_file(&path)) {
+                Ok(p) => p,
+                Err(e) => usage_error(&format!("{}: {}", path, e)),
+            };
+            programs.insert(path.clone(), prog);
+        }
+        case.program = Some(path);
+        for (_, f) in case.inputs.iter_mut().chain(case.outputs.iter_mut()) {
+            *f = resolve(f);
+            if !files.contains_key(f) {
+                files.insert(f.clone(), read_file(f));
+            }
+        }
+    }
+
+    // Each worker takes the next case to run until there are none left.
+    let cases: Arc<Vec<Case>> = Arc::new(cases);
+    let programs = Arc::new(programs);
+    let files = Arc::new(files);
+    let next = Arc::new(AtomicUsize::new(0));
+    let (tx, rx) = mpsc::channel();
+    let mut workers = vec![];
+    for _ in 0 .. std::cmp::min(jobs, cases.len()) {
+        let (cases, programs, files, next, tx) =
+            (cases.clone(), programs.clone(), files.clone(), next.clone(), tx.clone());
+        workers.push(std::thread::spawn(move || loop {
+            let ix = next.fetch_add(1, Ordering::SeqCst);
+            let case = match cases.get(ix) {
+                Some(c) => c,
+                None => break,
+            };
+            let prog = &programs[case.program.as_ref().unwrap()];
+            if tx.send((ix, run_case(case, prog, &files))).is_err() {
+                break;
+            }
+        }));
+    }
+    drop(tx);
+    let mut results: Vec<Option<CaseResult>> = vec![None; cases.len()];
+    for (ix, result) in rx {
+        results[ix] = Some(result);
+    }
+    for w in workers {
+        if w.join().is_err() {
+            usage_error("A case crashed the tester!");
+        }
+    }
+
+    // The table, then what went wrong in each case that failed.
+    let width = cases.iter().map(|c| c.name.len()).max().unwrap_or(0).max(4);
+    println!("{:w$}  RESULT  END      INSTRUCTIONS  TIME", "CASE", w = width);
+    let mut failed = 0;
+    for (case, result) in cases.iter().zip(&results) {
+        let result = result.as_ref().unwrap();
+        if !result.passed() {
+            failed += 1;
+        }
+        println!("{:w$}  {:6}  {:7}  {:12}  {}", case.name,
+                 if result.passed() { "pass" } else { "FAIL" },
+                 end_name(case, result), result.stats.instructions, result.stats.cycles,
+                 w = width);
+    }
+    for (case, result) in cases.iter().zip(&results) {
+        let result = result.as_ref().unwrap();
+        if !result.passed() {
+            println!();
+            println!("{} (line {}):", case.name, case.line);
+            for f in &result.failures {
+                println!("{}", f);
+            }
+        }
+    }
+    println!();
+    println!("{} cases, {} passed, {} failed", cases.len(), cases.len() - failed,
+             failed);
+    std::process::exit(if failed > 0 { EXIT_FAILED } else { EXIT_PASSED });
+}
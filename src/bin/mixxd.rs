@@ -1,6 +1,11 @@
 extern crate knoxide;
 
+use knoxide::arch_util;
 use knoxide::mix_fmt;
+use knoxide::mix_image;
+use knoxide::mix_mdk;
+use knoxide::mix_parse;
+use knoxide::mix_types::*;
 use std::io::prelude::*;
 
 fn main() {
@@ -8,6 +13,15 @@ fn main() {
     let mut flag_r = false;
     let mut flag_h = false;
     let mut flag_d = false;
+    let mut flag_i = false;
+    let mut flag_a = false;
+    let mut flag_addr = false;
+    let mut flag_p = false;
+    let mut flag_m = false;
+    let mut flag_k = false;
+    let mut flag_s = false;
+    let mut columns: Vec<mix_fmt::PrettyColumn> = mix_fmt::PRETTY_COLUMNS.to_vec();
+    let mut width: usize = 80;
     let mut infile: Option<&String> = None;
     let mut outfile: Option<&String> = None;
 
@@ -22,6 +36,48 @@ fn main() {
         else if arg == "-d" || arg == "--debug" {
             flag_d = true;
         }
+        else if arg == "-i" || arg == "--instructions" {
+            flag_i = true;
+        }
+        else if arg == "-s" || arg == "--source" {
+            flag_s = true;
+        }
+        else if arg == "-m" || arg == "--image" {
+            flag_m = true;
+        }
+        else if arg == "-k" || arg == "--mdk" {
+            flag_k = true;
+        }
+        else if arg == "-p" || arg == "--pretty" {
+            flag_p = true;
+        }
+        else if let Some(list) = arg.strip_prefix("--columns=") {
+            columns.clear();
+            for name in list.split(',') {
+                match mix_fmt::pretty_column(name) {
+                    Some(c) => columns.push(c),
+                    None => {
+                        eprintln!("mixxd: Unknown column '{}'!", name);
+                        std::process::exit(1);
+                    },
+                }
+            }
+        }
+        else if let Some(n) = arg.strip_prefix("--width=") {
+            width = match n.parse() {
+                Ok(w) => w,
+                Err(_) => {
+                    eprintln!("mixxd: Bad width '{}'!", arg);
+                    std::process::exit(1);
+                },
+            };
+        }
+        else if arg == "-A" || arg == "--addresses" {
+            flag_addr = true;
+        }
+        else if arg == "-a" || arg == "--all-errors" {
+            flag_a = true;
+        }
         else if infile == None {
             infile = Some(arg);
         } else if outfile == None {
@@ -33,8 +89,17 @@ fn main() {
     }
 
     if flag_h {
-        println!("Usage: mixxd [-r] [-v] [infile] [outfile]");
+        println!("Usage: mixxd [-r] [-a] [-m] [-k] [-A] [-i] [-s] [-p] [--columns=LIST] [--width=N] [-v] [infile] [outfile]");
         println!("-r: reverse operation (convert dump to binary)");
+        println!("-a: with -r, report every parsing error, not just the first");
+        println!("-m: with -r, write a portable image instead of raw words");
+        println!("-k: with -r, write a GNU MDK program (.mix) instead of raw words");
+        println!("-A: show the location of each word, collapsing runs of zeros");
+        println!("-i: show each word disassembled as an instruction");
+        println!("-s: write the words as MIXAL source, for this assembler or mixasm");
+        println!("-p: draw the words as a table, with extra columns for other views");
+        println!("--columns=LIST: the extra columns of -p, from int,text,instr,fields");
+        println!("--width=N: leave out extra columns past N characters (default 80)");
         println!("-v: verbose operation");
         std::process::exit(2);
     }
@@ -52,7 +117,65 @@ fn main() {
     }
 
     if flag_r {
-        panic!("Not yet implemented");
+        let mut in_words = String::new();
+        match infile {
+            Some(s) => {
+                let mut f = std::fs::File::open(s).expect(
+                    "mixxd: Failed to open file!");
+                if let Err(e) = f.read_to_string(&mut in_words) {
+                    eprintln!("mixxd: read failed! {}", e);
+                    std::process::exit(1);
+                }
+            },
+            None => {
+                if let Err(e) = std::io::stdin().read_to_string(&mut in_words) {
+                    eprintln!("mixxd: read failed! {}", e);
+                    std::process::exit(1);
+                }
+            },
+        };
+
+        let result = if flag_a {
+            mix_parse::parse_words_all(&in_words)
+        } else {
+            mix_parse::parse_words(&in_words).map_err(|e| vec![e])
+        };
+        let out_bytes = match result {
+            Ok(b) if flag_m || flag_k => {
+                let memory = b.chunks_exact(4).map(arch_util::u8sto32).collect();
+                let image = mix_image::MixImage {
+                    byte_size: 64, memory, start: 0, registers: None,
+                };
+                if flag_k {
+                    mix_mdk::write_mdk(&image)
+                } else {
+                    mix_image::write_image(&image)
+                }
+            },
+            Ok(b) => b,
+            Err(errs) => {
+                let name = infile.map_or("<stdin>", |s| s);
+                for e in &errs {
+                    eprint!("{}", fmt_error(name, &in_words, e));
+                }
+                std::process::exit(1);
+            },
+        };
+
+        match outfile {
+            Some(s) => {
+                let mut f = std::fs::File::create(s).expect(
+                    "mixxd: Failed to open file!");
+                if let Err(e) = f.write_all(&out_bytes) {
+                    eprintln!("mixxd: write failed! {}", e);
+                }
+            },
+            None => {
+                if let Err(e) = std::io::stdout().write_all(&out_bytes) {
+                    eprintln!("mixxd: write failed! {}", e);
+                }
+            },
+        };
     } else {
         let mut in_bytes: Vec<u8> = vec![];
         match infile {
@@ -71,7 +194,44 @@ fn main() {
             },
         };
 
-        let dump = mix_fmt::fmt_words(&in_bytes);
+        // Show a portable image or an MDK program as its memory words.
+        let mut start: MixAddr = 0;
+        let image = if mix_image::is_image(&in_bytes) {
+            Some(mix_image::read_image(&in_bytes).map_err(|e| e.to_string()))
+        } else if mix_mdk::is_mdk(&in_bytes) {
+            Some(mix_mdk::read_mdk(&in_bytes).map_err(|e| e.to_string()))
+        } else {
+            None
+        };
+        if let Some(image) = image {
+            match image {
+                Ok(image) => {
+                    start = image.start;
+                    in_bytes = image.memory.iter()
+                        .flat_map(|w| arch_util::u32to8s(*w).to_vec()).collect();
+                },
+                Err(e) => {
+                    eprintln!("mixxd: {}: {}", infile.map_or("<stdin>", |s| s), e);
+                    std::process::exit(1);
+                },
+            }
+        }
+
+        let dump = if flag_s {
+            let words: Vec<MixWord> = in_bytes.chunks_exact(4)
+                .map(arch_util::u8sto32).collect();
+            mix_fmt::fmt_mixal(&words, start)
+        } else if flag_p {
+            mix_fmt::fmt_words_pretty(&in_bytes, &columns, width)
+        } else if flag_addr {
+            let words: Vec<MixWord> = in_bytes.chunks_exact(4)
+                .map(arch_util::u8sto32).collect();
+            mix_fmt::fmt_memory(&words, flag_i)
+        } else if flag_i {
+            mix_fmt::fmt_words_disasm(&in_bytes)
+        } else {
+            mix_fmt::fmt_words(&in_bytes)
+        };
 
         match outfile {
             Some(s) => {
@@ -90,3 +250,19 @@ fn main() {
         };
     }
 }
+
+// Format a parsing error, followed by the offending line with the token
+// marked by carets underneath it.
+fn fmt_error(name: &str, text: &str, e: &mix_parse::ParseError) -> String {
+    let mut s = format!("mixxd: {}: {}\n", name, e);
+    let line = match text.lines().nth(e.line.wrapping_sub(1)) {
+        Some(l) => l,
+        None => return s,
+    };
+    s.push_str(&format!("    {}\n", line));
+    if e.column != 0 {
+        s.push_str(&format!("    {}{}\n", " ".repeat(e.column - 1),
+                            "^".repeat(e.token.chars().count().max(1))));
+    }
+    s
+}
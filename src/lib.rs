@@ -4,3 +4,18 @@ pub mod mix_util;
 pub mod mix_fmt;
 pub mod mix_parse;
 pub mod mix_core;
+pub mod mix_opcodes;
+pub mod mixal_parse;
+pub mod mixal_asm;
+pub mod mixal_listing;
+pub mod mix_deck;
+pub mod mix_image;
+pub mod mix_mdk;
+pub mod mixdb_command;
+pub mod mix_expr;
+pub mod mix_load;
+pub mod mix_io;
+pub mod mix_snapshot;
+pub mod mix_trace;
+pub mod mix_batch;
+pub mod mix_spec;
@@ -0,0 +1,36 @@
+// Running a program to the end without the debugger, as mixrun and mixtest
+// do: until it halts, faults or waits for input that won't come, or reaches
+// a limit on the instructions it may execute or the time it may take.
+
+use crate::mix_core;
+use crate::mix_types::*;
+
+// The instructions a program may execute unless told otherwise, so that
+// one stuck in a loop stops.
+pub const DEFAULT_INSTRUCTION_LIMIT: u64 = 10_000_000;
+
+// Limits on a run. Zero means no limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    pub instructions: u64,
+    pub time: u64,
+}
+
+impl Limits {
+    // Whether a run which stopped on reaching a limit reached the one on
+    // time, rather than the one on instructions.
+    pub fn out_of_time(&self, stats: &RunStats) -> bool {
+        self.time > 0 && stats.cycles >= self.time
+    }
+}
+
+// Run a machine from its program counter until the program ends, with the
+// limits counted from the start of the run.
+pub fn run_batch(st: &mut MixState, limits: Limits) -> RunOutcome {
+    let limit = |n| if n > 0 { Some(n) } else { None };
+    mix_core::do_mix_run(st, &StopCondition {
+        instructions: limit(limits.instructions),
+        time: limit(limits.time),
+        ..Default::default()
+    })
+}
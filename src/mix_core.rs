@@ -1,5 +1,11 @@
 use crate::mix_types::*;
 use crate::mix_util;
+use crate::mix_opcodes;
+use crate::mix_expr;
+use crate::mix_io;
+use crate::mix_io::DeviceKind;
+use crate::mix_trace;
+use std::io::Write;
 
 // Helper function to panic the system.
 // Stops running and puts the given message into the panic field.
@@ -9,45 +15,137 @@ fn do_panic(st: &mut MixState, msg: String) -> () {
 }
 
 // Helper function to pull out address and apply index registers.
-// Panic if the address field overflows when adding the index.
-fn effective_address(instr: MixWord, r: &MixRegisters) -> MixAddr {
+// Fail if the address field overflows when adding the index.
+fn effective_address(instr: MixWord, r: &MixRegisters) -> Result<MixAddr, String> {
     let base_addr = mix_util::get_bytes(instr, 0, 2) as u16;
     let i = mix_util::get_bytes(instr, 3, 3) as usize;
     if i == 0 {
-        return base_addr;
+        return Ok(base_addr);
     }
     else {
         let new_addr = mix_util::from_mix_addr(base_addr) +
             mix_util::from_mix_addr(r.i[i-1]);
         // Check address overflow
         if new_addr > MIX_ADDR_MAX || new_addr < MIX_ADDR_MIN {
-            panic!(format!("Address overflow! {}", new_addr));
+            return Err(format!("Address overflow! {}", new_addr));
         }
-        return mix_util::to_mix_addr(new_addr);
+        return Ok(mix_util::to_mix_addr(new_addr));
     }
 }
 
-// Look up the value at an address in memory.
-// Include fields according to the field specification.
-// Panic if the address doesn't correspond to a real memory address.
-fn memory_get(m: MixAddr, f: MixByte, st: &mut MixState) -> MixWord {
+// Check that an address corresponds to a real memory address, and return
+// its index. Panic the machine if it doesn't.
+fn memory_index(m: MixAddr, st: &mut MixState) -> Option<usize> {
     let m_val = mix_util::from_mix_addr(m);
-    if m_val > MIX_MEMORY_ADDR_MAX || m_val < MIX_MEMORY_ADDR_MIN {
+    if !(MIX_MEMORY_ADDR_MIN ..= MIX_MEMORY_ADDR_MAX).contains(&m_val) {
         do_panic(st, format!("Out of bounds memory access! {}", m_val));
+        return None;
+    }
+    Some(m_val as usize)
+}
+
+// Check that a field specification (L:R) names bytes of a word, with
+// L <= R <= 5. Panic the machine if it doesn't.
+fn check_field(f: MixByte, st: &mut MixState) -> bool {
+    if f / 8 > f % 8 || f % 8 > 5 {
+        do_panic(st, format!("Bad field specification ({}:{})", f / 8, f % 8));
+        return false;
+    }
+    true
+}
+
+// Whether the fields (L:R) given by two field specifications share a byte.
+fn fields_overlap(f1: MixByte, f2: MixByte) -> bool {
+    f1 / 8 <= f2 % 8 && f2 / 8 <= f1 % 8
+}
+
+// Record a hit on the first enabled watchpoint covering an access.
+fn check_watchpoints(st: &mut MixState, ix: usize, f: MixByte, write: bool,
+                     old: MixWord, new: MixWord) {
+    let pc = st.pc;
+    for w in st.watchpoints.iter_mut() {
+        let kind_matches = match w.kind {
+            WatchKind::Write => write,
+            WatchKind::Read => !write,
+            WatchKind::Access => true,
+        };
+        if w.enabled && kind_matches && w.addr as usize == ix &&
+            fields_overlap(w.field, f) {
+            w.hits += 1;
+            if st.watch_hit.is_none() {
+                st.watch_hit = Some(WatchHit {
+                    id: w.id, addr: w.addr, write, old, new, pc,
+                });
+            }
+        }
+    }
+}
+
+// Look up the value at an address in memory.
+// Include fields according to the field specification, shifted right as
+// the load and arithmetic operators see them.
+// Panic if the address doesn't correspond to a real memory address, or the
+// field isn't a field of a word.
+// Every read of memory by an instruction goes through here.
+fn memory_get(m: MixAddr, f: MixByte, st: &mut MixState) -> MixWord {
+    if !check_field(f, st) {
+        return 0;
     }
-    let m_ix = m_val as usize;
-    return mix_util::get_field_word(st.memory[m_ix], f);
+    let ix = match memory_index(m, st) {
+        Some(ix) => ix,
+        None => return 0,
+    };
+    let w = st.memory[ix];
+    check_watchpoints(st, ix, f, false, w, w);
+    mix_util::field_value(w, f)
 }
 
-fn do_mix_instruction(instr: MixWord, st: &mut MixState) -> () {
+// Store a value into the given field of a word in memory, the way the store
+// operators do.
+// Panic if the address doesn't correspond to a real memory address, or the
+// field isn't a field of a word.
+// Every write of memory by an instruction goes through here.
+fn memory_set(m: MixAddr, f: MixByte, value: MixWord, st: &mut MixState) {
+    if !check_field(f, st) {
+        return;
+    }
+    let ix = match memory_index(m, st) {
+        Some(ix) => ix,
+        None => return,
+    };
+    let old = st.memory[ix];
+    let new = mix_util::store_field(old, value, f);
+    st.memory[ix] = new;
+    if let Some(writes) = &mut st.step_writes {
+        writes.push(UndoWrite { addr: ix as MixAddr, field: f, old, new });
+    }
+    check_watchpoints(st, ix, f, true, old, new);
+}
+
+// Convert an address register to a word, with the same sign.
+fn addr_to_word(a: MixAddr) -> MixWord {
+    (((a as MixWord) >> 12 & 1) << 30) | (a as MixWord & 0o7777)
+}
+
+// Execute an instruction. Return the location to jump to, if it jumps.
+fn do_mix_instruction(instr: MixWord, st: &mut MixState) -> Option<MixAddr> {
     let f = mix_util::get_bytes(instr, 4, 4) as u8;
     let c = mix_util::get_bytes(instr, 5, 5) as u8;
-    let m = effective_address(instr, &st.r);
+    let m = match effective_address(instr, &st.r) {
+        Ok(m) => m,
+        Err(msg) => {
+            do_panic(st, msg);
+            return None;
+        },
+    };
+    if let 34 | 38 | 39 ..= 47 = c {
+        return do_jump(c, f, m, st);
+    }
     match c {
         // NOP
         0 => {},
         // Arithmetic operators
-        1...4 => do_arithmetic(c, f, m, st),
+        1 ..= 4 => do_arithmetic(c, f, m, st),
         // Special operators
         5 => do_special(c, f, m, st),
         // Shift operators
@@ -55,24 +153,43 @@ fn do_mix_instruction(instr: MixWord, st: &mut MixState) -> () {
         // MOVE
         7 => do_move(c, f, m, st),
         // Load operators
-        8...23 => do_load(c, f, m, st),
+        8 ..= 23 => do_load(c, f, m, st),
         // Store operators
-        24...33 => do_store(c, f, m, st),
+        24 ..= 33 => do_store(c, f, m, st),
         // I/O operators
-        34...38 => do_io(c, f, m, st),
-        // Jump operators
-        39...47 => do_jump(c, f, m, st),
+        35 ..= 37 => do_io(c, f, m, st),
         // Address transfer operators
-        48...55 => do_address_transfer(c, f, m, st),
+        48 ..= 55 => do_address_transfer(c, f, m, st),
         // Comparison operators
-        56...63 => do_compare(c, f, m, st),
+        56 ..= 63 => do_compare(c, f, m, st),
         _ => do_panic(st, format!("Bad operator {}", c)),
     }
+    None
+}
+
+// The floating point operators, F = 6 on ADD, SUB, MUL, DIV and CMPA, which
+// this machine doesn't have.
+fn float_unsupported(c: MixByte, st: &mut MixState) {
+    let name = match c {
+        1 => "FADD",
+        2 => "FSUB",
+        3 => "FMUL",
+        4 => "FDIV",
+        _ => "FCMP",
+    };
+    do_panic(st, format!("{} is not implemented", name));
 }
 
 fn do_arithmetic(c: MixByte, f: MixByte, m: MixAddr, st: &mut MixState)
     -> () {
+    if f == 6 {
+        float_unsupported(c, st);
+        return;
+    }
     let v = memory_get(m, f, st);
+    if st.panic_msg.is_some() {
+        return;
+    }
     match c {
         // ADD
         1 => {
@@ -85,8 +202,8 @@ fn do_arithmetic(c: MixByte, f: MixByte, m: MixAddr, st: &mut MixState)
         },
         // SUB
         2 => {
-            let new_val = mix_util::from_mix_word(v) -
-                mix_util::from_mix_word(st.r.a);
+            let new_val = mix_util::from_mix_word(st.r.a) -
+                mix_util::from_mix_word(v);
             if new_val < MIX_WORD_MIN || new_val > MIX_WORD_MAX {
                 st.overflow = true;
             }
@@ -117,107 +234,1152 @@ fn do_arithmetic(c: MixByte, f: MixByte, m: MixAddr, st: &mut MixState)
                 st.r.x = old_a_sign | mix_util::to_mix_word(new_rem as i32);
             }
         },
-        _ => panic!(format!("Bad opcode for arithmetic at {}", c)),
+        _ => panic!("Bad opcode for arithmetic at {}", c),
     }
 }
 
-fn do_special(_c: MixByte, _f: MixByte, _m: MixAddr, _st: &mut MixState) -> () {
-    return; // TODO
-    /*
+fn do_special(_c: MixByte, f: MixByte, _m: MixAddr, st: &mut MixState) {
     match f {
-        // NUM
-        0 => do_num(st),
-        // CHAR
-        1 => do_char(st),
+        // NUM: the number whose digits are the bytes of rAX, each taken
+        // modulo 10, into rA. The remainder modulo b^5 is kept if it's too
+        // large.
+        0 => {
+            let mut v: u64 = 0;
+            for w in &[st.r.a, st.r.x] {
+                for b in 1 ..= 5 {
+                    v = v * 10 + (mix_util::get_bytes(*w, b, b) % 10) as u64;
+                }
+            }
+            let sign = st.r.a & (1 << 30);
+            st.r.a = sign | (v % (MIX_WORD_MAX as u64 + 1)) as MixWord;
+        },
+        // CHAR: the ten decimal digits of rA, as characters, into rAX.
+        1 => {
+            let mut v = mix_util::from_mix_word(st.r.a).unsigned_abs() as u64;
+            let mut codes = [0; 10];
+            for code in codes.iter_mut().rev() {
+                *code = 30 + (v % 10) as u8;
+                v /= 10;
+            }
+            let sign_a = mix_util::get_bytes(st.r.a, 0, 0) as u8;
+            let sign_x = mix_util::get_bytes(st.r.x, 0, 0) as u8;
+            st.r.a = mix_util::word_from_bytes(sign_a, codes[0], codes[1], codes[2],
+                                               codes[3], codes[4]);
+            st.r.x = mix_util::word_from_bytes(sign_x, codes[5], codes[6], codes[7],
+                                               codes[8], codes[9]);
+        },
         // HLT
-        2 => do_hlt(st),
-        _ => panic!(format!("Bad field specification {}", f)),
+        2 => st.is_running = false,
+        _ => do_panic(st, format!("Bad field specification {}", f)),
     }
-    */
 }
 
-fn do_shift(_c: MixByte, _f: MixByte, _m: MixAddr, _st: &mut MixState) -> () {
-    return; // TODO
-    /*
-    match f {
+// Shift rA, or rA and rX as one register of ten bytes, by M bytes. The
+// signs stay where they are. SLC and SRC shift around in a circle; the
+// others shift in zeros.
+fn do_shift(_c: MixByte, f: MixByte, m: MixAddr, st: &mut MixState) {
+    let n = mix_util::from_mix_addr(m);
+    if n < 0 {
+        do_panic(st, format!("Negative shift {}", n));
+        return;
+    }
+    const A_MASK: u64 = (1 << 30) - 1;
+    const AX_MASK: u64 = (1 << 60) - 1;
+    let a = mix_util::get_bytes(st.r.a, 1, 5) as u64;
+    let ax = (a << 30) | mix_util::get_bytes(st.r.x, 1, 5) as u64;
+    // Shifting in zeros by ten bytes or more clears everything.
+    let bits = 6 * (n as u64).min(10);
+    let circle = 6 * (n as u64 % 10);
+    let (a, ax) = match f {
         // SLA
-        0 => do_sla(st),
+        0 => ((a << bits) & A_MASK, None),
         // SRA
-        1 => do_sra(st),
+        1 => (a >> bits, None),
         // SLAX
-        2 => do_slax(st),
+        2 => (0, Some((ax << bits) & AX_MASK)),
         // SRAX
-        3 => do_srax(st),
+        3 => (0, Some(ax >> bits)),
         // SLC
-        4 => do_slc(st),
+        4 => (0, Some(((ax << circle) | (ax >> (60 - circle))) & AX_MASK)),
         // SRC
-        5 => do_src(st),
-        _ => panic!(format!("Bad field specification {}", f)),
+        5 => (0, Some(((ax >> circle) | (ax << (60 - circle))) & AX_MASK)),
+        _ => {
+            do_panic(st, format!("Bad field specification {}", f));
+            return;
+        },
+    };
+    let sign = 1 << 30;
+    match ax {
+        Some(ax) => {
+            st.r.a = (st.r.a & sign) | (ax >> 30) as MixWord;
+            st.r.x = (st.r.x & sign) | (ax & A_MASK) as MixWord;
+        },
+        None => st.r.a = (st.r.a & sign) | a as MixWord,
     }
-    */
 }
 
-fn do_move(_c: MixByte, _f: MixByte, _m: MixAddr, _st: &mut MixState) -> () {
-    // TODO
+// Move F words starting at M to the location in rI1, one at a time.
+// rI1 is advanced by F.
+fn do_move(_c: MixByte, f: MixByte, m: MixAddr, st: &mut MixState) {
+    let from = mix_util::from_mix_addr(m);
+    for k in 0 .. f as i16 {
+        let w = memory_get(mix_util::to_mix_addr(from + k), 5, st);
+        if st.panic_msg.is_some() {
+            return;
+        }
+        memory_set(st.r.i[0], 5, w, st);
+        if st.panic_msg.is_some() {
+            return;
+        }
+        let next = mix_util::from_mix_addr(st.r.i[0]) + 1;
+        st.r.i[0] = mix_util::to_mix_addr(next);
+    }
 }
 
-fn do_load(_c: MixByte, _f: MixByte, _m: MixAddr, _st: &mut MixState) -> () {
-    // TODO
+fn do_load(c: MixByte, f: MixByte, m: MixAddr, st: &mut MixState) {
+    let mut v = memory_get(m, f, st);
+    if st.panic_msg.is_some() {
+        return;
+    }
+    // LDAN to LDXN load the value with its sign flipped.
+    if c >= 16 {
+        v ^= 1 << 30;
+    }
+    match (c - 8) % 8 {
+        0 => st.r.a = v,
+        7 => st.r.x = v,
+        i => {
+            if mix_util::get_bytes(v, 1, 3) != 0 {
+                do_panic(st, format!("Value too large for I{}: {}", i,
+                                     mix_util::from_mix_word(v)));
+                return;
+            }
+            let sign = mix_util::get_bytes(v, 0, 0) as MixAddr;
+            st.r.i[i as usize - 1] =
+                (sign << 12) | mix_util::get_bytes(v, 4, 5) as MixAddr;
+        },
+    }
 }
 
-fn do_store(_c: MixByte, _f: MixByte, _m: MixAddr, _st: &mut MixState) -> () {
-    // TODO
+fn do_store(c: MixByte, f: MixByte, m: MixAddr, st: &mut MixState) {
+    let v = match c {
+        24 => st.r.a,
+        25 ..= 30 => addr_to_word(st.r.i[c as usize - 25]),
+        31 => st.r.x,
+        // rJ always behaves as though its sign is +. Saving it confirms
+        // the call which set it.
+        32 => {
+            if let Some(frame) = st.calls.last_mut() {
+                frame.saved = true;
+            }
+            addr_to_word(st.r.j) & !(1 << 30)
+        },
+        // STZ
+        _ => 0,
+    };
+    memory_set(m, f, v, st);
 }
 
-fn do_io(_c: MixByte, _f: MixByte, _m: MixAddr, _st: &mut MixState) -> () {
-    // TODO
-    /*
-    match c {
-        // JBUS
-        34 => do_jbus(f, st),
+// Find the device on a unit. Panic the machine if there is none.
+fn device_index(unit: MixByte, st: &mut MixState) -> Option<usize> {
+    if let Some(ix) = st.io.iter().position(|d| d.unit == unit) {
+        return Some(ix);
+    }
+    let msg = match DeviceKind::of_unit(unit) {
+        Some(kind) => format!("No {} on unit {}", kind.name(), unit),
+        None => format!("Bad unit {}", unit),
+    };
+    do_panic(st, msg);
+    None
+}
+
+// Check that a block of a device fits in memory from M, and return the
+// index it starts at. Panic the machine if it doesn't.
+fn block_index(m: MixAddr, size: usize, st: &mut MixState) -> Option<usize> {
+    let base = mix_util::from_mix_addr(m);
+    if base < 0 || base as usize + size > MEM_SIZE {
+        do_panic(st, format!("Out of bounds memory access! {} words at {}",
+                             size, base));
+        return None;
+    }
+    Some(base as usize)
+}
+
+// IOC, IN and OUT. JBUS and JRED are jumps.
+fn do_io(c: MixByte, f: MixByte, m: MixAddr, st: &mut MixState) {
+    let ix = match device_index(f, st) {
+        Some(ix) => ix,
+        None => return,
+    };
+    let kind = st.io[ix].kind;
+    let size = kind.block_size();
+    let mut undo = DeviceUndo {
+        unit: f,
+        position: st.io[ix].position,
+        len: st.io[ix].blocks.len(),
+        old: None,
+    };
+    let result = match c {
         // IOC
-        35 => do_ioc(f, st),
+        35 => {
+            let dev = &mut st.io[ix];
+            let m = mix_util::from_mix_addr(m) as i64;
+            match (kind, m) {
+                (DeviceKind::Tape, 0) | (DeviceKind::PaperTape, 0) => {
+                    dev.position = 0;
+                    Ok(())
+                },
+                (DeviceKind::Tape, _) => {
+                    let pos = dev.position as i64 + m;
+                    dev.position = pos.max(0).min(dev.blocks.len() as i64) as usize;
+                    Ok(())
+                },
+                (DeviceKind::Disk, _) => {
+                    dev.position = mix_io::next_block(dev, st.r.x);
+                    Ok(())
+                },
+                // A page eject.
+                (DeviceKind::Printer, 0) => {
+                    let n = dev.position;
+                    dev.position += 1;
+                    undo.old = mix_io::put_block(dev, n, vec![]).map(|b| (n, b));
+                    Ok(())
+                },
+                _ => Err(format!("IOC {} is not an operation of the {}",
+                                 m, kind.name())),
+            }
+        },
         // IN
-        36 => do_in(f, st),
+        36 => {
+            let n = mix_io::next_block(&st.io[ix], st.r.x);
+            let block = match st.io[ix].blocks.get(n) {
+                _ if !kind.can_input() => None,
+                Some(b) => Some(b.clone()),
+                // Parts of a disk not yet written hold zeros.
+                None if kind == DeviceKind::Disk => Some(vec![0; size]),
+                // More may come for a device read as the program runs, so
+                // wait for it rather than fail.
+                None if kind.is_text() => {
+                    st.waiting = Some(f);
+                    return;
+                },
+                None => None,
+            };
+            match (block, block_index(m, size, st)) {
+                (Some(block), Some(base)) => {
+                    st.io[ix].position = n + 1;
+                    for (k, w) in block.iter().enumerate() {
+                        memory_set((base + k) as MixAddr, 5, *w, st);
+                    }
+                    Ok(())
+                },
+                (_, None) => Ok(()),
+                (None, _) if kind.can_input() => Err(format!(
+                    "No more input on unit {} ({})", f, kind.name())),
+                (None, _) => Err(format!("The {} cannot read", kind.name())),
+            }
+        },
         // OUT
-        37 => do_out(f, st),
-        // JRED
-        38 => do_jred(f, st),
+        _ => {
+            if !kind.can_output() {
+                Err(format!("The {} cannot write", kind.name()))
+            } else if let Some(base) = block_index(m, size, st) {
+                let mut block = vec![];
+                for k in 0 .. size {
+                    block.push(memory_get((base + k) as MixAddr, 5, st));
+                }
+                let n = mix_io::next_block(&st.io[ix], st.r.x);
+                st.io[ix].position = n + 1;
+                undo.old = mix_io::put_block(&mut st.io[ix], n, block)
+                    .map(|b| (n, b));
+                Ok(())
+            } else {
+                Ok(())
+            }
+        },
+    };
+    if let Err(msg) = result {
+        do_panic(st, msg);
+    }
+    if st.step_writes.is_some() {
+        st.step_device = Some(undo);
     }
-    */
 }
 
-fn do_jump(_c: MixByte, _f: MixByte, _m: MixAddr, _st: &mut MixState) -> () {
-    // TODO
+// The value of the register operated on by the register jump, address
+// transfer and comparison operators with the given code: A, I1 to I6 or X.
+fn register_word(c: MixByte, st: &MixState) -> MixWord {
+    match c % 8 {
+        0 => st.r.a,
+        7 => st.r.x,
+        i => addr_to_word(st.r.i[i as usize - 1]),
+    }
 }
 
-fn do_address_transfer(_c: MixByte, _f: MixByte, _m: MixAddr, _st: &mut MixState)
-    -> () {
-    // TODO
+// Set a register as register_word finds it. Panic if the value doesn't fit
+// in an index register.
+fn set_register_word(c: MixByte, v: MixWord, st: &mut MixState) {
+    match c % 8 {
+        0 => st.r.a = v,
+        7 => st.r.x = v,
+        i => {
+            if mix_util::get_bytes(v, 1, 3) != 0 {
+                do_panic(st, format!("Value too large for I{}: {}", i,
+                                     mix_util::from_mix_word(v)));
+                return;
+            }
+            let sign = mix_util::get_bytes(v, 0, 0) as MixAddr;
+            st.r.i[i as usize - 1] =
+                (sign << 12) | mix_util::get_bytes(v, 4, 5) as MixAddr;
+        },
+    }
 }
 
-fn do_compare(_c: MixByte, _f: MixByte, _m: MixAddr, _st: &mut MixState) -> () {
-    // TODO
+fn do_jump(c: MixByte, f: MixByte, m: MixAddr, st: &mut MixState)
+    -> Option<MixAddr> {
+    let jump = if c == 34 || c == 38 {
+        // JBUS, JRED: devices are never busy.
+        device_index(f, st).is_some() && c == 38
+    } else if c == 39 {
+        match f {
+            // JMP, JSJ
+            0 | 1 => true,
+            // JOV
+            2 => std::mem::replace(&mut st.overflow, false),
+            // JNOV
+            3 => !std::mem::replace(&mut st.overflow, false),
+            // JL, JE, JG, JGE, JNE, JLE
+            4 => st.comparison == MixCompare::Less,
+            5 => st.comparison == MixCompare::Equal,
+            6 => st.comparison == MixCompare::Greater,
+            7 => st.comparison != MixCompare::Less,
+            8 => st.comparison != MixCompare::Equal,
+            9 => st.comparison != MixCompare::Greater,
+            _ => {
+                do_panic(st, format!("Bad field specification {}", f));
+                false
+            },
+        }
+    } else {
+        let v = mix_util::from_mix_word(register_word(c, st));
+        match f {
+            // JrN, JrZ, JrP, JrNN, JrNZ, JrNP
+            0 => v < 0,
+            1 => v == 0,
+            2 => v > 0,
+            3 => v >= 0,
+            4 => v != 0,
+            5 => v <= 0,
+            _ => {
+                do_panic(st, format!("Bad field specification {}", f));
+                false
+            },
+        }
+    };
+    if !jump {
+        return None;
+    }
+    let target = memory_index(m, st)? as MixAddr;
+
+    // Follow subroutine calls. A jump to the return address of a call
+    // returns from it, and from any calls it made. Every jump but JSJ sets
+    // rJ, losing the way back from an unconfirmed call, and a JMP which
+    // isn't a return may be a call.
+    let returned = match st.calls.iter().rposition(|frame| frame.ret == target) {
+        Some(ix) => {
+            st.calls.truncate(ix);
+            true
+        },
+        None => false,
+    };
+    if c == 39 && f == 1 {
+        return Some(target);
+    }
+    let ret = st.pc + 1;
+    st.r.j = ret;
+    if matches!(st.calls.last(), Some(frame) if !frame.saved) {
+        st.calls.pop();
+    }
+    if c == 39 && f == 0 && !returned {
+        st.calls.push(CallFrame { call: st.pc, entry: target, ret, saved: false });
+    }
+    Some(target)
+}
+
+fn do_address_transfer(c: MixByte, f: MixByte, m: MixAddr, st: &mut MixState) {
+    let old = register_word(c, st);
+    let new = match f {
+        // INCr, DECr
+        0 | 1 => {
+            let mut v = mix_util::from_mix_word(old) as i64;
+            let mut d = mix_util::from_mix_addr(m) as i64;
+            if f == 1 {
+                d = -d;
+            }
+            v += d;
+            if v.abs() > MIX_WORD_MAX as i64 {
+                st.overflow = true;
+                v %= MIX_WORD_MAX as i64 + 1;
+            }
+            // As with ADD, a zero result keeps the register's sign.
+            if v == 0 {
+                old & (1 << 30)
+            } else {
+                mix_util::to_mix_word(v as i32)
+            }
+        },
+        // ENTr, ENNr: M, keeping the sign of the instruction even when M
+        // is zero.
+        2 => addr_to_word(m),
+        3 => addr_to_word(m) ^ (1 << 30),
+        _ => {
+            do_panic(st, format!("Bad field specification {}", f));
+            return;
+        },
+    };
+    set_register_word(c, new, st);
+}
+
+fn do_compare(c: MixByte, f: MixByte, m: MixAddr, st: &mut MixState) {
+    if c == 56 && f == 6 {
+        float_unsupported(c, st);
+        return;
+    }
+    let v = mix_util::from_mix_word(memory_get(m, f, st));
+    if st.panic_msg.is_some() {
+        return;
+    }
+    let r = mix_util::from_mix_word(mix_util::field_value(register_word(c, st), f));
+    st.comparison = match r.cmp(&v) {
+        std::cmp::Ordering::Less => MixCompare::Less,
+        std::cmp::Ordering::Equal => MixCompare::Equal,
+        std::cmp::Ordering::Greater => MixCompare::Greater,
+    };
 }
 
 // Execute the single instruction under the program counter.
-// Increment the program counter on completion.
-// (TODO: handle jumps, etc)
+// Increment the program counter on completion, unless the instruction
+// jumps. An input instruction waiting for input isn't executed: it sets
+// waiting and leaves the machine as it was, to be tried again.
 pub fn do_mix_step(st: &mut MixState) -> () {
-    let instr = memory_get(st.pc, 5, st);
-    do_mix_instruction(instr, st);
-    st.pc = (st.pc + 1) % (MEM_SIZE as u16);
+    st.watch_hit = None;
+    st.waiting = None;
+    // Fetching the instruction isn't a data access, so it can't trigger a
+    // watchpoint. The program counter is always a valid address.
+    let instr = st.memory[st.pc as usize];
+    let c = mix_util::get_bytes(instr, 5, 5) as MixByte;
+    let f = mix_util::get_bytes(instr, 4, 4) as MixByte;
+    // The state before the instruction, for the history and the trace.
+    let before = if st.history.is_some() || st.tracer.is_some() {
+        st.step_writes = Some(vec![]);
+        // Only STJ and the jumps change the calls in progress.
+        let calls = if c == 32 || (39 ..= 47).contains(&c) {
+            Some(st.calls.clone())
+        } else {
+            None
+        };
+        Some(UndoEntry {
+            pc: st.pc,
+            r: Some(st.r.clone()),
+            overflow: st.overflow,
+            comparison: st.comparison.clone(),
+            writes: vec![],
+            cycles: st.cycles,
+            calls,
+            panicked: st.panic_msg.is_some(),
+            device: None,
+        })
+    } else {
+        None
+    };
+    let m = match st.tracer {
+        // An instruction failing on its address is traced with M as 0.
+        Some(_) => Some(effective_address(instr, &st.r).unwrap_or(0)),
+        None => None,
+    };
+    let jump = do_mix_instruction(instr, st);
+    if st.waiting.is_some() {
+        st.step_writes = None;
+        return;
+    }
+    st.cycles += mix_opcodes::op_time(c, f);
+    st.pc = match jump {
+        Some(target) => target,
+        None => (st.pc + 1) % (MEM_SIZE as u16),
+    };
+    st.instructions += 1;
+    if let Some(mut entry) = before {
+        entry.writes = st.step_writes.take().unwrap_or_default();
+        entry.device = st.step_device.take();
+        if let Some(m) = m {
+            mix_trace::trace_step(st, instr, m, &entry);
+        }
+        if st.history.is_some() {
+            record(st, entry);
+        }
+    }
+}
+
+// Press the GO button, as TAOCP 1.3.1 describes: read a card from the card
+// reader into locations 0000-0015 and jump to 0000. This boots the machine
+// from a deck such as mix_deck punches. Fail if there is no card to read.
+pub fn go_button(st: &mut MixState) -> Result<(), String> {
+    let reader = match mix_io::find_device(&mut st.io, 16) {
+        Some(d) => d,
+        None => return Err("No card reader on unit 16".to_string()),
+    };
+    let card = match reader.blocks.get(reader.position) {
+        Some(c) => c.clone(),
+        None => return Err("No cards in the card reader".to_string()),
+    };
+    reader.position += 1;
+    st.memory[.. card.len()].copy_from_slice(&card);
+    st.pc = 0;
+    Ok(())
+}
+
+// Limits on the history: the most undo entries kept, how many instructions
+// apart checkpoints are taken, and the most checkpoints kept. Checkpoints
+// come more often than the undo log runs out, so that going back from one
+// refills the log up to the next.
+const UNDO_LIMIT: usize = 100_000;
+const CHECKPOINT_INTERVAL: u64 = 10_000;
+const CHECKPOINT_LIMIT: usize = 64;
+
+fn checkpoint(st: &MixState) -> Checkpoint {
+    Checkpoint {
+        instructions: st.instructions,
+        memory: Box::new(*st.memory),
+        r: st.r.clone(),
+        overflow: st.overflow,
+        comparison: st.comparison.clone(),
+        pc: st.pc,
+        cycles: st.cycles,
+        calls: st.calls.clone(),
+        io: st.io.clone(),
+    }
+}
+
+// Finish the undo entry for the instruction just executed, which was
+// started with the state before it, and add it to the history.
+fn record(st: &mut MixState, mut entry: UndoEntry) {
+    if entry.r.as_ref() == Some(&st.r) {
+        entry.r = None;
+    }
+    if entry.calls.as_ref() == Some(&st.calls) {
+        entry.calls = None;
+    }
+    entry.cycles = st.cycles - entry.cycles;
+    entry.panicked = !entry.panicked && st.panic_msg.is_some();
+    let cp = if st.instructions.is_multiple_of(CHECKPOINT_INTERVAL) {
+        Some(checkpoint(st))
+    } else {
+        None
+    };
+    let h = match &mut st.history {
+        Some(h) => h,
+        None => return,
+    };
+    h.entries.push_back(entry);
+    if h.entries.len() > UNDO_LIMIT {
+        h.entries.pop_front();
+    }
+    if let Some(cp) = cp {
+        h.checkpoints.push(cp);
+        if h.checkpoints.len() > CHECKPOINT_LIMIT {
+            h.checkpoints.remove(0);
+        }
+    }
+}
+
+// Start recording the history of execution, from the current state.
+pub fn start_recording(st: &mut MixState) {
+    let cp = checkpoint(st);
+    st.history = Some(History {
+        entries: std::collections::VecDeque::new(),
+        checkpoints: vec![cp],
+    });
+}
+
+// Stop recording, dropping the history.
+pub fn stop_recording(st: &mut MixState) {
+    st.history = None;
+}
+
+// The earliest instruction count the history can go back to, if execution
+// is being recorded.
+pub fn earliest_instruction(st: &MixState) -> Option<u64> {
+    let h = st.history.as_ref()?;
+    let log_start = st.instructions - h.entries.len() as u64;
+    Some(h.checkpoints.first()
+         .map_or(log_start, |cp| std::cmp::min(cp.instructions, log_start)))
+}
+
+// Undo the last instruction executed, returning what it changed. Going
+// back past the start of the undo log restores the latest checkpoint
+// before it and runs forward again, refilling the log.
+pub fn step_back(st: &mut MixState) -> Result<UndoEntry, String> {
+    let h = match &st.history {
+        Some(h) => h,
+        None => return Err("Execution is not being recorded".to_string()),
+    };
+    if h.entries.is_empty() {
+        let cp = h.checkpoints.iter().rev()
+            .find(|cp| cp.instructions < st.instructions).cloned();
+        let cp = match cp {
+            Some(cp) => cp,
+            None => return Err("Reached the start of the history".to_string()),
+        };
+        let target = st.instructions;
+        *st.memory = *cp.memory;
+        st.r = cp.r;
+        st.overflow = cp.overflow;
+        st.comparison = cp.comparison;
+        st.pc = cp.pc;
+        st.cycles = cp.cycles;
+        st.calls = cp.calls;
+        st.io = cp.io;
+        st.instructions = cp.instructions;
+        st.panic_msg = None;
+        // Instructions run again aren't traced again.
+        let tracer = st.tracer.take();
+        while st.instructions < target {
+            do_mix_step(st);
+        }
+        st.tracer = tracer;
+    }
+
+    let h = match &mut st.history {
+        Some(h) => h,
+        None => return Err("Execution is not being recorded".to_string()),
+    };
+    let entry = match h.entries.pop_back() {
+        Some(e) => e,
+        None => return Err("Reached the start of the history".to_string()),
+    };
+    for w in entry.writes.iter().rev() {
+        st.memory[w.addr as usize] = w.old;
+    }
+    if let Some(d) = &entry.device {
+        if let Some(dev) = mix_io::find_device(&mut st.io, d.unit) {
+            if let Some((n, old)) = &d.old {
+                dev.blocks[*n] = old.clone();
+            }
+            dev.blocks.truncate(d.len);
+            dev.position = d.position;
+        }
+    }
+    if let Some(r) = &entry.r {
+        st.r = r.clone();
+    }
+    st.overflow = entry.overflow;
+    st.comparison = entry.comparison.clone();
+    if let Some(calls) = &entry.calls {
+        st.calls = calls.clone();
+    }
+    st.cycles -= entry.cycles;
+    st.pc = entry.pc;
+    if entry.panicked {
+        st.panic_msg = None;
+    }
+    st.instructions -= 1;
+    // Checkpoints after this point are taken again if execution gets there.
+    let n = st.instructions;
+    h.checkpoints.retain(|cp| cp.instructions <= n);
+    Ok(entry)
 }
 
-// Continue execution until the program halts or an error occurs.
-// Stop as soon as that happens.
-pub fn do_mix_run(st: &mut MixState) -> () {
+// Go to the state after a number of instructions: back through the history,
+// or forward by executing instructions, passing over breakpoints. Going
+// forward fails, where it stops, on a fault, a HLT or a wait for input.
+pub fn goto_instruction(st: &mut MixState, n: u64) -> Result<(), String> {
+    while st.instructions > n {
+        step_back(st)?;
+    }
+    while st.instructions < n {
+        if let Some(msg) = &st.panic_msg {
+            return Err(format!("Panicked at instruction {}: {}",
+                               st.instructions, msg));
+        }
+        st.is_running = true;
+        do_mix_step(st);
+        let halted = !st.is_running;
+        st.is_running = false;
+        if let Some(unit) = st.waiting {
+            return Err(format!("Waiting for input on unit {} at instruction {}",
+                               unit, st.instructions));
+        }
+        if halted && st.instructions < n {
+            return Err(format!("Halted at instruction {}", st.instructions));
+        }
+    }
+    Ok(())
+}
+
+// The number for a new breakpoint or watchpoint.
+fn next_id(st: &MixState) -> usize {
+    let b = st.breakpoints.iter().map(|b| b.id).max().unwrap_or(0);
+    let w = st.watchpoints.iter().map(|w| w.id).max().unwrap_or(0);
+    std::cmp::max(b, w) + 1
+}
+
+// Set a breakpoint at an address and return its number.
+pub fn add_breakpoint(st: &mut MixState, addr: MixAddr) -> usize {
+    let id = next_id(st);
+    st.breakpoints.push(Breakpoint {
+        id, addr, enabled: true, hits: 0, ignore: 0, condition: None,
+    });
+    id
+}
+
+// Set a watchpoint on a field of a word and return its number.
+pub fn add_watchpoint(st: &mut MixState, addr: MixAddr, field: MixByte,
+                      kind: WatchKind) -> usize {
+    let id = next_id(st);
+    st.watchpoints.push(Watchpoint {
+        id, addr, field, kind, enabled: true, hits: 0,
+    });
+    id
+}
+
+// Find a watchpoint by its number.
+pub fn find_watchpoint<'b>(st: &'b mut MixState, id: usize)
+    -> Option<&'b mut Watchpoint> {
+    st.watchpoints.iter_mut().find(|w| w.id == id)
+}
+
+// Find a breakpoint by its number.
+pub fn find_breakpoint<'b>(st: &'b mut MixState, id: usize)
+    -> Option<&'b mut Breakpoint> {
+    st.breakpoints.iter_mut().find(|b| b.id == id)
+}
+
+// Count a hit on every enabled breakpoint at the program counter whose
+// condition holds. Return the number of the first one which stops
+// execution, once its ignore count has run out. A condition which can't be
+// evaluated, say because it divides by zero, counts as holding.
+fn check_breakpoints(st: &mut MixState) -> Option<usize> {
+    let hit: Vec<usize> = st.breakpoints.iter()
+        .filter(|b| b.enabled && b.addr == st.pc)
+        .filter(|b| match &b.condition {
+            Some(c) => mix_expr::eval(c, st) != Ok(0),
+            None => true,
+        })
+        .map(|b| b.id).collect();
+    let mut stop = None;
+    for b in st.breakpoints.iter_mut().filter(|b| hit.contains(&b.id)) {
+        b.hits += 1;
+        if b.ignore > 0 {
+            b.ignore -= 1;
+        } else if stop.is_none() {
+            stop = Some(b.id);
+        }
+    }
+    stop
+}
+
+// Run until the program halts, faults or waits for input, a breakpoint is
+// reached, a watchpoint is triggered or the stop condition holds, and say
+// which. A watchpoint stops execution after the instruction accessing the
+// watched word. The instruction under the program counter always executes,
+// so that a run can continue from a breakpoint. Each instruction is traced
+// if the machine has a tracer.
+pub fn do_mix_run(st: &mut MixState, cond: &StopCondition) -> RunOutcome {
+    let (instructions, cycles) = (st.instructions, st.cycles);
     st.is_running = true;
-    while st.is_running {
+    st.breakpoint_hit = None;
+    let mut first = true;
+    let reason = loop {
+        if !first {
+            st.breakpoint_hit = check_breakpoints(st);
+            if st.breakpoint_hit.is_some() || cond.breakpoints.contains(&st.pc) {
+                break StopReason::Breakpoint(st.pc);
+            }
+        }
+        let over = |limit: Option<u64>, n: u64| limit.is_some_and(|l| n >= l);
+        if over(cond.instructions, st.instructions - instructions) ||
+            over(cond.time, st.cycles - cycles) {
+            break StopReason::LimitReached;
+        }
+        first = false;
         do_mix_step(st);
+        if let Some(unit) = st.waiting {
+            break StopReason::WaitingForInput(unit);
+        } else if let Some(msg) = &st.panic_msg {
+            break StopReason::Fault(msg.clone());
+        } else if let Some(hit) = &st.watch_hit {
+            break StopReason::Watchpoint(hit.clone());
+        } else if !st.is_running {
+            break StopReason::Halted;
+        } else if cond.done.is_some_and(|done| done(st)) {
+            break StopReason::Done;
+        }
+    };
+    st.is_running = false;
+    RunOutcome {
+        reason,
+        stats: RunStats {
+            instructions: st.instructions - instructions,
+            cycles: st.cycles - cycles,
+        },
     }
-    match &st.panic_msg {
-        Some(s) => println!("Panicked! {}", s),
-        None => println!("Halted!"),
+}
+
+// Run as do_mix_run does, tracing the instructions executed with the given
+// tracer, and flush the trace when the run stops.
+pub fn do_mix_run_traced(st: &mut MixState, cond: &StopCondition,
+                         tracer: mix_trace::Tracer) -> RunOutcome {
+    let saved = st.tracer.replace(tracer);
+    let outcome = do_mix_run(st, cond);
+    if let Some(mut t) = std::mem::replace(&mut st.tracer, saved) {
+        // A failed flush is ignored, as a failed write stops tracing.
+        let _ = t.out.flush();
+    }
+    outcome
+}
+
+// Run backwards until reaching a breakpoint, undoing a write to a field
+// watched for writes, or the start of the history. At a watchpoint, the
+// machine is left just before the instruction making the write. Hits
+// aren't counted going backwards, nor are ignore counts used up. Fail,
+// saying why, on reaching the start of the history.
+pub fn do_mix_reverse_run(st: &mut MixState) -> Result<StopReason, String> {
+    st.breakpoint_hit = None;
+    st.watch_hit = None;
+    loop {
+        let entry = step_back(st)?;
+        for w in &entry.writes {
+            let hit = st.watchpoints.iter().find(|wp| {
+                wp.enabled && wp.kind != WatchKind::Read &&
+                    wp.addr == w.addr && fields_overlap(wp.field, w.field)
+            });
+            if let Some(wp) = hit {
+                let hit = WatchHit {
+                    id: wp.id, addr: w.addr, write: true, old: w.old,
+                    new: w.new, pc: entry.pc,
+                };
+                st.watch_hit = Some(hit.clone());
+                return Ok(StopReason::Watchpoint(hit));
+            }
+        }
+        let hit = st.breakpoints.iter().find(|b| {
+            b.enabled && b.addr == st.pc && match &b.condition {
+                Some(c) => mix_expr::eval(c, st) != Ok(0),
+                None => true,
+            }
+        });
+        if let Some(b) = hit {
+            st.breakpoint_hit = Some(b.id);
+            return Ok(StopReason::Breakpoint(st.pc));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mix_deck;
+
+    fn instr(a: u32, i: u8, f: u8, c: u8) -> MixWord {
+        mix_util::word_from_bytes(0, (a / 64) as u8, (a % 64) as u8, i, f, c)
+    }
+
+    fn run_to(st: &mut MixState, pc: MixAddr) -> StopReason {
+        do_mix_run(st, &StopCondition {
+            done: Some(&|st| st.pc == pc),
+            ..Default::default()
+        }).reason
+    }
+
+    #[test]
+    fn test_load_store_move_and_watchpoints() {
+        let mut mem: MixMemory = [0; MEM_SIZE];
+        mem[100] = instr(2000, 0, 10, 8);   // LDA  2000(1:2)
+        mem[101] = instr(2001, 0, 37, 24);  // STA  2001(4:5)
+        mem[102] = instr(2000, 0, 37, 18);  // LD2N 2000(4:5)
+        mem[103] = instr(2000, 0, 2, 7);    // MOVE 2000(2)
+        mem[104] = instr(2000, 0, 0, 33);   // STZ  2000(0:0)
+        mem[2000] = mix_util::word_from_bytes(1, 1, 2, 3, 4, 5);
+        let mut st = MixState::new(&mut mem);
+        st.pc = 100;
+        st.r.i[0] = 3000;
+        let read = add_watchpoint(&mut st, 2000, 9, WatchKind::Read);
+        let write = add_watchpoint(&mut st, 2001, 36, WatchKind::Write);
+
+        do_mix_step(&mut st);
+        assert_eq!(st.r.a, 66);
+        assert_eq!(st.watch_hit.as_ref().map(|w| w.id), Some(read));
+        do_mix_step(&mut st);
+        assert_eq!(st.memory[2001], 66);
+        assert_eq!(st.watch_hit, Some(WatchHit {
+            id: write, addr: 2001, write: true, old: 0, new: 66, pc: 101,
+        }));
+        do_mix_step(&mut st);
+        assert_eq!(st.r.i[1], (1 << 12) | 261);
+        assert_eq!(st.watch_hit, None);
+        do_mix_step(&mut st);
+        assert_eq!(&st.memory[3000 .. 3002], &[st.memory[2000], 66]);
+        assert_eq!(st.r.i[0], 3002);
+        do_mix_step(&mut st);
+        assert_eq!(st.memory[2000], mix_util::word_from_bytes(0, 1, 2, 3, 4, 5));
+        assert_eq!(st.watchpoints[0].hits, 2);
+
+        // A MOVE faulting on a word leaves where it goes, and rI1, alone.
+        st.memory[105] = instr(3999, 0, 2, 7);  // MOVE 3999(2)
+        st.memory[2501] = 7;
+        st.r.i[0] = 2500;
+        do_mix_step(&mut st);
+        assert!(st.panic_msg.is_some());
+        assert_eq!((st.memory[2500], st.memory[2501], st.r.i[0]), (0, 7, 2501));
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        // Memory full of NOPs, so running goes round and round.
+        let mut mem: MixMemory = [0; MEM_SIZE];
+        let mut st = MixState::new(&mut mem);
+        let id = add_breakpoint(&mut st, 5);
+        find_breakpoint(&mut st, id).unwrap().ignore = 1;
+        let outcome = do_mix_run(&mut st, &StopCondition::default());
+        assert_eq!((outcome.reason, st.breakpoint_hit), (StopReason::Breakpoint(5), Some(id)));
+        assert_eq!(outcome.stats, RunStats { instructions: 4005, cycles: 4005 });
+        assert_eq!(st.breakpoints[0].hits, 2);
+
+        // Continuing passes over the breakpoint it stopped at.
+        let other = add_breakpoint(&mut st, 6);
+        do_mix_run(&mut st, &StopCondition::default());
+        assert_eq!((st.pc, st.breakpoint_hit), (6, Some(other)));
+        find_breakpoint(&mut st, other).unwrap().enabled = false;
+        do_mix_run(&mut st, &StopCondition::default());
+        assert_eq!((st.pc, st.breakpoints[0].hits), (5, 3));
+
+        // Breakpoints and limits of the run's own.
+        let cond = StopCondition { breakpoints: vec![7], ..Default::default() };
+        assert_eq!(do_mix_run(&mut st, &cond).reason, StopReason::Breakpoint(7));
+        assert_eq!(st.breakpoint_hit, None);
+        let cond = StopCondition { instructions: Some(2), ..Default::default() };
+        assert_eq!(do_mix_run(&mut st, &cond).reason, StopReason::LimitReached);
+        assert_eq!(st.pc, 9);
+        let cond = StopCondition { time: Some(3), ..Default::default() };
+        assert_eq!(do_mix_run(&mut st, &cond).stats.cycles, 3);
+    }
+
+    #[test]
+    fn test_jumps_and_calls() {
+        let mut mem: MixMemory = [0; MEM_SIZE];
+        for (addr, w) in &[
+            (1000, instr(5, 0, 2, 49)),     // ENT1 5
+            (1001, instr(1010, 0, 0, 39)),  // JMP SUB
+            (1002, instr(2000, 0, 5, 57)),  // CMP1 2000
+            (1003, instr(1005, 0, 5, 39)),  // JE 1005
+            (1005, instr(1007, 0, 0, 39)),  // JMP 1007
+            (1007, instr(1009, 0, 2, 41)),  // J1P 1009
+            (1010, instr(1012, 0, 2, 32)),  // SUB STJ EXIT(0:2)
+            (1011, instr(2, 0, 1, 49)),     // DEC1 2
+            (1012, instr(0, 0, 0, 39)),     // EXIT JMP *
+            (2000, 3),
+        ] {
+            mem[*addr] = *w;
+        }
+        let mut st = MixState::new(&mut mem);
+        st.pc = 1000;
+        assert_eq!(run_to(&mut st, 1011), StopReason::Done);
+        assert_eq!(st.calls, vec![CallFrame {
+            call: 1001, entry: 1010, ret: 1002, saved: true,
+        }]);
+        assert_eq!(mix_util::get_bytes(st.memory[1012], 1, 2), 1002);
+
+        run_to(&mut st, 1002);
+        assert!(st.calls.is_empty());
+        assert_eq!((st.r.i[0], st.r.j), (3, 1013));
+
+        // JE jumps without calling, and J1P loses the way back from JMP.
+        run_to(&mut st, 1007);
+        assert_eq!((st.comparison.clone(), st.calls.len()), (MixCompare::Equal, 1));
+        do_mix_step(&mut st);
+        assert_eq!((st.pc, st.r.j, st.calls.len()), (1009, 1008, 0));
+    }
+
+    #[test]
+    fn test_history() {
+        let mut mem: MixMemory = [0; MEM_SIZE];
+        mem[0] = instr(1, 0, 0, 48);     // INCA 1
+        mem[1] = instr(3000, 0, 5, 24);  // STA 3000
+        mem[2] = instr(0, 0, 0, 39);     // JMP 0
+        let mut st = MixState::new(&mut mem);
+        start_recording(&mut st);
+        goto_instruction(&mut st, 150_000).unwrap();
+        assert_eq!((st.r.a, st.memory[3000]), (50_000, 50_000));
+        assert_eq!(earliest_instruction(&st), Some(0));
+
+        // Further back than the undo log reaches, so from a checkpoint.
+        goto_instruction(&mut st, 30_000).unwrap();
+        assert_eq!((st.r.a, st.memory[3000], st.pc), (10_000, 10_000, 0));
+        goto_instruction(&mut st, 30_001).unwrap();
+        assert_eq!(st.r.a, 10_001);
+        step_back(&mut st).unwrap();
+        assert_eq!((st.r.a, st.instructions), (10_000, 30_000));
+
+        // Back to the write of a watched word.
+        goto_instruction(&mut st, 30_006).unwrap();
+        let id = add_watchpoint(&mut st, 3000, 5, WatchKind::Write);
+        assert!(matches!(do_mix_reverse_run(&mut st), Ok(StopReason::Watchpoint(_))));
+        assert_eq!((st.pc, st.memory[3000], st.instructions), (1, 10_001, 30_004));
+        assert_eq!(st.watch_hit.as_ref().map(|h| (h.id, h.new)), Some((id, 10_002)));
+
+        st.watchpoints.clear();
+        let id = add_breakpoint(&mut st, 0);
+        assert_eq!(do_mix_reverse_run(&mut st), Ok(StopReason::Breakpoint(0)));
+        assert_eq!((st.pc, st.breakpoint_hit, st.r.a), (0, Some(id), 10_001));
+    }
+
+    // The shift examples of TAOCP 1.3.1, in order, then SLAX.
+    #[test]
+    fn test_shifts() {
+        let mut mem: MixMemory = [0; MEM_SIZE];
+        for (addr, w) in &[
+            (0, instr(1, 0, 3, 6)),    // SRAX 1
+            (1, instr(2, 0, 0, 6)),    // SLA 2
+            (2, instr(4, 0, 5, 6)),    // SRC 4
+            (3, instr(2, 0, 1, 6)),    // SRA 2
+            (4, instr(501, 0, 4, 6)),  // SLC 501
+            (5, instr(3, 0, 2, 6)),    // SLAX 3
+        ] {
+            mem[*addr] = *w;
+        }
+        let mut st = MixState::new(&mut mem);
+        st.r.a = mix_util::word_from_bytes(0, 1, 2, 3, 4, 5);
+        st.r.x = mix_util::word_from_bytes(1, 6, 7, 8, 9, 10);
+        for (a, x) in &[
+            ([0, 1, 2, 3, 4], [5, 6, 7, 8, 9]),
+            ([2, 3, 4, 0, 0], [5, 6, 7, 8, 9]),
+            ([6, 7, 8, 9, 2], [3, 4, 0, 0, 5]),
+            ([0, 0, 6, 7, 8], [3, 4, 0, 0, 5]),
+            ([0, 6, 7, 8, 3], [4, 0, 0, 5, 0]),
+            ([8, 3, 4, 0, 0], [5, 0, 0, 0, 0]),
+        ] {
+            do_mix_step(&mut st);
+            assert_eq!(st.r.a, mix_util::word_from_bytes(0, a[0], a[1], a[2], a[3], a[4]));
+            assert_eq!(st.r.x, mix_util::word_from_bytes(1, x[0], x[1], x[2], x[3], x[4]));
+        }
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let mut mem: MixMemory = [0; MEM_SIZE];
+        for (addr, w) in &[
+            (0, instr(5, 0, 2, 48)),    // ENTA 5
+            (1, instr(1, 0, 0, 6)),     // SLA 1
+            (2, instr(2000, 0, 5, 1)),  // ADD 2000
+            (3, instr(2001, 0, 5, 2)),  // SUB 2001
+            (4, instr(0, 0, 2, 5)),     // HLT
+            (2000, 10),
+            (2001, 3),
+        ] {
+            mem[*addr] = *w;
+        }
+        let mut st = MixState::new(&mut mem);
+        let outcome = do_mix_run(&mut st, &StopCondition::default());
+        assert_eq!((outcome.reason, st.r.a), (StopReason::Halted, 64 * 5 + 10 - 3));
+    }
+
+    #[test]
+    fn test_bad_fields() {
+        let mut mem: MixMemory = [0; MEM_SIZE];
+        mem[0] = instr(2000, 0, 7, 8);     // LDA 2000(0:7)
+        mem[1] = instr(2000, 0, 6, 1);     // FADD 2000
+        mem[2] = instr(2000, 0, 6, 56);    // FCMP 2000
+        mem[3] = instr(2000, 0, 6, 57);    // CMP1 2000(0:6)
+        mem[4] = instr(2000, 0, 41, 24);   // STA 2000(5:1)
+        mem[2000] = 5;
+        let mut st = MixState::new(&mut mem);
+        for (pc, msg) in &[
+            (0, "Bad field specification (0:7)"),
+            (1, "FADD is not implemented"),
+            (2, "FCMP is not implemented"),
+            (3, "Bad field specification (0:6)"),
+            (4, "Bad field specification (5:1)"),
+        ] {
+            st.panic_msg = None;
+            st.pc = *pc;
+            let outcome = do_mix_run(&mut st, &StopCondition::default());
+            assert_eq!(outcome.reason, StopReason::Fault(msg.to_string()));
+        }
+        assert_eq!((st.r.a, st.memory[2000]), (0, 5));
+    }
+
+    // A punched deck boots through its loading routine, which loads the
+    // program and jumps to its start.
+    #[test]
+    fn test_boot_from_deck() {
+        let mut words = vec![(3000, instr(2000, 0, 5, 8)), (3001, instr(0, 0, 2, 5))];
+        for i in 0 .. 10 {
+            words.push((2000 + i as MixAddr, mix_util::to_mix_word(700 - 100 * i)));
+        }
+        let deck = mix_deck::punch_deck(&words, 3000).unwrap();
+        let mut mem: MixMemory = [0; MEM_SIZE];
+        let mut st = MixState::new(&mut mem);
+        assert!(go_button(&mut st).is_err());
+        mix_io::attach(&mut st, mix_io::read_medium(16, deck.as_bytes()).unwrap());
+        go_button(&mut st).unwrap();
+        let cond = StopCondition { breakpoints: vec![3000], ..Default::default() };
+        assert_eq!(do_mix_run(&mut st, &cond).reason, StopReason::Breakpoint(3000));
+        for (addr, w) in &words {
+            assert_eq!(st.memory[*addr as usize], *w, "word at {}", addr);
+        }
+        assert_eq!(do_mix_run(&mut st, &StopCondition::default()).reason, StopReason::Halted);
+        assert_eq!(st.r.a, 700);
+    }
+
+    #[test]
+    fn test_io_and_halt() {
+        let mut mem: MixMemory = [0; MEM_SIZE];
+        for (addr, w) in &[
+            (100, instr(1000, 0, 16, 36)),  // IN 1000(16)
+            (101, instr(103, 0, 16, 38)),   // JRED 103(16)
+            (103, instr(103, 0, 16, 34)),   // JBUS 103(16)
+            (104, instr(1000, 0, 5, 15)),   // LDX 1000
+            (105, instr(0, 0, 0, 5)),       // NUM
+            (106, instr(1, 0, 0, 48)),      // INCA 1
+            (107, instr(0, 0, 1, 5)),       // CHAR
+            (108, instr(2000, 0, 5, 31)),   // STX 2000
+            (109, instr(2000, 0, 18, 37)),  // OUT 2000(18)
+            (110, instr(0, 0, 18, 35)),     // IOC 0(18)
+            (111, instr(0, 0, 2, 5)),       // HLT
+        ] {
+            mem[*addr] = *w;
+        }
+        let mut st = MixState::new(&mut mem);
+        mix_io::attach(&mut st, mix_io::read_medium(16, b"12345\n").unwrap());
+        mix_io::attach(&mut st, mix_io::new_device(18).unwrap());
+        start_recording(&mut st);
+        st.pc = 100;
+        let outcome = do_mix_run(&mut st, &StopCondition::default());
+        assert_eq!((outcome.reason, st.pc, st.instructions), (StopReason::Halted, 112, 11));
+        assert_eq!(st.r.a, mix_util::word_from_bytes(0, 30, 30, 30, 30, 30));
+        assert_eq!(mix_io::write_medium(&st.io[1]), b"12346\n\x0c\n".to_vec());
+
+        // Undoing output takes it off the medium, and input goes back on.
+        goto_instruction(&mut st, 0).unwrap();
+        assert_eq!((st.io[0].position, st.io[1].blocks.len()), (0, 0));
+        // Going forward stops at the HLT.
+        assert_eq!(goto_instruction(&mut st, 20),
+                   Err("Halted at instruction 11".to_string()));
+        assert_eq!(st.pc, 112);
+        goto_instruction(&mut st, 0).unwrap();
+        do_mix_step(&mut st);
+        st.pc = 100;
+        let outcome = do_mix_run(&mut st, &StopCondition::default());
+        assert_eq!(outcome.reason, StopReason::WaitingForInput(16));
+        assert_eq!((st.pc, st.instructions, outcome.stats), (100, 1, RunStats::default()));
+        assert_eq!(goto_instruction(&mut st, 5),
+                   Err("Waiting for input on unit 16 at instruction 1".to_string()));
+        st.io[0].blocks.push(vec![0; 16]);
+        do_mix_step(&mut st);
+        assert_eq!((st.pc, st.waiting), (101, None));
+        st.pc = 109;
+        st.io.clear();
+        do_mix_step(&mut st);
+        assert_eq!(st.panic_msg.as_deref(), Some("No line printer on unit 18"));
     }
 }
@@ -0,0 +1,285 @@
+// Object decks: programs punched on cards in the format read by the
+// loading routine of TAOCP exercise 1.3.1-26, led by the two cards of the
+// loading routine itself. Pressing GO with the deck in the card reader
+// (see mix_core::go_button) reads the first card into 0000-0015 and jumps
+// to it; the routine reads its second card, then loads the program and
+// jumps to its start.
+//
+// Each card of the program is 80 columns of MIX characters:
+//   Columns 1-5:   checksum (ignored by the loading routine)
+//   Column 6:      the number n of words on this card, 1 to 7
+//   Columns 7-10:  the location of the first word, in decimal
+//   Columns 11-80: n words of 10 columns each, as decimal magnitudes.
+//                  A negative word has its last digit "overpunched", so
+//                  that digit d is punched as the character with code 10+d.
+// The words are loaded into consecutive locations. The last card is a
+// transfer card, with TRANS0 in columns 1-6 and the start address in
+// columns 7-10.
+//
+// The checksum is the sum of the location, the magnitudes of the words and
+// the number of negative words, modulo 100000.
+//
+// The loading routine lives in 0000-0044, so every word must be loaded at
+// location 100 or above.
+
+use crate::mix_types::*;
+use crate::mix_util;
+
+pub const CARD_COLUMNS: usize = 80;
+pub const WORDS_PER_CARD: usize = 7;
+pub const MIN_LOAD_ADDR: usize = 100;
+
+const WORD_DIGITS: usize = 10;
+const CHECKSUM_MODULUS: u64 = 100000;
+
+// The loading routine, as the answer to exercise 1.3.1-26 gives it: each
+// instruction as (address, index, field, opcode). It's punched as
+// characters, so every byte is a character code and every sign is +.
+// Some instructions double as the constants 1, 25 and 30 in their (0:2)
+// field. Cards are read into BUFF = 0029-0044, which overlaps the end of
+// the routine's second card, so the routine ends at 0028.
+const LOADER: [(u16, u8, u8, u8); 29] = [
+    (16, 0, 16, 36),  // LOC   IN   16(16)      read the second card
+    (29, 0, 16, 36),  // READ  IN   BUFF(16)    read the next card
+    (0, 0, 0, 9),     //       LD1  0(0:0)      rI1 <- 0
+    (3, 0, 16, 34),   //       JBUS *(16)
+    (30, 0, 5, 8),    //       LDA  BUFF+1      columns 6-10
+    (1, 0, 0, 6),     // =1=   SLA  1
+    (6, 0, 3, 6),     //       SRAX 6           columns 7-10
+    (30, 0, 0, 5),    // =30=  NUM  30
+    (0, 0, 5, 24),    //       STA  LOC         the first location
+    (30, 0, 9, 8),    //       LDA  BUFF+1(1:1) column 6
+    (7, 0, 2, 2),     //       SUB  =30=(0:2)   the number of words
+    (0, 0, 5, 11),    // LOOP  LD3  LOC
+    (0, 3, 1, 40),    //       JAZ  0,3         start on a transfer card
+    (29, 0, 5, 24),   //       STA  BUFF
+    (0, 0, 5, 8),     //       LDA  LOC
+    (5, 0, 2, 1),     //       ADD  =1=(0:2)
+    (0, 0, 5, 24),    //       STA  LOC
+    (32, 1, 45, 8),   //       LDA  BUFF+3,1(5:5)  the last digit
+    (22, 0, 2, 2),    //       SUB  =25=(0:2)   negative if overpunched
+    (0, 3, 0, 24),    //       STA  0,3(0:0)    the sign
+    (31, 1, 5, 8),    //       LDA  BUFF+2,1
+    (32, 1, 5, 15),   //       LDX  BUFF+3,1
+    (25, 0, 0, 5),    // =25=  NUM  25
+    (0, 3, 13, 24),   //       STA  0,3(1:5)    the magnitude
+    (0, 1, 2, 7),     //       MOVE 0,1(2)      rI1 <- rI1+2
+    (29, 0, 5, 8),    //       LDA  BUFF
+    (5, 0, 2, 2),     //       SUB  =1=(0:2)
+    (11, 0, 2, 40),   //       JAP  LOOP
+    (1, 0, 0, 39),    //       JMP  READ
+];
+const LOADER_CARDS: usize = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeckError {
+    // The card the error was found on, counting from 1. Zero when punching.
+    pub card: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for DeckError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        if self.card == 0 {
+            write!(f, "Deck error: {}", self.message)
+        } else {
+            write!(f, "Deck error on card {}: {}", self.card, self.message)
+        }
+    }
+}
+
+impl std::error::Error for DeckError {}
+
+fn error(card: usize, message: String) -> DeckError {
+    DeckError { card, message }
+}
+
+fn checksum(addr: usize, words: &[MixWord]) -> u64 {
+    let mut sum = addr as u64;
+    for w in words {
+        sum += mix_util::get_bytes(*w, 1, 5) as u64;
+        sum += mix_util::get_bytes(*w, 0, 0) as u64;
+    }
+    sum % CHECKSUM_MODULUS
+}
+
+fn punch_word(w: MixWord) -> String {
+    let digits = format!("{:010}", mix_util::get_bytes(w, 1, 5));
+    if mix_util::get_bytes(w, 0, 0) == 0 {
+        return digits;
+    }
+    let last = digits.as_bytes()[WORD_DIGITS - 1] - b'0';
+    let mut s: String = digits[.. WORD_DIGITS - 1].to_string();
+    s.push(mix_util::mix_to_char(10 + last));
+    s
+}
+
+// The two cards of the loading routine, as lines of text.
+pub fn loader_cards() -> String {
+    let mut chars = String::new();
+    for &(a, i, f, c) in LOADER.iter() {
+        for b in &[(a / 64) as u8, (a % 64) as u8, i, f, c] {
+            chars.push(mix_util::mix_to_char(*b));
+        }
+    }
+    let mut cards = String::new();
+    for card in 0 .. LOADER_CARDS {
+        let text: String = chars.chars().skip(card * CARD_COLUMNS)
+            .take(CARD_COLUMNS).collect();
+        cards.push_str(&format!("{:<80}\n", text));
+    }
+    cards
+}
+
+// Punch the given words, as (location, word) pairs, onto a deck of cards
+// after the loading routine. Runs of consecutive locations share cards.
+// The deck ends with a transfer card to the start address.
+pub fn punch_deck(words: &[(MixAddr, MixWord)], start: MixAddr)
+    -> Result<String, DeckError> {
+    let mut sorted: Vec<(usize, MixWord)> = words.iter()
+        .map(|&(a, w)| (a as usize, w)).collect();
+    // A stable sort keeps later words for the same location last.
+    sorted.sort_by_key(|&(a, _)| a);
+    let mut deduped: Vec<(usize, MixWord)> = vec![];
+    for (a, w) in sorted {
+        if !(MIN_LOAD_ADDR .. MEM_SIZE).contains(&a) {
+            return Err(error(0, format!(
+                "Cannot load a word at location {}; the loading routine \
+                 needs locations below {}", a, MIN_LOAD_ADDR)));
+        }
+        match deduped.last_mut() {
+            Some(last) if last.0 == a => last.1 = w,
+            _ => deduped.push((a, w)),
+        }
+    }
+
+    let mut deck = loader_cards();
+    let mut ix = 0;
+    while ix < deduped.len() {
+        let addr = deduped[ix].0;
+        let mut card_words = vec![deduped[ix].1];
+        ix += 1;
+        while ix < deduped.len() && card_words.len() < WORDS_PER_CARD &&
+            deduped[ix].0 == addr + card_words.len() {
+            card_words.push(deduped[ix].1);
+            ix += 1;
+        }
+        let mut card = format!("{:05}{}{:04}", checksum(addr, &card_words),
+                               card_words.len(), addr);
+        for w in &card_words {
+            card.push_str(&punch_word(*w));
+        }
+        deck.push_str(&format!("{:<80}\n", card));
+    }
+    deck.push_str(&format!("{:<80}\n", format!("TRANS0{:04}", start)));
+    Ok(deck)
+}
+
+fn read_number(card: usize, s: &[char], what: &str) -> Result<u64, DeckError> {
+    let text: String = s.iter().collect();
+    match text.trim_start().parse::<u64>() {
+        Ok(n) => Ok(n),
+        Err(_) => Err(error(card, format!("Bad {} '{}'", what, text))),
+    }
+}
+
+fn read_word(card: usize, s: &[char]) -> Result<MixWord, DeckError> {
+    let mut digits: Vec<char> = s.to_vec();
+    let mut sign = 0;
+    if let Some(b) = mix_util::char_to_mix(digits[WORD_DIGITS - 1]) {
+        if (10 ..= 19).contains(&b) {
+            sign = 1;
+            digits[WORD_DIGITS - 1] = (b'0' + b - 10) as char;
+        }
+    }
+    let n = read_number(card, &digits, "word")?;
+    if n > MIX_WORD_MAX as u64 {
+        return Err(error(card, format!("Word {} does not fit in MIX", n)));
+    }
+    Ok(((sign as u32) << 30) | n as u32)
+}
+
+// Load a deck punched by punch_deck without running the loading routine,
+// checking each card's checksum, which the routine ignores. The routine's
+// own cards are skipped if the deck starts with them. Returns the memory
+// image and the start address from the transfer card.
+pub fn load_deck(deck: &str) -> Result<(Box<MixMemory>, MixAddr), DeckError> {
+    let mut memory: Box<MixMemory> = Box::new([0; MEM_SIZE]);
+    let loader = loader_cards();
+    let skip = if loader.lines().zip(deck.lines())
+        .filter(|(l, d)| l.trim_end() == d.trim_end()).count() == LOADER_CARDS {
+        LOADER_CARDS
+    } else {
+        0
+    };
+    for (i, line) in deck.lines().enumerate().skip(skip) {
+        let card = i + 1;
+        let mut cols: Vec<char> = line.chars().collect();
+        if cols.len() > CARD_COLUMNS {
+            return Err(error(card, "Card has more than 80 columns".to_string()));
+        }
+        cols.resize(CARD_COLUMNS, ' ');
+        let addr = read_number(card, &cols[6 .. 10], "location")? as usize;
+        if cols[.. 6].iter().collect::<String>() == "TRANS0" {
+            if addr >= MEM_SIZE {
+                return Err(error(card, format!(
+                    "Start address {} is outside memory", addr)));
+            }
+            return Ok((memory, addr as MixAddr));
+        }
+        let n = read_number(card, &cols[5 .. 6], "word count")? as usize;
+        if !(1 ..= WORDS_PER_CARD).contains(&n) {
+            return Err(error(card, format!("Bad word count {}", n)));
+        }
+        if addr < MIN_LOAD_ADDR || addr + n > MEM_SIZE {
+            return Err(error(card, format!("Bad location {}", addr)));
+        }
+        let mut words = vec![];
+        for k in 0 .. n {
+            let start = 10 + k * WORD_DIGITS;
+            words.push(read_word(card, &cols[start .. start + WORD_DIGITS])?);
+        }
+        let sum = read_number(card, &cols[.. 5], "checksum")?;
+        if sum != checksum(addr, &words) {
+            return Err(error(card, format!(
+                "Checksum {} does not match the card's contents", sum)));
+        }
+        memory[addr .. addr + n].copy_from_slice(&words);
+    }
+    Err(error(0, "Deck has no transfer card".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_punch_and_load() {
+        let mut words = vec![];
+        for i in 0 .. 9 {
+            words.push((1000 + i as MixAddr, mix_util::to_mix_word(i * 1000 - 3)));
+        }
+        words.push((3000, mix_util::to_mix_word(-1073741823)));
+        let deck = punch_deck(&words, 3000).unwrap();
+        let cards: Vec<&str> = deck.lines().collect();
+        assert_eq!(cards.len(), 6);
+        assert!(cards.iter().all(|c| c.chars().count() == CARD_COLUMNS));
+        assert_eq!(&cards[0][.. 10], " O O6 Z O6");
+        assert_eq!(&cards[2][5 .. 30], "71000000000000L0000000997");
+        assert_eq!(&cards[4][5 .. 20], "13000107374182L");
+        assert_eq!(cards[5].trim_end(), "TRANS03000");
+
+        let (memory, start) = load_deck(&deck).unwrap();
+        assert_eq!(start, 3000);
+        for (a, w) in &words {
+            assert_eq!(memory[*a as usize], *w);
+        }
+
+        let bad = deck.replacen("0000000997", "0000000998", 1);
+        assert_eq!(load_deck(&bad).unwrap_err().card, 3);
+        let unled: String = deck.lines().skip(LOADER_CARDS).map(|c| format!("{}\n", c))
+            .collect();
+        assert_eq!(load_deck(&unled).unwrap().1, 3000);
+        assert!(punch_deck(&[(99, 1)], 100).is_err());
+    }
+}
@@ -0,0 +1,423 @@
+// Expressions over the state of a machine, for conditional breakpoints and
+// for printing values in the debugger. For example
+//   rI1 > 10 && mem[2000](1:3) == 5
+//   mem[TABLE+3](4:5)
+//
+// Operands are decimal numbers, the registers rA, rX, rI1 to rI6 and rJ,
+// memory words mem[address], the overflow toggle overflow (0 or 1), the
+// comparison indicator cmp (compared with less, equal and greater), the
+// cycle counter cycles, and symbols of the loaded MIXAL program. A field
+// specification (L:R) after a register or memory word takes that field of
+// it, shifted right as the load operators do.
+//
+// Operators, from lowest to highest precedence, as in C:
+//   ||   &&   == != < <= > >=   + -   * / %   unary - and !
+// Comparisons and logical operators give 0 or 1.
+
+use std::collections::HashMap;
+use crate::mix_types::*;
+use crate::mix_util;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(i64),
+    Register(Register),
+    Memory(Box<Expr>),
+    // A field, given as a field specification, of a register or memory word.
+    Field(Box<Expr>, MixByte),
+    Overflow,
+    Comparison,
+    Cycles,
+    Unary(UnOp, Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExprError {
+    // The column (in chars, counting from 1) of the problem, or zero when
+    // evaluating.
+    pub column: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for ExprError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        if self.column == 0 {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "column {}: {}", self.column, self.message)
+        }
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+fn error(column: usize, message: String) -> ExprError {
+    ExprError { column, message }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Number(i64),
+    Word(String),
+    Op(&'static str),
+}
+
+// Operators, longest first so that the lexer finds "<=" before "<".
+const OPS: &[&str] = &[
+    "||", "&&", "==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "%",
+    "!", "(", ")", "[", "]", ":",
+];
+
+struct Parser<'a> {
+    toks: Vec<(Tok, usize)>,
+    pos: usize,
+    end_column: usize,
+    symbols: &'a HashMap<String, i32>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(s: &str, symbols: &'a HashMap<String, i32>)
+        -> Result<Parser<'a>, ExprError> {
+        let chars: Vec<char> = s.chars().collect();
+        let mut toks = vec![];
+        let mut i = 0;
+        'outer: while i < chars.len() {
+            let c = chars[i];
+            let col = i + 1;
+            if c.is_whitespace() {
+                i += 1;
+                continue;
+            }
+            if c.is_ascii_alphanumeric() {
+                let start = i;
+                while i < chars.len() && chars[i].is_ascii_alphanumeric() {
+                    i += 1;
+                }
+                let text: String = chars[start .. i].iter().collect();
+                let tok = if text.chars().all(|c| c.is_ascii_digit()) {
+                    match text.parse() {
+                        Ok(n) => Tok::Number(n),
+                        Err(_) => return Err(error(col, format!(
+                            "Number {} is too large", text))),
+                    }
+                } else {
+                    Tok::Word(text)
+                };
+                toks.push((tok, col));
+                continue;
+            }
+            for op in OPS {
+                let n = op.chars().count();
+                if i + n <= chars.len() &&
+                    chars[i .. i + n].iter().collect::<String>() == *op {
+                    toks.push((Tok::Op(op), col));
+                    i += n;
+                    continue 'outer;
+                }
+            }
+            return Err(error(col, format!("Unexpected character '{}'", c)));
+        }
+        Ok(Parser { toks, pos: 0, end_column: chars.len() + 1, symbols })
+    }
+
+    fn column(&self) -> usize {
+        self.toks.get(self.pos).map_or(self.end_column, |t| t.1)
+    }
+
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.toks.get(self.pos) {
+            Some((Tok::Op(op), _)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn eat_op(&mut self, op: &str) -> bool {
+        if self.peek_op() == Some(op) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_op(&mut self, op: &str) -> Result<(), ExprError> {
+        if self.eat_op(op) {
+            Ok(())
+        } else {
+            Err(error(self.column(), format!("Expected '{}'", op)))
+        }
+    }
+
+    // Parse a chain of binary operators at one precedence level.
+    fn binary(&mut self, ops: &[(&str, BinOp)],
+              next: fn(&mut Parser<'a>) -> Result<Expr, ExprError>)
+        -> Result<Expr, ExprError> {
+        let mut e = next(self)?;
+        'outer: loop {
+            for (text, op) in ops {
+                if self.eat_op(text) {
+                    e = Expr::Binary(*op, Box::new(e), Box::new(next(self)?));
+                    continue 'outer;
+                }
+            }
+            return Ok(e);
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, ExprError> {
+        self.binary(&[("||", BinOp::Or)], Parser::and)
+    }
+
+    fn and(&mut self) -> Result<Expr, ExprError> {
+        self.binary(&[("&&", BinOp::And)], Parser::comparison)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, ExprError> {
+        self.binary(&[("==", BinOp::Eq), ("!=", BinOp::Ne),
+                      ("<=", BinOp::Le), (">=", BinOp::Ge),
+                      ("<", BinOp::Lt), (">", BinOp::Gt)], Parser::sum)
+    }
+
+    fn sum(&mut self) -> Result<Expr, ExprError> {
+        self.binary(&[("+", BinOp::Add), ("-", BinOp::Sub)], Parser::term)
+    }
+
+    fn term(&mut self) -> Result<Expr, ExprError> {
+        self.binary(&[("*", BinOp::Mul), ("/", BinOp::Div), ("%", BinOp::Rem)],
+                    Parser::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ExprError> {
+        if self.eat_op("-") {
+            Ok(Expr::Unary(UnOp::Neg, Box::new(self.unary()?)))
+        } else if self.eat_op("!") {
+            Ok(Expr::Unary(UnOp::Not, Box::new(self.unary()?)))
+        } else {
+            self.postfix()
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Expr, ExprError> {
+        let e = self.primary()?;
+        let is_word = matches!(e, Expr::Register(_) | Expr::Memory(_));
+        if !is_word || self.peek_op() != Some("(") {
+            return Ok(e);
+        }
+        let column = self.column();
+        self.pos += 1;
+        let l = self.field_number()?;
+        self.expect_op(":")?;
+        let r = self.field_number()?;
+        self.expect_op(")")?;
+        if l > r || r > 5 {
+            return Err(error(column, format!("Bad field ({}:{})", l, r)));
+        }
+        Ok(Expr::Field(Box::new(e), (l * 8 + r) as MixByte))
+    }
+
+    fn field_number(&mut self) -> Result<i64, ExprError> {
+        match self.toks.get(self.pos) {
+            Some((Tok::Number(n), _)) => {
+                self.pos += 1;
+                Ok(*n)
+            },
+            _ => Err(error(self.column(), "Expected a field number".to_string())),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, ExprError> {
+        let column = self.column();
+        let tok = match self.toks.get(self.pos) {
+            Some((t, _)) => t.clone(),
+            None => return Err(error(column, "Expected a value".to_string())),
+        };
+        self.pos += 1;
+        match tok {
+            Tok::Number(n) => Ok(Expr::Number(n)),
+            Tok::Op("(") => {
+                let e = self.or()?;
+                self.expect_op(")")?;
+                Ok(e)
+            },
+            Tok::Op(op) => Err(error(column, format!("Unexpected '{}'", op))),
+            Tok::Word(w) => match w.as_str() {
+                "rA" => Ok(Expr::Register(Register::A)),
+                "rX" => Ok(Expr::Register(Register::X)),
+                "rJ" => Ok(Expr::Register(Register::J)),
+                "rI1" | "rI2" | "rI3" | "rI4" | "rI5" | "rI6" => {
+                    let n = w[2 ..].parse().unwrap_or(1);
+                    Ok(Expr::Register(Register::I(n)))
+                },
+                "mem" => {
+                    self.expect_op("[")?;
+                    let addr = self.or()?;
+                    self.expect_op("]")?;
+                    Ok(Expr::Memory(Box::new(addr)))
+                },
+                "overflow" => Ok(Expr::Overflow),
+                "cmp" => Ok(Expr::Comparison),
+                "cycles" => Ok(Expr::Cycles),
+                "less" => Ok(Expr::Number(-1)),
+                "equal" => Ok(Expr::Number(0)),
+                "greater" => Ok(Expr::Number(1)),
+                _ => match self.symbols.get(&w) {
+                    Some(v) => Ok(Expr::Number(*v as i64)),
+                    None => Err(error(column, format!("Unknown name {}", w))),
+                },
+            },
+        }
+    }
+}
+
+// Parse an expression. Symbols are replaced by their values right away.
+pub fn parse_expr(s: &str, symbols: &HashMap<String, i32>)
+    -> Result<Expr, ExprError> {
+    let mut p = Parser::new(s, symbols)?;
+    let e = p.or()?;
+    if p.pos < p.toks.len() {
+        return Err(error(p.column(), "Unexpected text after expression"
+                         .to_string()));
+    }
+    Ok(e)
+}
+
+fn register_word(st: &MixState, r: Register) -> MixWord {
+    let addr_word = |a: MixAddr| (((a as MixWord) >> 12 & 1) << 30) |
+        (a as MixWord & 0o7777);
+    match r {
+        Register::A => st.r.a,
+        Register::X => st.r.x,
+        Register::I(n) => addr_word(st.r.i[n - 1]),
+        Register::J => addr_word(st.r.j),
+    }
+}
+
+// Evaluate an expression to the word it denotes. Registers and memory give
+// their contents exactly, including the sign of zero; other values must fit
+// in a word.
+pub fn eval_word(e: &Expr, st: &MixState) -> Result<MixWord, ExprError> {
+    match e {
+        Expr::Register(r) => Ok(register_word(st, *r)),
+        Expr::Memory(addr) => {
+            let a = eval(addr, st)?;
+            if !(0 .. MEM_SIZE as i64).contains(&a) {
+                return Err(error(0, format!("Address {} is outside memory", a)));
+            }
+            Ok(st.memory[a as usize])
+        },
+        Expr::Field(w, f) => Ok(mix_util::field_value(eval_word(w, st)?, *f)),
+        _ => {
+            let v = eval(e, st)?;
+            if !(MIX_WORD_MIN as i64 ..= MIX_WORD_MAX as i64).contains(&v) {
+                return Err(error(0, format!("{} does not fit in a word", v)));
+            }
+            Ok(mix_util::to_mix_word(v as i32))
+        },
+    }
+}
+
+// Evaluate an expression to a number.
+pub fn eval(e: &Expr, st: &MixState) -> Result<i64, ExprError> {
+    let flag = |b: bool| if b { 1 } else { 0 };
+    match e {
+        Expr::Number(n) => Ok(*n),
+        Expr::Register(_) | Expr::Memory(_) | Expr::Field(_, _) =>
+            Ok(mix_util::from_mix_word(eval_word(e, st)?) as i64),
+        Expr::Overflow => Ok(flag(st.overflow)),
+        Expr::Comparison => Ok(match st.comparison {
+            MixCompare::Less => -1,
+            MixCompare::Equal => 0,
+            MixCompare::Greater => 1,
+        }),
+        Expr::Cycles => Ok(st.cycles as i64),
+        Expr::Unary(UnOp::Neg, x) => eval(x, st)?.checked_neg()
+            .ok_or_else(|| error(0, "Overflow".to_string())),
+        Expr::Unary(UnOp::Not, x) => Ok(flag(eval(x, st)? == 0)),
+        // Evaluate the right side of && and || only when it's needed.
+        Expr::Binary(BinOp::And, x, y) =>
+            Ok(flag(eval(x, st)? != 0 && eval(y, st)? != 0)),
+        Expr::Binary(BinOp::Or, x, y) =>
+            Ok(flag(eval(x, st)? != 0 || eval(y, st)? != 0)),
+        Expr::Binary(op, x, y) => {
+            let (a, b) = (eval(x, st)?, eval(y, st)?);
+            let v = match op {
+                BinOp::Eq => Some(flag(a == b)),
+                BinOp::Ne => Some(flag(a != b)),
+                BinOp::Lt => Some(flag(a < b)),
+                BinOp::Le => Some(flag(a <= b)),
+                BinOp::Gt => Some(flag(a > b)),
+                BinOp::Ge => Some(flag(a >= b)),
+                BinOp::Add => a.checked_add(b),
+                BinOp::Sub => a.checked_sub(b),
+                BinOp::Mul => a.checked_mul(b),
+                BinOp::Div | BinOp::Rem if b == 0 => {
+                    return Err(error(0, "Division by zero".to_string()));
+                },
+                BinOp::Div => a.checked_div(b),
+                BinOp::Rem => a.checked_rem(b),
+                BinOp::And | BinOp::Or => None,
+            };
+            v.ok_or_else(|| error(0, "Overflow".to_string()))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_eval() {
+        let mut symbols = HashMap::new();
+        symbols.insert("TABLE".to_string(), 1997);
+        let mut mem: MixMemory = [0; MEM_SIZE];
+        mem[2000] = mix_util::word_from_bytes(1, 0, 0, 5, 1, 2);
+        let mut st = MixState::new(&mut mem);
+        st.r.i[0] = 11;
+        st.r.a = mix_util::to_mix_word(-7);
+        st.r.x = 3;
+        st.comparison = MixCompare::Less;
+
+        let ev = |s: &str, st: &MixState| eval(&parse_expr(s, &symbols).unwrap(), st);
+        assert_eq!(ev("rI1 > 10 && mem[2000](1:3) == 5", &st), Ok(1));
+        assert_eq!(ev("rA + rX", &st), Ok(-4));
+        assert_eq!(ev("mem[TABLE+3](4:5)", &st), Ok(66));
+        assert_eq!(ev("mem[2000]", &st), Ok(-(5 * 4096 + 66)));
+        assert_eq!(ev("1 + 2 * 3 - -4 % 3", &st), Ok(8));
+        assert_eq!(ev("cmp == less || 1 / 0", &st), Ok(1));
+        assert_eq!(ev("!overflow && (rI1 - 1) * 2 >= 20", &st), Ok(1));
+        assert!(ev("1 / (rX - 3)", &st).is_err());
+        assert!(ev("mem[4000]", &st).is_err());
+        assert_eq!(eval_word(&parse_expr("rA(0:0)", &symbols).unwrap(), &st),
+                   Ok(1 << 30));
+
+        assert_eq!(parse_expr("rA +", &symbols).unwrap_err().column, 5);
+        assert_eq!(parse_expr("NOSUCH", &symbols).unwrap_err().column, 1);
+        assert!(parse_expr("mem[1](2:1)", &symbols).is_err());
+        assert!(parse_expr("rA rX", &symbols).is_err());
+    }
+}
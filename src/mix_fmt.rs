@@ -1,11 +1,13 @@
 use crate::mix_types::*;
 use crate::mix_util;
 use crate::arch_util;
+use crate::mix_opcodes;
+use crate::mix_opcodes::FieldUse;
 
 // Format a MIX program (a raw byte string) as
 // row-by-row string listing of MIX words. Format
 // each word using the fmt_word function below.
-pub fn fmt_words(in_bytes: &Vec<u8>) -> String {
+pub fn fmt_words(in_bytes: &[u8]) -> String {
     let mut ix: usize = 0;
     let mut fmt_str = String::new();
     while ix + 3 < in_bytes.len() {
@@ -26,3 +28,387 @@ pub fn fmt_word(w: MixWord) -> String {
     let b5 = mix_util::get_bytes(w, 5, 5);
     format!(" {} {:2} {:2} {:2} {:2} {:2}", sign, b1, b2, b3, b4, b5)
 }
+
+// Format a MIX program like fmt_words, but follow each word with its
+// disassembly.
+pub fn fmt_words_disasm(in_bytes: &[u8]) -> String {
+    let mut ix: usize = 0;
+    let mut fmt_str = String::new();
+    while ix + 3 < in_bytes.len() {
+        let w = arch_util::u8sto32(&in_bytes[ix..ix+4]);
+        fmt_str.push_str(&format!("{}  {}\n", fmt_word(w), disasm_word(w)));
+        ix += 4;
+    }
+    fmt_str
+}
+
+// Format a memory image as an addressed dump, one word per line with its
+// location, for example
+// 0100: +  0  0  0  2  5
+// Runs of two or more zero words are collapsed into a line of "...". The
+// last word is always shown, so that the dump records the image's size.
+// If disasm is set, each word is followed by its disassembly as a comment.
+pub fn fmt_memory(words: &[MixWord], disasm: bool) -> String {
+    let mut fmt_str = String::new();
+    let mut ix: usize = 0;
+    while ix < words.len() {
+        let mut end = ix;
+        while end + 1 < words.len() && words[end] == 0 {
+            end += 1;
+        }
+        if end - ix >= 2 {
+            fmt_str.push_str("...\n");
+            ix = end;
+            continue;
+        }
+        let w = words[ix];
+        fmt_str.push_str(&format!("{:04}:{}", ix, fmt_word(w)));
+        if disasm {
+            fmt_str.push_str(&format!("  # {}", disasm_word(w)));
+        }
+        fmt_str.push('\n');
+        ix += 1;
+    }
+    fmt_str
+}
+
+// Extra columns of the pretty table drawn by fmt_words_pretty, beyond the
+// location, sign and bytes of each word.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PrettyColumn {
+    // The word as a signed integer.
+    Integer,
+    // The five bytes as MIX characters.
+    Text,
+    // The word disassembled as an instruction.
+    Instruction,
+    // The (0:2), (3:3), (4:4) and (5:5) fields of an instruction.
+    Fields,
+}
+
+pub const PRETTY_COLUMNS: &[PrettyColumn] = &[
+    PrettyColumn::Integer, PrettyColumn::Text,
+    PrettyColumn::Instruction, PrettyColumn::Fields,
+];
+
+// Look up a pretty column by the name used on the command line.
+pub fn pretty_column(name: &str) -> Option<PrettyColumn> {
+    match name {
+        "int" | "integer" => Some(PrettyColumn::Integer),
+        "text" => Some(PrettyColumn::Text),
+        "instr" | "instruction" => Some(PrettyColumn::Instruction),
+        "fields" => Some(PrettyColumn::Fields),
+        _ => None,
+    }
+}
+
+// A column of a table: its heading, width and whether it's right-aligned.
+struct TableColumn {
+    heading: &'static str,
+    width: usize,
+    right: bool,
+}
+
+fn table_column(c: PrettyColumn) -> TableColumn {
+    match c {
+        PrettyColumn::Integer =>
+            TableColumn { heading: "INTEGER", width: 11, right: true },
+        PrettyColumn::Text =>
+            TableColumn { heading: "TEXT", width: 5, right: false },
+        // The widest instruction is like LD1N -4095,6(1:3), 17 characters.
+        PrettyColumn::Instruction =>
+            TableColumn { heading: "INSTRUCTION", width: 17, right: false },
+        PrettyColumn::Fields =>
+            TableColumn { heading: "    A  I  F  C", width: 14, right: false },
+    }
+}
+
+fn pretty_cell(w: MixWord, c: PrettyColumn) -> String {
+    match c {
+        PrettyColumn::Integer => {
+            let sign = if mix_util::get_bytes(w, 0, 0) != 0 { "-" } else { "" };
+            format!("{}{}", sign, mix_util::get_bytes(w, 1, 5))
+        },
+        PrettyColumn::Text => fmt_text(w),
+        PrettyColumn::Instruction => disasm_word(w),
+        PrettyColumn::Fields => {
+            let sign = if mix_util::get_bytes(w, 0, 0) != 0 { "-" } else { "" };
+            format!("{:>5} {:2} {:2} {:2}",
+                    format!("{}{}", sign, mix_util::get_bytes(w, 1, 2)),
+                    mix_util::get_bytes(w, 3, 3), mix_util::get_bytes(w, 4, 4),
+                    mix_util::get_bytes(w, 5, 5))
+        },
+    }
+}
+
+fn table_rule(cols: &[TableColumn], left: char, mid: char, right: char)
+    -> String {
+    let parts: Vec<String> = cols.iter()
+        .map(|c| "─".repeat(c.width + 2)).collect();
+    format!("{}{}{}\n", left, parts.join(&mid.to_string()), right)
+}
+
+fn table_row(cols: &[TableColumn], cells: &[String]) -> String {
+    let parts: Vec<String> = cols.iter().zip(cells).map(|(c, s)| {
+        if c.right {
+            format!(" {:>1$} ", s, c.width)
+        } else {
+            format!(" {:1$} ", s, c.width)
+        }
+    }).collect();
+    format!("│{}│\n", parts.join("│"))
+}
+
+// Format a MIX program (a raw byte string) as a table drawn with box
+// characters, one row per word, giving its location, sign and bytes. The
+// extra columns follow, in the order given, as long as the table stays
+// within width characters.
+pub fn fmt_words_pretty(in_bytes: &[u8], extras: &[PrettyColumn],
+                        width: usize) -> String {
+    let mut cols = vec![
+        TableColumn { heading: "LOC", width: 4, right: false },
+        TableColumn { heading: "±", width: 1, right: false },
+    ];
+    for heading in &["1", "2", "3", "4", "5"] {
+        cols.push(TableColumn { heading, width: 2, right: true });
+    }
+    let mut table_width = 1 + cols.iter().map(|c| c.width + 3).sum::<usize>();
+    let mut shown = vec![];
+    for e in extras {
+        let c = table_column(*e);
+        if table_width + c.width + 3 > width {
+            break;
+        }
+        table_width += c.width + 3;
+        cols.push(c);
+        shown.push(*e);
+    }
+
+    let mut fmt_str = table_rule(&cols, '┌', '┬', '┐');
+    let headings: Vec<String> = cols.iter()
+        .map(|c| c.heading.to_string()).collect();
+    fmt_str.push_str(&table_row(&cols, &headings));
+    fmt_str.push_str(&table_rule(&cols, '├', '┼', '┤'));
+    for (addr, chunk) in in_bytes.chunks_exact(4).enumerate() {
+        let w = arch_util::u8sto32(chunk);
+        let mut cells = vec![
+            format!("{:04}", addr),
+            (if mix_util::get_bytes(w, 0, 0) != 0 { "-" } else { "+" }).to_string(),
+        ];
+        for b in 1 ..= 5 {
+            cells.push(mix_util::get_bytes(w, b, b).to_string());
+        }
+        for e in &shown {
+            cells.push(pretty_cell(w, *e));
+        }
+        fmt_str.push_str(&table_row(&cols, &cells));
+    }
+    fmt_str.push_str(&table_rule(&cols, '└', '┴', '┘'));
+    fmt_str
+}
+
+// Format a memory image as MIXAL source, in Knuth's card layout, which
+// assembles back to the same image with this crate's assembler or with GNU
+// MDK's mixasm. Nonzero words are written as instructions where that gives
+// back the same word, and otherwise as CON. Runs of zero words are left
+// out, using ORIG to skip them.
+pub fn fmt_mixal(words: &[MixWord], start: MixAddr) -> String {
+    let mut fmt_str = String::new();
+    let mut next: Option<usize> = None;
+    for (addr, w) in words.iter().enumerate() {
+        if *w == 0 {
+            continue;
+        }
+        if next != Some(addr) {
+            fmt_str.push_str(&mixal_line("ORIG", &addr.to_string()));
+        }
+        let negative = mix_util::get_bytes(*w, 0, 0) != 0;
+        let text = if negative && mix_util::get_bytes(*w, 1, 2) == 0 {
+            // An address of -0 assembles to +0, so write the value instead.
+            format!("CON -{}", mix_util::get_bytes(*w, 1, 5))
+        } else {
+            disasm_word(*w)
+        };
+        let mut parts = text.splitn(2, ' ');
+        let op = parts.next().unwrap_or("");
+        fmt_str.push_str(&mixal_line(op, parts.next().unwrap_or("")));
+        next = Some(addr + 1);
+    }
+    fmt_str.push_str(&mixal_line("END", &start.to_string()));
+    fmt_str
+}
+
+fn mixal_line(op: &str, address: &str) -> String {
+    format!("{}\n", format!("{:10} {:4} {}", "", op, address).trim_end())
+}
+
+fn fmt_addr(a: MixAddr) -> String {
+    let sign = if (a >> 12) & 1 != 0 { '-' } else { '+' };
+    format!(" {} {:2} {:2}", sign, (a >> 6) & 63, a & 63)
+}
+
+// Format the five bytes of a word as MIX characters.
+pub fn fmt_text(w: MixWord) -> String {
+    (1 ..= 5).map(|b| mix_util::mix_to_char(
+        mix_util::get_bytes(w, b, b) as MixByte)).collect()
+}
+
+// Format the registers, one per line, with each value's bytes and its
+// value as a signed integer.
+pub fn fmt_registers(r: &MixRegisters) -> String {
+    let mut fmt_str = String::new();
+    for (name, w) in &[("A", r.a), ("X", r.x)] {
+        fmt_str.push_str(&format!("{:2}:{}  {}\n", name, fmt_word(*w),
+                                  mix_util::from_mix_word(*w)));
+    }
+    for (i, a) in r.i.iter().enumerate() {
+        fmt_str.push_str(&format!("I{}:{}  {}\n", i + 1, fmt_addr(*a),
+                                  mix_util::from_mix_addr(*a)));
+    }
+    fmt_str.push_str(&format!("J :{}  {}\n", fmt_addr(r.j),
+                              mix_util::from_mix_addr(r.j)));
+    fmt_str
+}
+
+// Format the registers, flags and program counter of a machine.
+pub fn fmt_state(st: &MixState) -> String {
+    let mut fmt_str = fmt_registers(&st.r);
+    let cmp = match st.comparison {
+        MixCompare::Less => "less",
+        MixCompare::Equal => "equal",
+        MixCompare::Greater => "greater",
+    };
+    fmt_str.push_str(&format!("Overflow: {}  Comparison: {}\n",
+                              if st.overflow { "on" } else { "off" }, cmp));
+    fmt_str.push_str(&format!("PC: {:04}  Cycles: {}  Instructions: {}\n",
+                              st.pc, st.cycles, st.instructions));
+    if let Some(msg) = &st.panic_msg {
+        fmt_str.push_str(&format!("Panicked: {}\n", msg));
+    }
+    fmt_str
+}
+
+// Disassemble a word into a MIXAL instruction, for example
+// LDA 2000,2(1:3)
+// The index and field are left out when they are 0 and the default field.
+// A word which isn't a valid instruction is shown as a CON of its value.
+pub fn disasm_word(w: MixWord) -> String {
+    let sign = if mix_util::get_bytes(w, 0, 0) != 0 { "-" } else { "" };
+    let a = mix_util::get_bytes(w, 1, 2);
+    let i = mix_util::get_bytes(w, 3, 3);
+    let f = mix_util::get_bytes(w, 4, 4) as MixByte;
+    let c = mix_util::get_bytes(w, 5, 5) as MixByte;
+    let op = match mix_opcodes::decode_op(c, f) {
+        Some(op) if i <= 6 => op,
+        _ => return format!("CON {}{}", sign, mix_util::get_bytes(w, 1, 5)),
+    };
+    // NOP, NUM, CHAR and HLT ignore the address, so leave it out if unused.
+    if (c == 0 || c == 5) && a == 0 && i == 0 && f == op.f && sign.is_empty() {
+        return op.name.to_string();
+    }
+    let mut s = format!("{} {}{}", op.name, sign, a);
+    if i != 0 {
+        s.push_str(&format!(",{}", i));
+    }
+    if f != op.f {
+        match op.field_use {
+            FieldUse::Field => s.push_str(&format!("({}:{})", f / 8, f % 8)),
+            FieldUse::Number => s.push_str(&format!("({})", f)),
+            FieldUse::Variant => {},
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instr(a: u32, i: u8, f: u8, c: u8) -> MixWord {
+        mix_util::word_from_bytes(0, (a / 64) as u8, (a % 64) as u8, i, f, c)
+    }
+
+    #[test]
+    fn test_disasm_word() {
+        assert_eq!(disasm_word(instr(2000, 2, 11, 8)), "LDA 2000,2(1:3)");
+        assert_eq!(disasm_word(instr(2000, 0, 5, 8)), "LDA 2000");
+        assert_eq!(disasm_word(instr(1, 3, 5, 4) | (1 << 30)), "DIV -1,3");
+        assert_eq!(disasm_word(instr(3009, 0, 2, 32)), "STJ 3009");
+        assert_eq!(disasm_word(instr(3009, 0, 5, 32)), "STJ 3009(0:5)");
+        assert_eq!(disasm_word(instr(1000, 0, 16, 36)), "IN 1000(16)");
+        assert_eq!(disasm_word(instr(0, 0, 2, 5)), "HLT");
+        assert_eq!(disasm_word(instr(3, 0, 6, 39)), "JG 3");
+        assert_eq!(disasm_word(instr(0, 0, 6, 1)), "FADD 0");
+        assert_eq!(disasm_word(instr(0, 0, 2, 48)), "ENTA 0");
+        // Invalid field, index and variant
+        assert_eq!(disasm_word(instr(0, 0, 7, 8)), "CON 456");
+        assert_eq!(disasm_word(instr(0, 7, 5, 8)), "CON 29000");
+        assert_eq!(disasm_word(instr(0, 0, 10, 39)), "CON 679");
+        assert_eq!(disasm_word(mix_util::to_mix_word(-456)), "CON -456");
+        assert_eq!(disasm_word(mix_util::to_mix_word(-12)), "LD4 -0(0:0)");
+    }
+
+    #[test]
+    fn test_fmt_words_pretty() {
+        let mut bytes: Vec<u8> = vec![];
+        for w in &[instr(2000, 2, 11, 8), mix_util::to_mix_word(-3)] {
+            bytes.extend(arch_util::u32to8s(*w).iter());
+        }
+        let table = fmt_words_pretty(&bytes, PRETTY_COLUMNS, 200);
+        let rows: Vec<&str> = table.lines().collect();
+        assert_eq!(rows.len(), 6);
+        assert_eq!(rows[1], "│ LOC  │ ± │  1 │  2 │  3 │  4 │  5 │     INTEGER \
+                             │ TEXT  │ INSTRUCTION       │     A  I  F  C │");
+        assert_eq!(rows[3], "│ 0000 │ + │ 31 │ 16 │  2 │ 11 │  8 │   524296904 \
+                             │ 1OBJH │ LDA 2000,2(1:3)   │  2000  2 11  8 │");
+        assert_eq!(rows[4], "│ 0001 │ - │  0 │  0 │  0 │  0 │  3 │          -3 \
+                             │     C │ MUL -0(0:0)       │    -0  0  0  3 │");
+        assert!(rows.iter().all(|r| r.chars().count() == rows[0].chars().count()));
+
+        // The widest instruction still fits its column.
+        let widest = instr(4095, 6, 11, 17) | (1 << 30);
+        assert_eq!(disasm_word(widest), "LD1N -4095,6(1:3)");
+        let table = fmt_words_pretty(&arch_util::u32to8s(widest),
+                                     &[PrettyColumn::Instruction], 200);
+        let rows: Vec<&str> = table.lines().collect();
+        assert_eq!(rows[3], "│ 0000 │ - │ 63 │ 63 │  6 │ 11 │ 17 │ LD1N -4095,6(1:3) │");
+        assert!(rows.iter().all(|r| r.chars().count() == rows[0].chars().count()));
+
+        let narrow = fmt_words_pretty(&bytes, PRETTY_COLUMNS, 55);
+        assert!(narrow.contains("INTEGER") && !narrow.contains("TEXT"));
+        assert!(narrow.lines().all(|r| r.chars().count() <= 55));
+    }
+
+    #[test]
+    fn test_fmt_mixal() {
+        let mut words = [0; 3003];
+        words[1000] = instr(2000, 2, 11, 8);
+        words[1001] = instr(0, 0, 2, 5);
+        words[3000] = mix_util::to_mix_word(-3);
+        words[3002] = 1 << 30;
+        let lines = [
+            "ORIG 1000", "LDA  2000,2(1:3)", "HLT", "ORIG 3000", "CON  -3",
+            "ORIG 3002", "CON  -0", "END  1000",
+        ];
+        assert_eq!(fmt_mixal(&words, 1000),
+                   lines.iter().map(|l| format!("{:10} {}\n", "", l))
+                   .collect::<String>());
+    }
+
+    #[test]
+    fn test_fmt_memory() {
+        let mut words = [0; 9];
+        words[1] = 5;
+        words[5] = instr(2000, 0, 5, 8);
+        assert_eq!(fmt_memory(&words, false), "\
+0000: +  0  0  0  0  0
+0001: +  0  0  0  0  5
+...
+0005: + 31 16  0  5  8
+...
+0008: +  0  0  0  0  0
+");
+        assert_eq!(fmt_memory(&words[5 .. 6], true),
+                   "0000: + 31 16  0  5  8  # LDA 2000\n");
+    }
+}
@@ -0,0 +1,270 @@
+// Portable MIX memory images.
+//
+// An image starts with a header, all multi-byte numbers big-endian:
+//   Bytes 0-3:   the magic number "MIXI"
+//   Byte 4:      format version, currently 1
+//   Byte 5:      the byte size of the machine, 64 or 100
+//   Byte 6:      flags; bit 0 is set if register state follows the header
+//   Byte 7:      reserved, zero
+//   Bytes 8-11:  the number of words of memory in the image
+//   Bytes 12-13: the start address
+// Then, if present, the registers: A, X, I1-I6 and J, the overflow toggle
+// (0 or 1) and the comparison indicator (0 less, 1 equal, 2 greater).
+// Then the memory words.
+//
+// Words are stored as six bytes, a sign (0 for +, 1 for -) followed by the
+// five MIX bytes, and addresses (the index registers and J) as three, a
+// sign and two bytes. So the layout doesn't depend on the host, nor on how
+// this implementation packs words.
+//
+// Files without the magic number are read as the legacy format: the raw
+// 4-byte words of a MixMemory, least significant byte first.
+
+use crate::arch_util;
+use crate::mix_types::*;
+use crate::mix_util;
+
+pub const IMAGE_MAGIC: &[u8; 4] = b"MIXI";
+pub const IMAGE_VERSION: u8 = 1;
+
+const HEADER_LEN: usize = 14;
+const FLAG_REGISTERS: u8 = 1;
+const WORD_LEN: usize = 6;
+const ADDR_LEN: usize = 3;
+
+// The register state saved with an image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageRegisters {
+    pub r: MixRegisters,
+    pub overflow: bool,
+    pub comparison: MixCompare,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MixImage {
+    pub byte_size: u8,
+    pub memory: Vec<MixWord>,
+    pub start: MixAddr,
+    pub registers: Option<ImageRegisters>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageError {
+    pub message: String,
+}
+
+impl std::fmt::Display for ImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "Image error: {}", self.message)
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+fn error(message: String) -> ImageError {
+    ImageError { message }
+}
+
+// Check whether bytes start with the magic number of a portable image.
+pub fn is_image(bytes: &[u8]) -> bool {
+    bytes.starts_with(IMAGE_MAGIC)
+}
+
+pub(crate) fn put_word(out: &mut Vec<u8>, w: MixWord) {
+    for b in 0 ..= 5 {
+        out.push(mix_util::get_bytes(w, b, b) as u8);
+    }
+}
+
+pub(crate) fn put_addr(out: &mut Vec<u8>, a: MixAddr) {
+    out.push(((a >> 12) & 1) as u8);
+    out.push(((a >> 6) as u8) & MIX_BYTE_MAX);
+    out.push((a as u8) & MIX_BYTE_MAX);
+}
+
+pub(crate) fn put_registers(out: &mut Vec<u8>, regs: &ImageRegisters) {
+    put_word(out, regs.r.a);
+    put_word(out, regs.r.x);
+    for i in &regs.r.i {
+        put_addr(out, *i);
+    }
+    put_addr(out, regs.r.j);
+    out.push(regs.overflow as u8);
+    out.push(match regs.comparison {
+        MixCompare::Less => 0,
+        MixCompare::Equal => 1,
+        MixCompare::Greater => 2,
+    });
+}
+
+// Write an image in the portable format.
+pub fn write_image(image: &MixImage) -> Vec<u8> {
+    let mut out: Vec<u8> = IMAGE_MAGIC.to_vec();
+    out.push(IMAGE_VERSION);
+    out.push(image.byte_size);
+    out.push(if image.registers.is_some() { FLAG_REGISTERS } else { 0 });
+    out.push(0);
+    out.extend(&(image.memory.len() as u32).to_be_bytes());
+    out.extend(&image.start.to_be_bytes());
+    if let Some(regs) = &image.registers {
+        put_registers(&mut out, regs);
+    }
+    for w in &image.memory {
+        put_word(&mut out, *w);
+    }
+    out
+}
+
+// Reads the parts of an image in order, checking that they're there and
+// that each byte fits the machine.
+pub(crate) struct Reader<'a> {
+    pub bytes: &'a [u8],
+    pub pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn take(&mut self, n: usize) -> Result<&'a [u8], ImageError> {
+        if self.pos + n > self.bytes.len() {
+            return Err(error(format!(
+                "Image is truncated at byte {}", self.bytes.len())));
+        }
+        let s = &self.bytes[self.pos .. self.pos + n];
+        self.pos += n;
+        Ok(s)
+    }
+
+    fn take_bytes(&mut self, n: usize) -> Result<&'a [u8], ImageError> {
+        let pos = self.pos;
+        let s = self.take(n)?;
+        if s[0] > 1 {
+            return Err(error(format!("Bad sign {} at byte {}", s[0], pos)));
+        }
+        if let Some(ix) = s[1 ..].iter().position(|b| *b > MIX_BYTE_MAX) {
+            return Err(error(format!("Byte {} at byte {} does not fit in MIX",
+                                     s[1 + ix], pos + 1 + ix)));
+        }
+        Ok(s)
+    }
+
+    pub fn word(&mut self) -> Result<MixWord, ImageError> {
+        let s = self.take_bytes(WORD_LEN)?;
+        Ok(mix_util::word_from_bytes(s[0], s[1], s[2], s[3], s[4], s[5]))
+    }
+
+    pub fn addr(&mut self) -> Result<MixAddr, ImageError> {
+        let s = self.take_bytes(ADDR_LEN)?;
+        Ok(((s[0] as MixAddr) << 12) | ((s[1] as MixAddr) << 6) | s[2] as MixAddr)
+    }
+
+    pub fn registers(&mut self) -> Result<ImageRegisters, ImageError> {
+        let a = self.word()?;
+        let x = self.word()?;
+        let mut i: [MixAddr; 6] = [0; 6];
+        for reg in i.iter_mut() {
+            *reg = self.addr()?;
+        }
+        let j = self.addr()?;
+        let tail = self.take(2)?;
+        let comparison = match tail[1] {
+            0 => MixCompare::Less,
+            1 => MixCompare::Equal,
+            2 => MixCompare::Greater,
+            c => return Err(error(format!("Bad comparison indicator {}", c))),
+        };
+        Ok(ImageRegisters {
+            r: MixRegisters { a, x, i, j },
+            overflow: tail[0] != 0,
+            comparison,
+        })
+    }
+}
+
+// Read an image, either in the portable format or in the legacy one.
+// A legacy image has no registers, and starts at address 0.
+pub fn read_image(bytes: &[u8]) -> Result<MixImage, ImageError> {
+    if !is_image(bytes) {
+        let chunks = bytes.chunks_exact(4);
+        if !chunks.remainder().is_empty() {
+            return Err(error(format!(
+                "Raw image of {} bytes is not a whole number of words",
+                bytes.len())));
+        }
+        let memory = chunks.map(arch_util::u8sto32).collect();
+        return Ok(MixImage { byte_size: 64, memory, start: 0, registers: None });
+    }
+
+    let mut rd = Reader { bytes, pos: 0 };
+    let header = rd.take(HEADER_LEN)?;
+    if header[4] != IMAGE_VERSION {
+        return Err(error(format!("Unsupported image version {}", header[4])));
+    }
+    let byte_size = header[5];
+    if byte_size != 64 {
+        return Err(error(format!(
+            "Image is for a machine with byte size {}; only 64 is supported",
+            byte_size)));
+    }
+    let flags = header[6];
+    let len = u32::from_be_bytes([header[8], header[9], header[10], header[11]])
+        as usize;
+    let start = u16::from_be_bytes([header[12], header[13]]);
+    if start as usize >= MEM_SIZE {
+        return Err(error(format!("Start address {} is outside memory", start)));
+    }
+
+    let registers = if flags & FLAG_REGISTERS != 0 {
+        Some(rd.registers()?)
+    } else {
+        None
+    };
+
+    if bytes.len() - rd.pos != len * WORD_LEN {
+        return Err(error(format!(
+            "Image should hold {} words, but has {} bytes of them",
+            len, bytes.len() - rd.pos)));
+    }
+    let mut memory = Vec::with_capacity(len);
+    for _ in 0 .. len {
+        memory.push(rd.word()?);
+    }
+    Ok(MixImage { byte_size, memory, start, registers })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_round_trip() {
+        let mut memory = vec![0; 10];
+        memory[3] = mix_util::to_mix_word(-123456);
+        memory[9] = mix_util::word_from_bytes(0, 63, 1, 2, 3, 4);
+        let image = MixImage {
+            byte_size: 64,
+            memory,
+            start: 3,
+            registers: Some(ImageRegisters {
+                r: MixRegisters {
+                    a: 1 << 30, x: 5, i: [1, 2, 3, 4, 5, 4095 | (1 << 12)], j: 7,
+                },
+                overflow: true,
+                comparison: MixCompare::Greater,
+            }),
+        };
+        let bytes = write_image(&image);
+        assert!(is_image(&bytes));
+        assert_eq!(&bytes[4 .. 14], &[1, 64, 1, 0, 0, 0, 0, 10, 0, 3]);
+        assert_eq!(&bytes[bytes.len() - 6 ..], &[0, 63, 1, 2, 3, 4]);
+        assert_eq!(read_image(&bytes).unwrap(), image);
+
+        assert!(read_image(&bytes[.. bytes.len() - 1]).is_err());
+        let mut bad = bytes.clone();
+        bad[5] = 100;
+        assert!(read_image(&bad).is_err());
+
+        let mut raw = vec![];
+        raw.extend(arch_util::u32to8s(77).iter());
+        let legacy = read_image(&raw).unwrap();
+        assert_eq!((legacy.memory, legacy.start), (vec![77], 0));
+    }
+}
@@ -0,0 +1,284 @@
+// Input and output devices, as described in TAOCP 1.3.1:
+//   Unit   Device         Block size   Medium
+//   0-7    tape           100 words    words
+//   8-15   disk or drum   100 words    words
+//   16     card reader    16 words     text, input only
+//   17     card punch     16 words     text, output only
+//   18     line printer   24 words     text, output only
+//   19     typewriter     14 words     text
+//   20     paper tape     14 words     text, input only
+//
+// Devices here finish every operation at once. They are never busy, so
+// JBUS never jumps and JRED always does, and an operation takes no more
+// time than the instruction starting it.
+//
+// A device holds its medium as a list of blocks, and its position in it:
+// the next card, line or block. A disk is positioned by rX before each
+// transfer instead.
+//
+// A medium of text is a line per block, of five characters a word. Lines
+// read are padded with spaces to fill the block; lines written have
+// trailing spaces taken off. A page eject on the printer is written as a
+// line holding just a form feed. A medium of words is a file of words, six
+// bytes each as in a portable image (see mix_image), filling each block in
+// turn.
+
+use crate::mix_fmt;
+use crate::mix_image;
+use crate::mix_image::Reader;
+use crate::mix_types::*;
+use crate::mix_util;
+
+pub const UNIT_MAX: u8 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeviceKind {
+    Tape,
+    Disk,
+    CardReader,
+    CardPunch,
+    Printer,
+    Typewriter,
+    PaperTape,
+}
+
+impl DeviceKind {
+    // The kind of device on a unit, if the unit exists.
+    pub fn of_unit(unit: u8) -> Option<DeviceKind> {
+        match unit {
+            0 ..= 7 => Some(DeviceKind::Tape),
+            8 ..= 15 => Some(DeviceKind::Disk),
+            16 => Some(DeviceKind::CardReader),
+            17 => Some(DeviceKind::CardPunch),
+            18 => Some(DeviceKind::Printer),
+            19 => Some(DeviceKind::Typewriter),
+            20 => Some(DeviceKind::PaperTape),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DeviceKind::Tape => "tape",
+            DeviceKind::Disk => "disk",
+            DeviceKind::CardReader => "card reader",
+            DeviceKind::CardPunch => "card punch",
+            DeviceKind::Printer => "line printer",
+            DeviceKind::Typewriter => "typewriter",
+            DeviceKind::PaperTape => "paper tape",
+        }
+    }
+
+    pub fn block_size(self) -> usize {
+        match self {
+            DeviceKind::Tape | DeviceKind::Disk => 100,
+            DeviceKind::CardReader | DeviceKind::CardPunch => 16,
+            DeviceKind::Printer => 24,
+            DeviceKind::Typewriter | DeviceKind::PaperTape => 14,
+        }
+    }
+
+    pub fn can_input(self) -> bool {
+        !matches!(self, DeviceKind::CardPunch | DeviceKind::Printer)
+    }
+
+    pub fn can_output(self) -> bool {
+        !matches!(self, DeviceKind::CardReader | DeviceKind::PaperTape)
+    }
+
+    // Whether the medium is text, rather than words.
+    pub fn is_text(self) -> bool {
+        !matches!(self, DeviceKind::Tape | DeviceKind::Disk)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Device {
+    pub unit: u8,
+    pub kind: DeviceKind,
+    // The blocks of the medium, in order. An empty block on the printer is
+    // a page eject.
+    pub blocks: Vec<Vec<MixWord>>,
+    pub position: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IoError {
+    pub message: String,
+}
+
+impl std::fmt::Display for IoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for IoError {}
+
+fn error(message: String) -> IoError {
+    IoError { message }
+}
+
+// A device on a unit, with nothing on its medium.
+pub fn new_device(unit: u8) -> Result<Device, IoError> {
+    match DeviceKind::of_unit(unit) {
+        Some(kind) => Ok(Device { unit, kind, blocks: vec![], position: 0 }),
+        None => Err(error(format!("There is no unit {}; units are 0 to {}",
+                                  unit, UNIT_MAX))),
+    }
+}
+
+// Check that a file can be attached to a unit, for input or for output:
+// that the unit exists, and that its device can read, or write.
+pub fn check_attachment(unit: u8, output: bool) -> Result<DeviceKind, IoError> {
+    let kind = new_device(unit)?.kind;
+    if output && !kind.can_output() {
+        Err(error(format!("Unit {} ({}) cannot write", unit, kind.name())))
+    } else if !output && !kind.can_input() {
+        Err(error(format!("Unit {} ({}) cannot read", unit, kind.name())))
+    } else {
+        Ok(kind)
+    }
+}
+
+// Convert a line of text to a block, padding it with spaces.
+fn text_block(line: &str, size: usize) -> Result<Vec<MixWord>, String> {
+    let mut codes = vec![];
+    for c in line.chars() {
+        match mix_util::char_to_mix(c) {
+            Some(b) => codes.push(b),
+            None => return Err(format!("'{}' is not a MIX character", c)),
+        }
+    }
+    if codes.len() > size * 5 {
+        return Err(format!("The line is longer than {} characters", size * 5));
+    }
+    codes.resize(size * 5, 0);
+    Ok(codes.chunks(5).map(|b| mix_util::word_from_bytes(
+        0, b[0], b[1], b[2], b[3], b[4])).collect())
+}
+
+// A device on a unit, with the medium in the contents of a file.
+pub fn read_medium(unit: u8, bytes: &[u8]) -> Result<Device, IoError> {
+    let mut dev = new_device(unit)?;
+    let size = dev.kind.block_size();
+    if dev.kind.is_text() {
+        let text = std::str::from_utf8(bytes).map_err(|_| error(format!(
+            "The medium for unit {} ({}) is not text", unit, dev.kind.name())))?;
+        for (k, line) in text.lines().enumerate() {
+            let block = if line == "\u{c}" && dev.kind == DeviceKind::Printer {
+                vec![]
+            } else {
+                text_block(line, size).map_err(|e| error(format!(
+                    "Unit {} ({}), line {}: {}", unit, dev.kind.name(),
+                    k + 1, e)))?
+            };
+            dev.blocks.push(block);
+        }
+    } else {
+        let mut rd = Reader { bytes, pos: 0 };
+        let mut words = vec![];
+        while rd.pos < bytes.len() {
+            words.push(rd.word().map_err(|e| error(format!(
+                "Unit {} ({}): {}", unit, dev.kind.name(), e.message)))?);
+        }
+        for chunk in words.chunks(size) {
+            let mut block = chunk.to_vec();
+            block.resize(size, 0);
+            dev.blocks.push(block);
+        }
+    }
+    Ok(dev)
+}
+
+// The contents of a file holding the medium of a device.
+pub fn write_medium(dev: &Device) -> Vec<u8> {
+    let mut out = vec![];
+    for block in &dev.blocks {
+        if dev.kind.is_text() {
+            let line: String = if block.is_empty() {
+                "\u{c}".to_string()
+            } else {
+                block.iter().map(|w| mix_fmt::fmt_text(*w)).collect()
+            };
+            out.extend(line.trim_end_matches(' ').as_bytes());
+            out.push(b'\n');
+        } else {
+            for w in block {
+                mix_image::put_word(&mut out, *w);
+            }
+        }
+    }
+    out
+}
+
+pub fn find_device(io: &mut [Device], unit: u8) -> Option<&mut Device> {
+    io.iter_mut().find(|d| d.unit == unit)
+}
+
+// Attach a device to a machine, replacing any already on its unit.
+pub fn attach(st: &mut MixState, dev: Device) {
+    st.io.retain(|d| d.unit != dev.unit);
+    st.io.push(dev);
+    st.io.sort_by_key(|d| d.unit);
+}
+
+// The block to transfer next: for a disk, the one given by rX, and
+// otherwise the one at the device's position.
+pub fn next_block(dev: &Device, x: MixWord) -> usize {
+    match dev.kind {
+        DeviceKind::Disk => mix_util::get_bytes(x, 4, 5) as usize,
+        _ => dev.position,
+    }
+}
+
+// Write a block to a device at an index, adding empty blocks to reach it
+// if need be. Return what it replaced, if anything.
+pub fn put_block(dev: &mut Device, ix: usize, block: Vec<MixWord>)
+    -> Option<Vec<MixWord>> {
+    if ix < dev.blocks.len() {
+        return Some(std::mem::replace(&mut dev.blocks[ix], block));
+    }
+    let size = dev.kind.block_size();
+    dev.blocks.resize(ix, vec![0; size]);
+    dev.blocks.push(block);
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_media() {
+        let cards = read_medium(16, b"HELLO, WORLD\n\n").unwrap();
+        assert_eq!((cards.kind, cards.blocks.len()), (DeviceKind::CardReader, 2));
+        assert_eq!(cards.blocks[0].len(), 16);
+        assert_eq!(cards.blocks[0][0], mix_util::word_from_bytes(0, 8, 5, 13, 13, 16));
+        assert_eq!(cards.blocks[1], vec![0; 16]);
+        assert!(read_medium(16, b"hello\n").is_err());
+        assert!(read_medium(16, "X".repeat(81).as_bytes()).is_err());
+        assert!(read_medium(21, b"").is_err());
+
+        let mut printer = new_device(18).unwrap();
+        put_block(&mut printer, 0, cards.blocks[0][.. 3].to_vec());
+        put_block(&mut printer, 1, vec![]);
+        assert_eq!(write_medium(&printer), b"HELLO, WORLD\n\x0c\n".to_vec());
+        assert_eq!(read_medium(18, &write_medium(&printer)).unwrap().blocks[1], vec![]);
+
+        let mut disk = new_device(8).unwrap();
+        assert_eq!(put_block(&mut disk, 2, vec![7; 100]), None);
+        assert_eq!(put_block(&mut disk, 0, vec![1; 100]), Some(vec![0; 100]));
+        let bytes = write_medium(&disk);
+        assert_eq!(bytes.len(), 3 * 100 * 6);
+        assert_eq!(read_medium(8, &bytes).unwrap(), disk);
+        assert_eq!(next_block(&disk, 2), 2);
+
+        assert_eq!(check_attachment(16, false), Ok(DeviceKind::CardReader));
+        assert_eq!(check_attachment(16, true).unwrap_err().message,
+                   "Unit 16 (card reader) cannot write");
+        assert_eq!(check_attachment(18, false).unwrap_err().message,
+                   "Unit 18 (line printer) cannot read");
+        assert!(check_attachment(19, true).is_ok() && check_attachment(21, false).is_err());
+    }
+}
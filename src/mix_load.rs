@@ -0,0 +1,365 @@
+// Loading programs into a machine, whatever form they come in.
+//
+// A file is read as:
+//   a portable image, if it starts with the image magic number (see
+//     mix_image);
+//   a GNU MDK program, if it starts with MDK's signature, compressed or not
+//     (see mix_mdk);
+//   MIXAL source, if it's text and any line has an instruction or
+//     directive in its OP column, and it's assembled on the fly;
+//   a text dump of words (see mix_parse::parse_memory), if it's other text;
+//   a legacy raw image, if it isn't text.
+
+use std::collections::HashMap;
+use crate::mix_types::*;
+use crate::mix_core;
+use crate::mix_image;
+use crate::mix_image::{ImageRegisters, MixImage};
+use crate::mix_mdk;
+use crate::mix_parse;
+use crate::mixal_asm;
+use crate::mixal_parse;
+use crate::mixal_parse::Label;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProgramFormat {
+    Image,
+    Mdk,
+    Raw,
+    Dump,
+    Mixal,
+}
+
+impl ProgramFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            ProgramFormat::Image => "image",
+            ProgramFormat::Mdk => "MDK program",
+            ProgramFormat::Raw => "raw image",
+            ProgramFormat::Dump => "dump",
+            ProgramFormat::Mixal => "MIXAL source",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub format: ProgramFormat,
+    // Memory from address 0, with the start address and any registers.
+    pub image: MixImage,
+    // The number of words the file gave.
+    pub size: usize,
+    // Symbols defined by MIXAL source, and their values.
+    pub symbols: HashMap<String, i32>,
+    // Where each word came from, for a program assembled from source.
+    pub debug: Option<DebugInfo>,
+}
+
+// The line of source a word of memory was assembled from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLoc {
+    pub line: usize,
+    // The label of the line, if it has one.
+    pub label: Option<String>,
+    // Whether the word is data, from CON, ALF or the constant pool.
+    pub data: bool,
+}
+
+// Debugging information for a program assembled from MIXAL source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugInfo {
+    pub file: String,
+    // The lines of the source file.
+    pub lines: Vec<String>,
+    // The source of each word of memory, by address, or None where nothing
+    // was assembled.
+    pub locs: Vec<Option<SourceLoc>>,
+}
+
+impl DebugInfo {
+    pub fn loc(&self, addr: MixAddr) -> Option<&SourceLoc> {
+        self.locs.get(addr as usize).and_then(|l| l.as_ref())
+    }
+
+    pub fn is_data(&self, addr: MixAddr) -> bool {
+        matches!(self.loc(addr), Some(l) if l.data)
+    }
+
+    // Whether a file name names the source file: either the name it was
+    // loaded by, or the last component of that.
+    pub fn is_file(&self, name: &str) -> bool {
+        let base = std::path::Path::new(&self.file).file_name()
+            .and_then(|f| f.to_str());
+        name == self.file || Some(name) == base
+    }
+
+    // The address of the first instruction assembled from a line or, if
+    // there's none, from the nearest line after it that has one.
+    pub fn line_addr(&self, line: usize) -> Option<MixAddr> {
+        let code = self.locs.iter().enumerate().filter_map(|(addr, l)| match l {
+            Some(l) if !l.data && l.line >= line => Some((l.line, addr)),
+            _ => None,
+        });
+        code.min().map(|(_, addr)| addr as MixAddr)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadError {
+    pub message: String,
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+fn error(message: String) -> LoadError {
+    LoadError { message }
+}
+
+// Check whether text looks like MIXAL: whether any line, other than a
+// comment, has a word starting with a letter in its OP column. The tokens
+// of a dump are numbers, signs, locations and quoted text, so never do,
+// once its # comments are taken off.
+fn is_mixal(text: &str) -> bool {
+    text.lines().any(|line| {
+        if line.starts_with('*') {
+            return false;
+        }
+        let line = line.split('#').next().unwrap_or("");
+        let mut words = line.split_whitespace();
+        let op = if line.starts_with(char::is_whitespace) {
+            words.next()
+        } else {
+            words.nth(1)
+        };
+        match op {
+            Some(op) => op.starts_with(|c: char| c.is_ascii_alphabetic()) &&
+                op.chars().all(|c| c.is_ascii_alphanumeric()),
+            None => false,
+        }
+    })
+}
+
+// Work out what format a file is in.
+pub fn detect_format(bytes: &[u8]) -> ProgramFormat {
+    if mix_image::is_image(bytes) {
+        return ProgramFormat::Image;
+    }
+    if mix_mdk::is_mdk(bytes) {
+        return ProgramFormat::Mdk;
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(t) if t.contains('\0') => ProgramFormat::Raw,
+        Ok(t) if is_mixal(t) => ProgramFormat::Mixal,
+        Ok(_) => ProgramFormat::Dump,
+        Err(_) => ProgramFormat::Raw,
+    }
+}
+
+fn debug_info(file: &str, src: &str, prog: &mixal_asm::MixalProgram)
+    -> DebugInfo {
+    let lines: Vec<String> = src.lines().map(|l| l.to_string()).collect();
+    // The source assembled, so it parses.
+    let stmts = mixal_parse::parse_program(src).unwrap_or_default();
+    let mut labels: Vec<Option<String>> = vec![None; lines.len() + 1];
+    for s in stmts {
+        labels[s.line] = match s.label {
+            Some(Label::Symbol(name)) => Some(name),
+            Some(Label::Local(d)) => Some(format!("{}H", d)),
+            None => None,
+        };
+    }
+    let mut locs = vec![None; MEM_SIZE];
+    for w in &prog.words {
+        locs[w.addr as usize] = Some(SourceLoc {
+            line: w.line,
+            label: labels[w.line].clone(),
+            data: w.data,
+        });
+    }
+    DebugInfo { file: file.to_string(), lines, locs }
+}
+
+// Read a program from the contents of a file, in any of the formats. The
+// name of the file goes in the debugging information of MIXAL source.
+pub fn read_program(file: &str, bytes: &[u8]) -> Result<Program, LoadError> {
+    let format = detect_format(bytes);
+    let text = std::str::from_utf8(bytes).unwrap_or("");
+    let (image, size, symbols, debug) = match format {
+        ProgramFormat::Image | ProgramFormat::Raw => {
+            let image = mix_image::read_image(bytes)
+                .map_err(|e| error(e.to_string()))?;
+            let size = image.memory.len();
+            (image, size, HashMap::new(), None)
+        },
+        ProgramFormat::Mdk => {
+            let image = mix_mdk::read_mdk(bytes)
+                .map_err(|e| error(e.to_string()))?;
+            let size = image.memory.len();
+            (image, size, HashMap::new(), None)
+        },
+        ProgramFormat::Dump => {
+            let memory = mix_parse::parse_memory(text).map_err(|errs| {
+                let msgs: Vec<String> = errs.iter().map(|e| e.to_string())
+                    .collect();
+                error(msgs.join("\n"))
+            })?;
+            let size = memory.len();
+            (MixImage { byte_size: 64, memory, start: 0, registers: None },
+             size, HashMap::new(), None)
+        },
+        ProgramFormat::Mixal => {
+            let prog = mixal_asm::assemble(text)
+                .map_err(|e| error(format!("Assembly error on {}", e)))?;
+            let symbols = prog.symbols.iter()
+                .map(|s| (s.name.clone(), s.value)).collect();
+            (MixImage {
+                byte_size: 64,
+                memory: prog.memory.to_vec(),
+                start: prog.start,
+                registers: None,
+            }, prog.words.len(), symbols, Some(debug_info(file, text, &prog)))
+        },
+    };
+    if image.memory.len() > MEM_SIZE {
+        return Err(error(format!("{} words do not fit in memory",
+                                 image.memory.len())));
+    }
+    Ok(Program { format, image, size, symbols, debug })
+}
+
+// Load an image into a machine. Without an address, this replaces the
+// machine: memory, registers and flags are cleared and then set from the
+// image, the program counter is set to its start and the counters are
+// reset, as are the record of subroutine calls and the history if it's
+// being recorded. Breakpoints and watchpoints are kept. With an address, the words
+// of the image are copied into memory from there, and nothing else changes.
+pub fn load_image(st: &mut MixState, image: &MixImage, at: Option<MixAddr>)
+    -> Result<(), LoadError> {
+    let words = &image.memory;
+    let base = match at {
+        Some(a) => a as usize,
+        None => 0,
+    };
+    if base + words.len() > MEM_SIZE {
+        return Err(error(format!("{} words at {:04} do not fit in memory",
+                                 words.len(), base)));
+    }
+    if at.is_none() {
+        let regs = image.registers.clone().unwrap_or(ImageRegisters {
+            r: MixRegisters { a: 0, x: 0, i: [0; 6], j: 0 },
+            overflow: false,
+            comparison: MixCompare::Equal,
+        });
+        *st.memory = [0; MEM_SIZE];
+        st.r = regs.r;
+        st.overflow = regs.overflow;
+        st.comparison = regs.comparison;
+        st.pc = image.start;
+        st.is_running = false;
+        st.panic_msg = None;
+        st.cycles = 0;
+        st.breakpoint_hit = None;
+        st.watch_hit = None;
+        st.calls.clear();
+        st.instructions = 0;
+    }
+    st.memory[base .. base + words.len()].copy_from_slice(words);
+    // The history starts from the loaded program.
+    if at.is_none() && st.history.is_some() {
+        mix_core::start_recording(st);
+    }
+    Ok(())
+}
+
+// Save the memory, registers and program counter of a machine as an image,
+// which load_image can later restore.
+pub fn save_image(st: &MixState) -> MixImage {
+    MixImage {
+        byte_size: 64,
+        memory: st.memory.to_vec(),
+        start: st.pc,
+        registers: Some(ImageRegisters {
+            r: st.r.clone(),
+            overflow: st.overflow,
+            comparison: st.comparison.clone(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mix_util;
+
+    #[test]
+    fn test_read_and_load_program() {
+        let src = "* A COMMENT\nX EQU 1000\n ORIG 3000\nSTART LDA X\n HLT\n END START\n";
+        assert_eq!(detect_format(src.as_bytes()), ProgramFormat::Mixal);
+        assert_eq!(detect_format(b"3000: + 1000 0 5 8\n# LDA\n"),
+                   ProgramFormat::Dump);
+        assert_eq!(detect_format(&[1, 0, 0, 0]), ProgramFormat::Raw);
+
+        let prog = read_program("m.mixal", src.as_bytes()).unwrap();
+        assert_eq!((prog.size, prog.image.start), (2, 3000));
+        assert_eq!(prog.symbols.get("X"), Some(&1000));
+        assert_eq!(prog.symbols.get("START"), Some(&3000));
+        let debug = prog.debug.as_ref().unwrap();
+        assert_eq!(debug.loc(3000), Some(&SourceLoc {
+            line: 4, label: Some("START".to_string()), data: false,
+        }));
+        assert_eq!(debug.loc(2999), None);
+        assert_eq!((debug.line_addr(1), debug.line_addr(5), debug.line_addr(6)),
+                   (Some(3000), Some(3001), None));
+        assert!(debug.is_file("m.mixal") && !debug.is_file("p.mixal"));
+
+        let mut mem: MixMemory = [0; MEM_SIZE];
+        let mut st = MixState::new(&mut mem);
+        st.r.a = 5;
+        st.cycles = 7;
+        load_image(&mut st, &prog.image, None).unwrap();
+        assert_eq!((st.r.a, st.cycles, st.pc), (0, 0, 3000));
+        assert_eq!(st.memory[3000], prog.image.memory[3000]);
+        let saved = save_image(&st);
+
+        let mdk = read_program("p.mix", &mix_mdk::write_mdk(&saved)).unwrap();
+        assert_eq!(mdk.format, ProgramFormat::Mdk);
+        assert_eq!((mdk.size, mdk.image.start), (3002, 3000));
+        assert_eq!(mdk.image.memory[..], saved.memory[.. 3002]);
+
+        let dump = read_program("d", b"+ 1 2 3 4 5\n-7\n").unwrap();
+        assert_eq!(dump.format, ProgramFormat::Dump);
+        load_image(&mut st, &dump.image, Some(100)).unwrap();
+        assert_eq!(st.memory[101], mix_util::to_mix_word(-7));
+        assert_eq!(st.pc, 3000);
+        assert!(load_image(&mut st, &dump.image, Some(3999)).is_err());
+
+        load_image(&mut st, &saved, None).unwrap();
+        assert_eq!(st.memory[101], 0);
+
+        assert!(read_program("d", b" LDA UNDEFINED(9:9)\n END 0\n").is_err());
+        assert!(read_program("d", b"+ 1 2\n").is_err());
+    }
+
+    #[test]
+    fn test_load_while_recording() {
+        let src = " ORIG 1000\nSTART INCA 1\n JMP START\n END START\n";
+        let prog = read_program("m.mixal", src.as_bytes()).unwrap();
+        let mut mem: MixMemory = [0; MEM_SIZE];
+        let mut st = MixState::new(&mut mem);
+        mix_core::start_recording(&mut st);
+        load_image(&mut st, &prog.image, None).unwrap();
+
+        // Back past where the undo log reaches, so from the first checkpoint,
+        // which must hold the program.
+        mix_core::goto_instruction(&mut st, 250_000).unwrap();
+        mix_core::goto_instruction(&mut st, 5000).unwrap();
+        assert_eq!((st.pc, st.r.a), (1000, 2500));
+        assert_eq!(st.memory[1000], prog.image.memory[1000]);
+    }
+}
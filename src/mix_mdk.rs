@@ -0,0 +1,185 @@
+// GNU MDK's compiled programs: the .mix files written by mixasm and run by
+// mixvm.
+//
+// A file is gzip-compressed, and holds numbers least significant byte
+// first, as MDK writes them on the machines it's built for:
+//   Bytes 0-3:   the signature 0xDEADBEEF
+//   Bytes 4-7:   the major version of the format, 1
+//   Bytes 8-11:  the minor version, 2
+//   Bytes 12-13: the start address
+//   Bytes 14-15: padding
+//   Bytes 16-23: the length of the name of the source file, which follows
+// Then the code, as 32-bit records. A record with bit 31 clear is a word,
+// packed as a MixWord is, which is stored at the current address; the
+// address then moves on by one. A record with bit 31 set moves the current
+// address to its low 12 bits.
+//
+// Only programs assembled without debugging information (without mixasm's
+// -g) are read. Uncompressed files are read too, as mixvm does.
+//
+// This layout was worked out without mixasm at hand. It is only checked by
+// reading back what write_mdk writes, not against files mixasm wrote, so a
+// file from mixasm may be rejected or misread.
+
+use std::io::prelude::*;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use crate::arch_util;
+use crate::mix_image::MixImage;
+use crate::mix_types::*;
+
+pub const MDK_SIGNATURE: u32 = 0xDEAD_BEEF;
+pub const MDK_VERSION: (u32, u32) = (1, 2);
+
+const GZIP_MAGIC: &[u8; 2] = b"\x1f\x8b";
+const HEADER_LEN: usize = 24;
+const ADDRESS_TAG: u32 = 1 << 31;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MdkError {
+    pub message: String,
+}
+
+impl std::fmt::Display for MdkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "MDK error: {}", self.message)
+    }
+}
+
+impl std::error::Error for MdkError {}
+
+fn error(message: String) -> MdkError {
+    MdkError { message }
+}
+
+// The contents of a file, uncompressed if it's compressed.
+fn uncompress(bytes: &[u8]) -> Result<Vec<u8>, MdkError> {
+    if !bytes.starts_with(GZIP_MAGIC) {
+        return Ok(bytes.to_vec());
+    }
+    let mut data = vec![];
+    GzDecoder::new(bytes).read_to_end(&mut data)
+        .map_err(|e| error(format!("Bad compressed data: {}", e)))?;
+    Ok(data)
+}
+
+// Check whether bytes are an MDK program. No raw image starts with the
+// signature, since bit 31 of a word is never set.
+pub fn is_mdk(bytes: &[u8]) -> bool {
+    match uncompress(bytes) {
+        Ok(data) => data.starts_with(&arch_util::u32to8s(MDK_SIGNATURE)),
+        Err(_) => false,
+    }
+}
+
+// Read an MDK program. It has no registers, and memory runs up to the last
+// word it stores.
+pub fn read_mdk(bytes: &[u8]) -> Result<MixImage, MdkError> {
+    let data = uncompress(bytes)?;
+    if data.len() < HEADER_LEN {
+        return Err(error(format!(
+            "Header is truncated at byte {}", data.len())));
+    }
+    let num = |pos: usize| arch_util::u8sto32(&data[pos .. pos + 4]);
+    if num(0) != MDK_SIGNATURE {
+        return Err(error("Not an MDK program".to_string()));
+    }
+    if num(4) != MDK_VERSION.0 {
+        return Err(error(format!("Unsupported version {}.{}", num(4), num(8))));
+    }
+    let start = num(12) as u16;
+    if start as usize >= MEM_SIZE {
+        return Err(error(format!("Start address {} is outside memory", start)));
+    }
+    let name_len = num(16) as usize | (num(20) as usize) << 32;
+    let code = match data.get(HEADER_LEN + name_len ..) {
+        Some(code) => code,
+        None => return Err(error(format!(
+            "File name of {} bytes is truncated", name_len))),
+    };
+    let records = code.chunks_exact(4);
+    if !records.remainder().is_empty() {
+        return Err(error(format!(
+            "Code of {} bytes is not a whole number of records", code.len())));
+    }
+
+    let mut memory = vec![];
+    let mut addr = 0;
+    for r in records.map(arch_util::u8sto32) {
+        if r & ADDRESS_TAG != 0 {
+            addr = (r & 0xfff) as usize;
+            continue;
+        }
+        if addr >= MEM_SIZE {
+            return Err(error(format!("Word at {} is outside memory", addr)));
+        }
+        if memory.len() <= addr {
+            memory.resize(addr + 1, 0);
+        }
+        memory[addr] = r;
+        addr += 1;
+    }
+    Ok(MixImage { byte_size: 64, memory, start, registers: None })
+}
+
+// Write an image as an MDK program, leaving out runs of zero words. The
+// registers of the image, if any, are lost.
+pub fn write_mdk(image: &MixImage) -> Vec<u8> {
+    let mut data = vec![];
+    for n in &[MDK_SIGNATURE, MDK_VERSION.0, MDK_VERSION.1, image.start as u32,
+               0, 0] {
+        data.extend(&arch_util::u32to8s(*n));
+    }
+    let mut next: Option<usize> = None;
+    for (addr, w) in image.memory.iter().enumerate() {
+        if *w == 0 {
+            continue;
+        }
+        if next != Some(addr) {
+            data.extend(&arch_util::u32to8s(ADDRESS_TAG | addr as u32));
+        }
+        data.extend(&arch_util::u32to8s(*w));
+        next = Some(addr + 1);
+    }
+    // Compressing into memory can't fail.
+    let mut gz = GzEncoder::new(vec![], Compression::default());
+    gz.write_all(&data).expect("Failed to compress MDK program");
+    gz.finish().expect("Failed to compress MDK program")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mix_util;
+
+    #[test]
+    fn test_read_and_write_mdk() {
+        let mut memory = vec![0; 3003];
+        memory[0] = 5;
+        memory[3000] = mix_util::word_from_bytes(0, 31, 16, 2, 11, 8);
+        memory[3001] = 1 << 30;
+        memory[3002] = mix_util::to_mix_word(-7);
+        let image = MixImage { byte_size: 64, memory, start: 3000, registers: None };
+        let bytes = write_mdk(&image);
+        assert!(bytes.starts_with(GZIP_MAGIC) && is_mdk(&bytes));
+        assert_eq!(read_mdk(&bytes), Ok(image.clone()));
+
+        // Two address records and four words follow the header.
+        let data = uncompress(&bytes).unwrap();
+        assert_eq!(data.len(), HEADER_LEN + 6 * 4);
+        assert_eq!(&data[HEADER_LEN + 8 .. HEADER_LEN + 12],
+                   &arch_util::u32to8s(ADDRESS_TAG | 3000));
+        assert!(is_mdk(&data));
+        assert_eq!(read_mdk(&data), Ok(image));
+
+        assert!(!is_mdk(&[0; 4]) && !is_mdk(b"\x1f\x8bnot gzip"));
+        assert!(read_mdk(&data[.. 20]).is_err());
+        assert!(read_mdk(&data[.. data.len() - 1]).is_err());
+        let mut far = data.clone();
+        far.extend(&arch_util::u32to8s(ADDRESS_TAG | 3999));
+        far.extend(&[1, 0, 0, 0, 2, 0, 0, 0]);
+        assert_eq!(read_mdk(&far), Err(error("Word at 4000 is outside memory"
+                                             .to_string())));
+    }
+}
@@ -0,0 +1,143 @@
+use crate::mix_types::*;
+
+// How an operation uses the F field of an instruction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldUse {
+    // A field specification (L:R) of the memory word.
+    Field,
+    // A plain number: a device unit, or the word count of MOVE.
+    Number,
+    // Part of the operation; it picks between operations with the same C.
+    Variant,
+}
+
+// A MIX operation as written in MIXAL: its mnemonic, the opcode (C field)
+// and the default F field used when an instruction doesn't give one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MixOp {
+    pub name: &'static str,
+    pub c: MixByte,
+    pub f: MixByte,
+    pub field_use: FieldUse,
+}
+
+const fn field(name: &'static str, c: MixByte, f: MixByte) -> MixOp {
+    MixOp { name, c, f, field_use: FieldUse::Field }
+}
+
+const fn number(name: &'static str, c: MixByte, f: MixByte) -> MixOp {
+    MixOp { name, c, f, field_use: FieldUse::Number }
+}
+
+const fn variant(name: &'static str, c: MixByte, f: MixByte) -> MixOp {
+    MixOp { name, c, f, field_use: FieldUse::Variant }
+}
+
+// Every operation from the MIX instruction table in TAOCP 1.3.1.
+pub const MIX_OPS: &[MixOp] = &[
+    number("NOP", 0, 0),
+    field("ADD", 1, 5), variant("FADD", 1, 6),
+    field("SUB", 2, 5), variant("FSUB", 2, 6),
+    field("MUL", 3, 5), variant("FMUL", 3, 6),
+    field("DIV", 4, 5), variant("FDIV", 4, 6),
+    variant("NUM", 5, 0), variant("CHAR", 5, 1), variant("HLT", 5, 2),
+    variant("SLA", 6, 0), variant("SRA", 6, 1),
+    variant("SLAX", 6, 2), variant("SRAX", 6, 3),
+    variant("SLC", 6, 4), variant("SRC", 6, 5),
+    number("MOVE", 7, 1),
+    field("LDA", 8, 5),
+    field("LD1", 9, 5), field("LD2", 10, 5), field("LD3", 11, 5),
+    field("LD4", 12, 5), field("LD5", 13, 5), field("LD6", 14, 5),
+    field("LDX", 15, 5),
+    field("LDAN", 16, 5),
+    field("LD1N", 17, 5), field("LD2N", 18, 5), field("LD3N", 19, 5),
+    field("LD4N", 20, 5), field("LD5N", 21, 5), field("LD6N", 22, 5),
+    field("LDXN", 23, 5),
+    field("STA", 24, 5),
+    field("ST1", 25, 5), field("ST2", 26, 5), field("ST3", 27, 5),
+    field("ST4", 28, 5), field("ST5", 29, 5), field("ST6", 30, 5),
+    field("STX", 31, 5),
+    field("STJ", 32, 2),
+    field("STZ", 33, 5),
+    number("JBUS", 34, 0), number("IOC", 35, 0),
+    number("IN", 36, 0), number("OUT", 37, 0),
+    number("JRED", 38, 0),
+    variant("JMP", 39, 0), variant("JSJ", 39, 1),
+    variant("JOV", 39, 2), variant("JNOV", 39, 3),
+    variant("JL", 39, 4), variant("JE", 39, 5), variant("JG", 39, 6),
+    variant("JGE", 39, 7), variant("JNE", 39, 8), variant("JLE", 39, 9),
+    variant("JAN", 40, 0), variant("JAZ", 40, 1), variant("JAP", 40, 2),
+    variant("JANN", 40, 3), variant("JANZ", 40, 4), variant("JANP", 40, 5),
+    variant("J1N", 41, 0), variant("J1Z", 41, 1), variant("J1P", 41, 2),
+    variant("J1NN", 41, 3), variant("J1NZ", 41, 4), variant("J1NP", 41, 5),
+    variant("J2N", 42, 0), variant("J2Z", 42, 1), variant("J2P", 42, 2),
+    variant("J2NN", 42, 3), variant("J2NZ", 42, 4), variant("J2NP", 42, 5),
+    variant("J3N", 43, 0), variant("J3Z", 43, 1), variant("J3P", 43, 2),
+    variant("J3NN", 43, 3), variant("J3NZ", 43, 4), variant("J3NP", 43, 5),
+    variant("J4N", 44, 0), variant("J4Z", 44, 1), variant("J4P", 44, 2),
+    variant("J4NN", 44, 3), variant("J4NZ", 44, 4), variant("J4NP", 44, 5),
+    variant("J5N", 45, 0), variant("J5Z", 45, 1), variant("J5P", 45, 2),
+    variant("J5NN", 45, 3), variant("J5NZ", 45, 4), variant("J5NP", 45, 5),
+    variant("J6N", 46, 0), variant("J6Z", 46, 1), variant("J6P", 46, 2),
+    variant("J6NN", 46, 3), variant("J6NZ", 46, 4), variant("J6NP", 46, 5),
+    variant("JXN", 47, 0), variant("JXZ", 47, 1), variant("JXP", 47, 2),
+    variant("JXNN", 47, 3), variant("JXNZ", 47, 4), variant("JXNP", 47, 5),
+    variant("INCA", 48, 0), variant("DECA", 48, 1),
+    variant("ENTA", 48, 2), variant("ENNA", 48, 3),
+    variant("INC1", 49, 0), variant("DEC1", 49, 1),
+    variant("ENT1", 49, 2), variant("ENN1", 49, 3),
+    variant("INC2", 50, 0), variant("DEC2", 50, 1),
+    variant("ENT2", 50, 2), variant("ENN2", 50, 3),
+    variant("INC3", 51, 0), variant("DEC3", 51, 1),
+    variant("ENT3", 51, 2), variant("ENN3", 51, 3),
+    variant("INC4", 52, 0), variant("DEC4", 52, 1),
+    variant("ENT4", 52, 2), variant("ENN4", 52, 3),
+    variant("INC5", 53, 0), variant("DEC5", 53, 1),
+    variant("ENT5", 53, 2), variant("ENN5", 53, 3),
+    variant("INC6", 54, 0), variant("DEC6", 54, 1),
+    variant("ENT6", 54, 2), variant("ENN6", 54, 3),
+    variant("INCX", 55, 0), variant("DECX", 55, 1),
+    variant("ENTX", 55, 2), variant("ENNX", 55, 3),
+    field("CMPA", 56, 5), variant("FCMP", 56, 6),
+    field("CMP1", 57, 5), field("CMP2", 58, 5), field("CMP3", 59, 5),
+    field("CMP4", 60, 5), field("CMP5", 61, 5), field("CMP6", 62, 5),
+    field("CMPX", 63, 5),
+];
+
+// Find an operation by its MIXAL mnemonic.
+pub fn lookup_op(name: &str) -> Option<&'static MixOp> {
+    MIX_OPS.iter().find(|o| o.name == name)
+}
+
+// Find the operation an instruction with the given C and F fields performs.
+// Returns None if there isn't one, or if F isn't valid for it.
+pub fn decode_op(c: MixByte, f: MixByte) -> Option<&'static MixOp> {
+    if let Some(o) = MIX_OPS.iter().find(|o| o.c == c && o.f == f &&
+                                         o.field_use == FieldUse::Variant) {
+        return Some(o);
+    }
+    MIX_OPS.iter().find(|o| o.c == c && match o.field_use {
+        FieldUse::Field => f / 8 <= f % 8 && f % 8 <= 5,
+        FieldUse::Number => true,
+        FieldUse::Variant => false,
+    })
+}
+
+// The execution time of an instruction, in units of u, from the table in
+// TAOCP 1.3.1. Waiting for I/O devices isn't included.
+pub fn op_time(c: MixByte, f: MixByte) -> u64 {
+    match (c, f) {
+        (1, 6) | (2, 6) | (56, 6) => 4,
+        (3, 6) => 9,
+        (4, 6) => 11,
+        (1, _) | (2, _) => 2,
+        (3, _) => 10,
+        (4, _) => 12,
+        (5, _) => 10,
+        (6, _) => 2,
+        (7, _) => 1 + 2 * f as u64,
+        (8 ..= 33, _) => 2,
+        (56 ..= 63, _) => 2,
+        _ => 1,
+    }
+}
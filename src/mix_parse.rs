@@ -3,95 +3,449 @@ use crate::mix_types::*;
 use crate::mix_util;
 use crate::arch_util;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+    TooFewTokens,
+    TooManyTokens,
+    BadSign,
+    BadByte,
+    ByteOutOfRange { value: u64 },
+    BadAddress,
+    AddressOutOfRange { value: u64 },
+    BadInteger,
+    IntegerOutOfRange { value: i64 },
+    BadText,
+    BadChar { c: char },
+    BadLocation,
+    LocationOutOfRange { value: u64 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct ParseError {
-    pub message: &'static str,
+    pub kind: ParseErrorKind,
+    // The line the error occurred on, counting from 1. Zero if unknown.
+    pub line: usize,
+    // The column (in chars) where the offending token starts, counting
+    // from 1. Zero if the error isn't about a particular token.
+    pub column: usize,
+    // The text of the offending token, if any.
+    pub token: String,
+}
+
+impl ParseError {
+    fn new(kind: ParseErrorKind, column: usize, token: &str) -> ParseError {
+        ParseError { kind, line: 0, column, token: token.to_string() }
+    }
+
+    // A description of the error, without its location.
+    pub fn message(&self) -> String {
+        match &self.kind {
+            ParseErrorKind::TooFewTokens =>
+                "Too few tokens! A word is a sign and 5 bytes, a sign and \
+                 4 packed fields, an integer or quoted text.".to_string(),
+            ParseErrorKind::TooManyTokens =>
+                "Too many tokens! A word is a sign and 5 bytes, a sign and \
+                 4 packed fields, an integer or quoted text.".to_string(),
+            ParseErrorKind::BadSign =>
+                format!("Invalid sign '{}'. Sign must be '+' or '-'.",
+                        self.token),
+            ParseErrorKind::BadByte =>
+                format!("Invalid byte '{}'. Byte must be a small \
+                         nonnegative number.", self.token),
+            ParseErrorKind::ByteOutOfRange { value } =>
+                format!("Byte {} does not fit into a MIX byte (0 to {}).",
+                        value, MIX_BYTE_MAX),
+            ParseErrorKind::BadAddress =>
+                format!("Invalid address '{}'. Address must be a \
+                         nonnegative number.", self.token),
+            ParseErrorKind::AddressOutOfRange { value } =>
+                format!("Address {} does not fit into two MIX bytes \
+                         (0 to {}).", value, MIX_ADDR_MAX),
+            ParseErrorKind::BadInteger =>
+                format!("Invalid integer '{}'.", self.token),
+            ParseErrorKind::IntegerOutOfRange { value } =>
+                format!("Integer {} does not fit into a MIX word.", value),
+            ParseErrorKind::BadText =>
+                format!("Invalid text {}. Text must be at most 5 characters \
+                         in double quotes.", self.token),
+            ParseErrorKind::BadChar { c } =>
+                format!("Invalid text {}. '{}' is not in the MIX \
+                         character set.", self.token, c),
+            ParseErrorKind::BadLocation =>
+                format!("Invalid location '{}'.", self.token),
+            ParseErrorKind::LocationOutOfRange { value } =>
+                format!("Location {} is outside memory (0 to {}).",
+                        value, MEM_SIZE - 1),
+        }
+    }
 }
 
 impl std::fmt::Display for ParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
-        write!(f, "Parsing error: {}", self.message)
+        if self.line == 0 {
+            write!(f, "Parsing error: {}", self.message())
+        } else if self.column == 0 {
+            write!(f, "Parsing error on line {}: {}", self.line, self.message())
+        } else {
+            write!(f, "Parsing error on line {}, column {}: {}",
+                   self.line, self.column, self.message())
+        }
     }
 }
 
-impl std::error::Error for ParseError {
-    fn description(&self) -> &str { &self.message }
-}
+impl std::error::Error for ParseError {}
 
 // Read in a MIX program as a
 // row-by-row string listing of MIX words and convert it to
-// a sequence of raw bytes (in little-endian word order). Parse
-// each word using the parse_word function below.
+// a sequence of raw bytes (in little-endian word order). The listing is
+// parsed with parse_memory below.
+// Return the error from the first line which fails to parse.
 
-pub fn parse_words(in_words: &String) -> Result<Vec<u8>, ParseError> {
+pub fn parse_words(in_words: &str) -> Result<Vec<u8>, ParseError> {
+    parse_words_all(in_words).map_err(|mut errs| errs.swap_remove(0))
+}
+
+// Like parse_words, but return every error in the listing.
+pub fn parse_words_all(in_words: &str) -> Result<Vec<u8>, Vec<ParseError>> {
+    let words = parse_memory(in_words)?;
     let mut bytes: Vec<u8> = vec![];
-    for s in in_words.lines() {
+    for w in words {
+        bytes.extend(arch_util::u32to8s(w).iter());
+    }
+    Ok(bytes)
+}
+
+// Read in a listing of MIX words, as produced by fmt_words or fmt_memory.
+// Each word is parsed with the parse_word function below. Beyond that:
+//   A line may start with a location and a colon, as in
+//     0100: + 1 2 3 4 5
+//   which places the word there. Lines without one follow the previous
+//   word, starting from location 0.
+//   Everything from a # to the end of the line is a comment.
+//   Blank lines and lines of "..." (a run of zero words) are skipped.
+// Locations skipped over are zero. The result ends with the last word
+// given.
+pub fn parse_memory(text: &str) -> Result<Vec<MixWord>, Vec<ParseError>> {
+    let mut words: Vec<MixWord> = vec![];
+    let mut errs: Vec<ParseError> = vec![];
+    let mut addr: usize = 0;
+    for (i, line) in text.lines().enumerate() {
+        let s = match line.find('#') {
+            Some(ix) => &line[.. ix],
+            None => line,
+        };
+        let trimmed = s.trim();
+        if trimmed.is_empty() || trimmed == "..." {
+            continue;
+        }
+        let (offset, s) = match parse_location(s) {
+            Ok(Some((a, offset))) => {
+                addr = a;
+                (offset, &s[offset ..])
+            },
+            Ok(None) => (0, s),
+            Err(e) => {
+                errs.push(ParseError { line: i + 1, ..e });
+                continue;
+            },
+        };
         match parse_word(s) {
             Ok(w) => {
-                bytes.extend(arch_util::u32to8s(w).iter());
+                if words.len() <= addr {
+                    words.resize(addr + 1, 0);
+                }
+                words[addr] = w;
+                addr += 1;
             },
             Err(e) => {
-                return Err(e);
+                let column = if e.column == 0 {
+                    0
+                } else {
+                    e.column + line[.. offset].chars().count()
+                };
+                errs.push(ParseError { line: i + 1, column, ..e });
             }
         }
     }
-    Ok(bytes)
+    if errs.is_empty() { Ok(words) } else { Err(errs) }
 }
 
-// Parse a line of the form returned by fmt_word() into a MixWord.
-// Return an error if the line cannot be parsed.
-// Lines should have the form
-// <sign> <b1> <b2> <b3> <b4> <b5>
-// for example
-// + 20 42 10 0 5
-// All bytes specified must be between 0 and MIX_BYTE_MAX.
+// Parse the location prefix of a line in an addressed dump, if it has one.
+// Return the location and the byte offset just past the colon.
+fn parse_location(s: &str) -> Result<Option<(usize, usize)>, ParseError> {
+    let trimmed = s.trim_start();
+    let digits = trimmed.find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(trimmed.len());
+    if digits == 0 || !trimmed[digits ..].starts_with(':') {
+        return Ok(None);
+    }
+    let column = s.chars().count() - trimmed.chars().count() + 1;
+    let t = &trimmed[.. digits];
+    match usize::from_str(t) {
+        Ok(a) if a < MEM_SIZE => {
+            Ok(Some((a, s.len() - trimmed.len() + digits + 1)))
+        },
+        Ok(a) => {
+            Err(ParseError::new(
+                ParseErrorKind::LocationOutOfRange { value: a as u64 },
+                column, t))
+        },
+        Err(_e) => {
+            Err(ParseError::new(ParseErrorKind::BadLocation, column, t))
+        },
+    }
+}
+
+// Split a line into whitespace separated tokens, with the column (in chars,
+// counting from 1) where each one starts.
+fn tokens(s: &str) -> Vec<(usize, &str)> {
+    let mut toks = vec![];
+    let mut start: Option<(usize, usize)> = None;
+    for (col, (ix, c)) in s.char_indices().enumerate() {
+        match (c.is_whitespace(), start) {
+            (true, Some((scol, six))) => {
+                toks.push((scol, &s[six .. ix]));
+                start = None;
+            },
+            (false, None) => start = Some((col + 1, ix)),
+            _ => {},
+        }
+    }
+    if let Some((scol, six)) = start {
+        toks.push((scol, &s[six ..]));
+    }
+    toks
+}
+
+// Parse a line describing a MixWord. Return an error if the line cannot be
+// parsed. Several notations are accepted:
+//
+// The form returned by fmt_word(), a sign and five bytes:
+//   <sign> <b1> <b2> <b3> <b4> <b5>
+//   for example + 20 42 10 0 5
+// A sign and four packed fields, laid out like an instruction, with the
+// first field (the address) spread across two bytes:
+//   <sign> <A> <I> <F> <C>
+//   for example + 2000 2 3 8
+// A signed decimal integer, with or without a space after the sign:
+//   -123456
+// Five characters of MIX text in double quotes, padded with blanks if
+// there are fewer than five:
+//   "HELLO"
+//
+// All bytes specified must be between 0 and MIX_BYTE_MAX, addresses must fit
+// in two bytes, and integers must fit in a word.
 
 pub fn parse_word(s: &str) -> Result<MixWord, ParseError> {
-    let sign: u8;
-    let mut bytes: [u8; 5] = [0; 5];
-    let toks: Vec<&str> = s.split_whitespace().collect();
-    if toks.len() < 6 {
-        return Err(ParseError {
-            message: "Too few tokens! MIX words have 5 bytes and a sign.",
-        });
+    let trimmed = s.trim_start();
+    if trimmed.starts_with('"') {
+        let column = s.chars().count() - trimmed.chars().count() + 1;
+        return parse_text(trimmed.trim_end(), column);
+    }
+    let toks = tokens(s);
+    if toks.len() == 1 {
+        return parse_integer(toks[0].1, toks[0].0);
+    }
+    if toks.is_empty() || (toks.len() > 2 && toks.len() < 5) {
+        return Err(ParseError::new(ParseErrorKind::TooFewTokens, 0, ""));
     } else if toks.len() > 6 {
-        return Err(ParseError {
-            message: "Too many tokens! MIX words have 5 bytes and a sign.",
-        });
+        return Err(ParseError::new(ParseErrorKind::TooManyTokens,
+                                   toks[6].0, toks[6].1));
     }
-    match toks[0] {
-        "+" => {
-            sign = 0;
-        },
-        "-" => {
-            sign = 1;
+    let sign: u8 = match toks[0].1 {
+        "+" => 0,
+        "-" => 1,
+        t => {
+            return Err(ParseError::new(ParseErrorKind::BadSign, toks[0].0, t));
         }
-        _ => {
-            return Err(ParseError {
-                message: "Invalid sign. Sign must be '+' or '-'",
-            });
+    };
+    if toks.len() == 2 {
+        // The sign has been given, so the magnitude must be just digits.
+        let (col, t) = toks[1];
+        if !t.chars().all(|c| c.is_ascii_digit()) {
+            return Err(ParseError::new(ParseErrorKind::BadInteger, col, t));
         }
+        return parse_integer(t, col).map(|w| w | (sign as MixWord) << 30);
     }
-    for i in 0..5 {
-        match u8::from_str(toks[i+1]) {
-            Ok(b) => {
-                if b <= MIX_BYTE_MAX {
-                    bytes[i] = b;
-                } else {
-                    return Err(ParseError {
-                        message: "Invalid byte token. Byte must fit \
-                        into a MIX byte.",
-                    });
-                }
+    let mut bytes: [u8; 5] = [0; 5];
+    let byte_toks = if toks.len() == 5 {
+        // Packed fields: spread the address over the first two bytes.
+        let (col, t) = toks[1];
+        let a = match u64::from_str(t) {
+            Ok(a) if a <= MIX_ADDR_MAX as u64 => a,
+            Ok(a) => {
+                return Err(ParseError::new(
+                    ParseErrorKind::AddressOutOfRange { value: a }, col, t));
             },
             Err(_e) => {
-                return Err(ParseError {
-                    message: "Invalid byte token. Byte must be a \
-                    small nonnegative number.",
-                });
+                return Err(ParseError::new(ParseErrorKind::BadAddress, col, t));
             },
-        }
+        };
+        bytes[0] = (a >> 6) as u8;
+        bytes[1] = (a as u8) & MIX_BYTE_MAX;
+        &toks[2..]
+    } else {
+        &toks[1..]
+    };
+    let first = 5 - byte_toks.len();
+    for (i, (col, t)) in byte_toks.iter().enumerate() {
+        bytes[first + i] = parse_byte(t, *col)?;
     }
     Ok(mix_util::word_from_bytes(sign, bytes[0], bytes[1],
                                  bytes[2], bytes[3], bytes[4]))
 }
+
+fn parse_byte(t: &str, col: usize) -> Result<MixByte, ParseError> {
+    match u64::from_str(t) {
+        Ok(b) => {
+            if b <= MIX_BYTE_MAX as u64 {
+                Ok(b as MixByte)
+            } else {
+                Err(ParseError::new(
+                    ParseErrorKind::ByteOutOfRange { value: b }, col, t))
+            }
+        },
+        Err(_e) => {
+            Err(ParseError::new(ParseErrorKind::BadByte, col, t))
+        },
+    }
+}
+
+fn parse_integer(t: &str, col: usize) -> Result<MixWord, ParseError> {
+    // Keep the sign of -0.
+    if t == "-0" {
+        return Ok(1 << 30);
+    }
+    match i64::from_str(t) {
+        Ok(n) if (MIX_WORD_MIN as i64 ..= MIX_WORD_MAX as i64).contains(&n) => {
+            Ok(mix_util::to_mix_word(n as i32))
+        },
+        Ok(n) => {
+            Err(ParseError::new(
+                ParseErrorKind::IntegerOutOfRange { value: n }, col, t))
+        },
+        Err(_e) => {
+            Err(ParseError::new(ParseErrorKind::BadInteger, col, t))
+        },
+    }
+}
+
+fn parse_text(t: &str, col: usize) -> Result<MixWord, ParseError> {
+    let chars: Vec<char> = t.chars().collect();
+    if chars.len() < 2 || chars[chars.len() - 1] != '"' || chars.len() > 7 {
+        return Err(ParseError::new(ParseErrorKind::BadText, col, t));
+    }
+    let mut bytes: [u8; 5] = [0; 5];
+    for (i, c) in chars[1 .. chars.len() - 1].iter().enumerate() {
+        match mix_util::char_to_mix(*c) {
+            Some(b) => bytes[i] = b,
+            None => {
+                return Err(ParseError::new(
+                    ParseErrorKind::BadChar { c: *c }, col, t));
+            },
+        }
+    }
+    Ok(mix_util::word_from_bytes(0, bytes[0], bytes[1],
+                                 bytes[2], bytes[3], bytes[4]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mix_fmt;
+
+    #[test]
+    fn test_fmt_parse_words_round_trip() {
+        let mut bytes: Vec<u8> = vec![];
+        for w in &[0, 1, (1 << 30) | 12345, mix_util::to_mix_word(-1), 1 << 29] {
+            bytes.extend(arch_util::u32to8s(*w).iter());
+        }
+        let dump = mix_fmt::fmt_words(&bytes);
+        assert_eq!(parse_words(&dump).unwrap(), bytes);
+
+        let e = parse_words(" + 1 2 3 4 5\n + 1 2 3\n").unwrap_err();
+        assert_eq!(e.line, 2);
+    }
+
+    fn round_trip(s: &str) -> MixWord {
+        let w = parse_word(s).unwrap();
+        assert_eq!(parse_word(&mix_fmt::fmt_word(w)).unwrap(), w);
+        w
+    }
+
+    #[test]
+    fn test_parse_word_notations() {
+        assert_eq!(round_trip(" + 20 42 10 0 5"),
+                   mix_util::word_from_bytes(0, 20, 42, 10, 0, 5));
+        assert_eq!(round_trip("+ 2000 2 3 8"),
+                   mix_util::word_from_bytes(0, 31, 16, 2, 3, 8));
+        assert_eq!(round_trip("- 4095 0 5 63"),
+                   mix_util::word_from_bytes(1, 63, 63, 0, 5, 63));
+        assert_eq!(round_trip("-123456"), mix_util::to_mix_word(-123456));
+        assert_eq!(round_trip("+ 1073741823"), mix_util::to_mix_word(MIX_WORD_MAX));
+        assert_eq!(round_trip("77"), 77);
+        assert_eq!(round_trip("-0"), 1 << 30);
+        assert_eq!(round_trip("\"HELLO\""),
+                   mix_util::word_from_bytes(0, 8, 5, 13, 13, 16));
+        assert_eq!(round_trip("\"RED P\""),
+                   mix_util::word_from_bytes(0, 19, 5, 4, 0, 17));
+        assert_eq!(round_trip("\"AB\""), mix_util::word_from_bytes(0, 1, 2, 0, 0, 0));
+
+        assert!(parse_word("+ 4096 0 0 0").is_err());
+        assert!(parse_word("1073741824").is_err());
+        assert!(parse_word("\"hello\"").is_err());
+        assert!(parse_word("\"TOOLONG\"").is_err());
+        assert!(parse_word("+ 1 2").is_err());
+        assert!(parse_word("").is_err());
+        assert_eq!(round_trip("- 5"), mix_util::to_mix_word(-5));
+        assert_eq!(round_trip("- 0"), 1 << 30);
+        assert_eq!(parse_word("- -5").unwrap_err().kind, ParseErrorKind::BadInteger);
+        assert!(parse_word("+ -5").is_err());
+        assert!(parse_word("- +5").is_err());
+        assert!(parse_word("--5").is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let e = parse_word(" +  1 2 99 4 5").unwrap_err();
+        assert_eq!(e.kind, ParseErrorKind::ByteOutOfRange { value: 99 });
+        assert_eq!((e.column, e.token.as_str()), (9, "99"));
+        let e = parse_word("* 1 2 3 4 5").unwrap_err();
+        assert_eq!((e.kind, e.column), (ParseErrorKind::BadSign, 1));
+        let e = parse_word("\"Ab\"").unwrap_err();
+        assert_eq!(e.kind, ParseErrorKind::BadChar { c: 'b' });
+
+        let errs = parse_words_all("+ 1 2\n+ 0 0 0 0 0\n- x 0 0 0 0\n").unwrap_err();
+        assert_eq!(errs.len(), 2);
+        assert_eq!((errs[0].line, errs[1].line, errs[1].column), (1, 3, 3));
+        assert_eq!(errs[1].to_string(),
+                   "Parsing error on line 3, column 3: Invalid byte 'x'. \
+                    Byte must be a small nonnegative number.");
+    }
+
+    #[test]
+    fn test_parse_memory() {
+        let text = "\
+# A sparse dump
+0002: + 0 0 0 0 7   # seven
++ 1 2 3 4 5
+
+...
+0010: -3
+";
+        let words = parse_memory(text).unwrap();
+        assert_eq!(words.len(), 11);
+        assert_eq!(words[2], 7);
+        assert_eq!(words[3], mix_util::word_from_bytes(0, 1, 2, 3, 4, 5));
+        assert_eq!(words[10], mix_util::to_mix_word(-3));
+        assert_eq!(words.iter().filter(|w| **w != 0).count(), 3);
+        assert_eq!(parse_memory(&mix_fmt::fmt_memory(&words, true)).unwrap(),
+                   words);
+
+        let errs = parse_memory("4000: + 0 0 0 0 0\n  0001: + 0 0 0 0 99\n")
+            .unwrap_err();
+        assert_eq!(errs[0].kind,
+                   ParseErrorKind::LocationOutOfRange { value: 4000 });
+        assert_eq!((errs[1].line, errs[1].column), (2, 19));
+    }
+}
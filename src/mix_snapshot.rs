@@ -0,0 +1,276 @@
+// Snapshots of the complete state of a machine, to save to disk and restore
+// later.
+//
+// A snapshot file is laid out as follows, all multi-byte numbers big-endian
+// and words, addresses and registers stored as in a portable image (see
+// mix_image):
+//   Bytes 0-3:   the magic number "MIXS"
+//   Byte 4:      format version, currently 1
+//   Byte 5:      the byte size of the machine, 64 or 100
+//   Bytes 6-7:   reserved, zero
+//   The registers, overflow toggle and comparison indicator
+//   The program counter, two bytes
+//   The cycle count and the instruction count, eight bytes each
+//   The panic message, if the machine has panicked: its length in bytes,
+//     two bytes, then its text in UTF-8. A length of zero means no panic.
+//   The subroutine calls in progress, outermost first: their number, two
+//     bytes, then for each the location of the call, the entry and the
+//     return address, two bytes each, and a byte which is 1 if the call has
+//     saved rJ
+//   The devices: their number, one byte, then for each its unit number,
+//     one byte, and its position, four bytes
+//   The memory: the number of words, four bytes, then the words
+//
+// Later versions may add to the end of this, but won't change what comes
+// before.
+
+use crate::mix_core;
+use crate::mix_image;
+use crate::mix_io;
+use crate::mix_image::{ImageRegisters, Reader};
+use crate::mix_types::*;
+
+pub const SNAPSHOT_MAGIC: &[u8; 4] = b"MIXS";
+pub const SNAPSHOT_VERSION: u8 = 1;
+
+// How far a device has got through its medium: the card or line for a
+// card reader, punch or printer, the block for a tape.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DevicePosition {
+    pub unit: u8,
+    pub position: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub registers: ImageRegisters,
+    pub pc: MixAddr,
+    pub cycles: u64,
+    pub instructions: u64,
+    pub panic_msg: Option<String>,
+    pub calls: Vec<CallFrame>,
+    pub devices: Vec<DevicePosition>,
+    pub memory: Box<MixMemory>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotError {
+    pub message: String,
+}
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "Snapshot error: {}", self.message)
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+fn error(message: String) -> SnapshotError {
+    SnapshotError { message }
+}
+
+impl From<mix_image::ImageError> for SnapshotError {
+    fn from(e: mix_image::ImageError) -> SnapshotError {
+        error(e.message)
+    }
+}
+
+// Take a snapshot of a machine.
+pub fn take_snapshot(st: &MixState) -> Snapshot {
+    Snapshot {
+        registers: ImageRegisters {
+            r: st.r.clone(),
+            overflow: st.overflow,
+            comparison: st.comparison.clone(),
+        },
+        pc: st.pc,
+        cycles: st.cycles,
+        instructions: st.instructions,
+        panic_msg: st.panic_msg.clone(),
+        calls: st.calls.clone(),
+        devices: st.io.iter().map(|d| DevicePosition {
+            unit: d.unit,
+            position: d.position as u32,
+        }).collect(),
+        memory: Box::new(*st.memory),
+    }
+}
+
+// Put a machine in the state of a snapshot. Breakpoints and watchpoints are
+// kept, and the history starts again from here if it's being recorded. The
+// devices attached keep their media, and are moved to their positions in
+// the snapshot; a device the snapshot doesn't mention is left where it is.
+pub fn restore_snapshot(st: &mut MixState, snap: &Snapshot) {
+    *st.memory = *snap.memory;
+    st.r = snap.registers.r.clone();
+    st.overflow = snap.registers.overflow;
+    st.comparison = snap.registers.comparison.clone();
+    st.pc = snap.pc;
+    st.cycles = snap.cycles;
+    st.instructions = snap.instructions;
+    st.panic_msg = snap.panic_msg.clone();
+    st.calls = snap.calls.clone();
+    for d in &snap.devices {
+        if let Some(dev) = mix_io::find_device(&mut st.io, d.unit) {
+            dev.position = d.position as usize;
+        }
+    }
+    st.is_running = false;
+    st.breakpoint_hit = None;
+    st.watch_hit = None;
+    if st.history.is_some() {
+        mix_core::start_recording(st);
+    }
+}
+
+// Write a snapshot in the snapshot file format.
+pub fn write_snapshot(snap: &Snapshot) -> Vec<u8> {
+    let mut out: Vec<u8> = SNAPSHOT_MAGIC.to_vec();
+    out.extend(&[SNAPSHOT_VERSION, 64, 0, 0]);
+    mix_image::put_registers(&mut out, &snap.registers);
+    out.extend(&snap.pc.to_be_bytes());
+    out.extend(&snap.cycles.to_be_bytes());
+    out.extend(&snap.instructions.to_be_bytes());
+    let msg = snap.panic_msg.as_ref().map_or("", |m| m.as_str()).as_bytes();
+    out.extend(&(msg.len() as u16).to_be_bytes());
+    out.extend(msg);
+    out.extend(&(snap.calls.len() as u16).to_be_bytes());
+    for frame in &snap.calls {
+        out.extend(&frame.call.to_be_bytes());
+        out.extend(&frame.entry.to_be_bytes());
+        out.extend(&frame.ret.to_be_bytes());
+        out.push(frame.saved as u8);
+    }
+    out.push(snap.devices.len() as u8);
+    for d in &snap.devices {
+        out.push(d.unit);
+        out.extend(&d.position.to_be_bytes());
+    }
+    out.extend(&(MEM_SIZE as u32).to_be_bytes());
+    for w in snap.memory.iter() {
+        mix_image::put_word(&mut out, *w);
+    }
+    out
+}
+
+fn be_u16(s: &[u8]) -> u16 {
+    u16::from_be_bytes([s[0], s[1]])
+}
+
+fn be_u64(s: &[u8]) -> u64 {
+    let mut b = [0; 8];
+    b.copy_from_slice(&s[.. 8]);
+    u64::from_be_bytes(b)
+}
+
+// Read a snapshot file, checking that everything in it fits the machine.
+pub fn read_snapshot(bytes: &[u8]) -> Result<Snapshot, SnapshotError> {
+    if !bytes.starts_with(SNAPSHOT_MAGIC) {
+        return Err(error("Not a snapshot".to_string()));
+    }
+    let mut rd = Reader { bytes, pos: 0 };
+    let header = rd.take(8)?;
+    if header[4] != SNAPSHOT_VERSION {
+        return Err(error(format!("Unsupported snapshot version {}", header[4])));
+    }
+    if header[5] != 64 {
+        return Err(error(format!(
+            "Snapshot is of a machine with byte size {}; only 64 is supported",
+            header[5])));
+    }
+    let registers = rd.registers()?;
+    let check_addr = |a: u16, what: &str| if a as usize >= MEM_SIZE {
+        Err(error(format!("{} {} is outside memory", what, a)))
+    } else {
+        Ok(a)
+    };
+    let pc = check_addr(be_u16(rd.take(2)?), "Program counter")?;
+    let cycles = be_u64(rd.take(8)?);
+    let instructions = be_u64(rd.take(8)?);
+    let len = be_u16(rd.take(2)?) as usize;
+    let panic_msg = match len {
+        0 => None,
+        _ => match std::str::from_utf8(rd.take(len)?) {
+            Ok(m) => Some(m.to_string()),
+            Err(_) => return Err(error("Panic message is not UTF-8".to_string())),
+        },
+    };
+    let ncalls = be_u16(rd.take(2)?);
+    let mut calls = vec![];
+    for _ in 0 .. ncalls {
+        let s = rd.take(7)?;
+        calls.push(CallFrame {
+            call: check_addr(be_u16(&s[0 .. 2]), "Call")?,
+            entry: check_addr(be_u16(&s[2 .. 4]), "Call")?,
+            // The return address may be just past the end of memory.
+            ret: be_u16(&s[4 .. 6]),
+            saved: s[6] != 0,
+        });
+    }
+    let ndevices = rd.take(1)?[0];
+    let mut devices = vec![];
+    for _ in 0 .. ndevices {
+        let s = rd.take(5)?;
+        devices.push(DevicePosition {
+            unit: s[0],
+            position: u32::from_be_bytes([s[1], s[2], s[3], s[4]]),
+        });
+    }
+    let s = rd.take(4)?;
+    let nwords = u32::from_be_bytes([s[0], s[1], s[2], s[3]]) as usize;
+    if nwords != MEM_SIZE {
+        return Err(error(format!(
+            "Snapshot has {} words of memory; the machine has {}",
+            nwords, MEM_SIZE)));
+    }
+    let mut memory = Box::new([0; MEM_SIZE]);
+    for w in memory.iter_mut() {
+        *w = rd.word()?;
+    }
+    Ok(Snapshot {
+        registers, pc, cycles, instructions, panic_msg, calls, devices, memory,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut mem: MixMemory = [0; MEM_SIZE];
+        mem[3999] = 1 << 30 | 12345;
+        let mut st = MixState::new(&mut mem);
+        st.r.a = 77;
+        st.r.i[2] = (1 << 12) | 5;
+        st.overflow = true;
+        st.pc = 3000;
+        st.cycles = 1 << 40;
+        st.instructions = 99;
+        st.panic_msg = Some("Out of bounds memory access! 4000".to_string());
+        st.calls.push(CallFrame { call: 3000, entry: 3010, ret: 3001, saved: true });
+        let mut cards = mix_io::read_medium(16, b"A\nB\nC\nD\n").unwrap();
+        cards.position = 3;
+        mix_io::attach(&mut st, cards);
+        let snap = take_snapshot(&st);
+        assert_eq!(snap.devices, vec![DevicePosition { unit: 16, position: 3 }]);
+
+        let bytes = write_snapshot(&snap);
+        assert_eq!(&bytes[.. 8], b"MIXS\x01\x40\x00\x00");
+        assert_eq!(read_snapshot(&bytes).unwrap(), snap);
+        assert!(read_snapshot(&bytes[.. bytes.len() - 1]).is_err());
+        let mut bad = bytes.clone();
+        bad[4] = 2;
+        assert!(read_snapshot(&bad).is_err());
+
+        let mut other: MixMemory = [0; MEM_SIZE];
+        let mut st2 = MixState::new(&mut other);
+        mix_io::attach(&mut st2, mix_io::read_medium(16, b"A\nB\nC\nD\n").unwrap());
+        restore_snapshot(&mut st2, &snap);
+        assert_eq!(st2.io[0].position, 3);
+        assert_eq!(take_snapshot(&st2).memory, snap.memory);
+        assert_eq!((st2.r.a, st2.pc, st2.cycles, st2.calls.len()),
+                   (77, 3000, 1 << 40, 1));
+    }
+}
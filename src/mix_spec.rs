@@ -0,0 +1,356 @@
+// Test specifications, for checking programs against expected results, as
+// mixtest does.
+//
+// A spec file is a list of cases. Each line is a setting, a keyword and its
+// value; # starts a comment. Settings before the first case apply to every
+// case, and those after it to that case alone:
+//   program <file>           the program to run, in any form mixdb loads
+//   input <unit> <file>      attach a device with its medium read from file
+//   output <unit> <file>     the medium the device should end up with
+//   check <expr>             an expression, as mixdb prints them, which
+//                            should be true (not zero) at the end
+//   limit <count>            the most instructions the program may execute
+//   time <units>             the most time the program may take
+//   case <name>              start a case
+// For example
+//   program sum.mixal
+//   time 5000
+//
+//   case two cards
+//   input 16 two.cards
+//   output 18 two.out
+//   check rA == 42 && mem[TOTAL] == 17
+//
+// A case passes if the program halts within its limits, every device given
+// an output ends up with it, and every check holds. The card punch and line
+// printer are always attached, so that a program may use them whether their
+// output is checked or not.
+
+use std::collections::HashMap;
+use crate::mix_batch;
+use crate::mix_batch::Limits;
+use crate::mix_expr;
+use crate::mix_expr::{BinOp, Expr};
+use crate::mix_fmt;
+use crate::mix_io;
+use crate::mix_load;
+use crate::mix_load::Program;
+use crate::mix_types::*;
+
+// Devices attached to every machine a case runs on.
+const ALWAYS_ATTACHED: [u8; 2] = [17, 18];
+
+// The most differences shown for one device.
+const DIFF_LIMIT: usize = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    pub name: String,
+    // The line of the spec file the case starts on.
+    pub line: usize,
+    pub program: Option<String>,
+    pub inputs: Vec<(u8, String)>,
+    pub outputs: Vec<(u8, String)>,
+    pub checks: Vec<String>,
+    pub limits: Limits,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpecError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for SpecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "Spec error on line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for SpecError {}
+
+fn error(line: usize, message: String) -> SpecError {
+    SpecError { line, message }
+}
+
+// Parse a unit and a file, checking that the unit can do what's asked.
+fn parse_attachment(value: &str, line: usize, output: bool)
+    -> Result<(u8, String), SpecError> {
+    let mut words = value.splitn(2, char::is_whitespace);
+    let unit = words.next().unwrap_or("");
+    let file = words.next().unwrap_or("").trim();
+    match unit.parse::<u8>() {
+        _ if file.is_empty() => Err(error(line, "Give a unit and a file".to_string())),
+        Ok(u) => match mix_io::check_attachment(u, output) {
+            Ok(_) => Ok((u, file.to_string())),
+            Err(e) => Err(error(line, e.message)),
+        },
+        Err(_) => Err(error(line, format!("Bad unit '{}'", unit))),
+    }
+}
+
+// Parse a spec file.
+pub fn parse_spec(text: &str) -> Result<Vec<Case>, SpecError> {
+    let mut defaults = Case {
+        name: String::new(),
+        line: 0,
+        program: None,
+        inputs: vec![],
+        outputs: vec![],
+        checks: vec![],
+        limits: Limits {
+            instructions: mix_batch::DEFAULT_INSTRUCTION_LIMIT,
+            time: 0,
+        },
+    };
+    let mut cases: Vec<Case> = vec![];
+    for (k, line) in text.lines().enumerate() {
+        let n = k + 1;
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (key, value) = match line.find(char::is_whitespace) {
+            Some(ix) => (&line[.. ix], line[ix ..].trim()),
+            None => (line, ""),
+        };
+        if key == "case" {
+            if value.is_empty() {
+                return Err(error(n, "Missing the name of the case".to_string()));
+            }
+            cases.push(Case { name: value.to_string(), line: n, ..defaults.clone() });
+            continue;
+        }
+        let case = cases.last_mut().unwrap_or(&mut defaults);
+        if value.is_empty() {
+            return Err(error(n, format!("Missing a value for {}", key)));
+        }
+        let number = || value.parse::<u64>()
+            .map_err(|_| error(n, format!("Bad number '{}'", value)));
+        match key {
+            "program" => case.program = Some(value.to_string()),
+            "input" => case.inputs.push(parse_attachment(value, n, false)?),
+            "output" => case.outputs.push(parse_attachment(value, n, true)?),
+            "check" => case.checks.push(value.to_string()),
+            "limit" => case.limits.instructions = number()?,
+            "time" => case.limits.time = number()?,
+            _ => return Err(error(n, format!("Unknown setting '{}'", key))),
+        }
+    }
+    if cases.is_empty() {
+        return Err(error(text.lines().count(), "There are no cases".to_string()));
+    }
+    Ok(cases)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseResult {
+    pub end: StopReason,
+    pub stats: RunStats,
+    // What went wrong, a message each, possibly over several lines.
+    pub failures: Vec<String>,
+}
+
+impl CaseResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+// Show how lines of text differ, a line at a time.
+fn diff_lines(expected: &[&str], actual: &[&str]) -> Vec<String> {
+    let mut diffs = vec![];
+    for k in 0 .. std::cmp::max(expected.len(), actual.len()) {
+        let (e, a) = (expected.get(k), actual.get(k));
+        if e == a {
+            continue;
+        }
+        let show = |l: Option<&&str>| match l {
+            Some(l) => format!("\"{}\"", l),
+            None => "nothing".to_string(),
+        };
+        let heading = format!("line {}: expected", k + 1);
+        diffs.push(format!("  {} {}\n  {:>w$} {}", heading, show(e), "got", show(a),
+                           w = heading.len()));
+    }
+    diffs
+}
+
+// Show how blocks of words differ, a word at a time.
+fn diff_blocks(expected: &[Vec<MixWord>], actual: &[Vec<MixWord>]) -> Vec<String> {
+    let mut diffs = vec![];
+    for k in 0 .. std::cmp::max(expected.len(), actual.len()) {
+        match (expected.get(k), actual.get(k)) {
+            (Some(e), Some(a)) => {
+                for (ix, (we, wa)) in e.iter().zip(a).enumerate() {
+                    if we != wa {
+                        diffs.push(format!(
+                            "  block {}, word {}: expected {}, got {}", k, ix,
+                            mix_fmt::fmt_word(*we), mix_fmt::fmt_word(*wa)));
+                    }
+                }
+            },
+            (Some(_), None) => diffs.push(format!("  block {}: missing", k)),
+            (None, Some(_)) => diffs.push(format!("  block {}: not expected", k)),
+            (None, None) => {},
+        }
+    }
+    diffs
+}
+
+// Compare the medium of a device with what's expected of it, giving the
+// differences, if any.
+fn compare_medium(dev: &mix_io::Device, expected: &[u8]) -> Result<Vec<String>, String> {
+    if dev.kind.is_text() {
+        let expected = std::str::from_utf8(expected)
+            .map_err(|_| "The expected output is not text".to_string())?;
+        let actual = String::from_utf8_lossy(&mix_io::write_medium(dev)).into_owned();
+        let e: Vec<&str> = expected.lines().map(|l| l.trim_end_matches(' ')).collect();
+        let a: Vec<&str> = actual.lines().collect();
+        Ok(diff_lines(&e, &a))
+    } else {
+        let e = mix_io::read_medium(dev.unit, expected).map_err(|e| e.message)?;
+        Ok(diff_blocks(&e.blocks, &dev.blocks))
+    }
+}
+
+// Evaluate a check, giving why it failed if it did.
+fn run_check(check: &str, program: &Program, st: &MixState) -> Option<String> {
+    let expr = match mix_expr::parse_expr(check, &program.symbols) {
+        Ok(e) => e,
+        Err(e) => return Some(format!("Bad check '{}': {}", check, e)),
+    };
+    match mix_expr::eval(&expr, st) {
+        Ok(0) => {},
+        Ok(_) => return None,
+        Err(e) => return Some(format!("Check '{}' failed: {}", check, e)),
+    }
+    // For a comparison, show what was compared.
+    let values = match &expr {
+        Expr::Binary(BinOp::Eq, l, r) | Expr::Binary(BinOp::Ne, l, r) |
+        Expr::Binary(BinOp::Lt, l, r) | Expr::Binary(BinOp::Le, l, r) |
+        Expr::Binary(BinOp::Gt, l, r) | Expr::Binary(BinOp::Ge, l, r) =>
+            match (mix_expr::eval(l, st), mix_expr::eval(r, st)) {
+                (Ok(l), Ok(r)) => format!(": the values are {} and {}", l, r),
+                _ => String::new(),
+            },
+        _ => String::new(),
+    };
+    Some(format!("Check '{}' failed{}", check, values))
+}
+
+// Run a case on a fresh machine. The contents of the files the case names
+// are given by name.
+pub fn run_case(case: &Case, program: &Program, files: &HashMap<String, Vec<u8>>)
+    -> CaseResult {
+    let mut mem: MixMemory = [0; MEM_SIZE];
+    let mut st = MixState::new(&mut mem);
+    let mut failures = vec![];
+    if let Err(e) = mix_load::load_image(&mut st, &program.image, None) {
+        failures.push(e.message);
+    }
+    let empty = vec![];
+    let contents = |file: &String| files.get(file).unwrap_or(&empty);
+    for (unit, file) in &case.inputs {
+        match mix_io::read_medium(*unit, contents(file)) {
+            Ok(dev) => mix_io::attach(&mut st, dev),
+            Err(e) => failures.push(format!("{}: {}", file, e)),
+        }
+    }
+    let units = case.outputs.iter().map(|(u, _)| *u).chain(ALWAYS_ATTACHED.iter().cloned());
+    for unit in units {
+        if !st.io.iter().any(|d| d.unit == unit) {
+            mix_io::attach(&mut st, mix_io::new_device(unit).unwrap());
+        }
+    }
+    let outcome = if failures.is_empty() {
+        mix_batch::run_batch(&mut st, case.limits)
+    } else {
+        RunOutcome { reason: StopReason::Halted, stats: RunStats::default() }
+    };
+    match &outcome.reason {
+        StopReason::Fault(msg) => failures.push(format!(
+            "Faulted at instruction {}: {}", st.instructions, msg)),
+        StopReason::WaitingForInput(unit) => failures.push(format!(
+            "Ran out of input on unit {} at instruction {}", unit, st.instructions)),
+        StopReason::LimitReached if case.limits.out_of_time(&outcome.stats) =>
+            failures.push(format!(
+                "Reached the limit of {} units of time", case.limits.time)),
+        StopReason::LimitReached => failures.push(format!(
+            "Reached the limit of {} instructions", case.limits.instructions)),
+        _ => {},
+    }
+    for (unit, file) in &case.outputs {
+        let dev = st.io.iter().find(|d| d.unit == *unit).unwrap();
+        match compare_medium(dev, contents(file)) {
+            Ok(diffs) if diffs.is_empty() => {},
+            Ok(diffs) => {
+                let mut msg = format!("Unit {} ({}) differs from {}:", unit,
+                                      dev.kind.name(), file);
+                for d in diffs.iter().take(DIFF_LIMIT) {
+                    msg.push('\n');
+                    msg.push_str(d);
+                }
+                if diffs.len() > DIFF_LIMIT {
+                    msg.push_str(&format!("\n  and {} more", diffs.len() - DIFF_LIMIT));
+                }
+                failures.push(msg);
+            },
+            Err(e) => failures.push(format!("{}: {}", file, e)),
+        }
+    }
+    for check in &case.checks {
+        if let Some(msg) = run_check(check, program, &st) {
+            failures.push(msg);
+        }
+    }
+    CaseResult { end: outcome.reason, stats: outcome.stats, failures }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spec_and_cases() {
+        let spec = "program p.mixal  # shared\nlimit 50\n\ncase copy\n\
+                    input 16 cards\noutput 18 printed\ncheck rA == 3\n\
+                    case loops\nlimit 10\n";
+        let cases = parse_spec(spec).unwrap();
+        assert_eq!(cases.len(), 2);
+        assert_eq!((cases[0].name.as_str(), cases[0].line), ("copy", 4));
+        assert_eq!(cases[0].inputs, vec![(16, "cards".to_string())]);
+        assert_eq!(cases[1].program, Some("p.mixal".to_string()));
+        assert_eq!((cases[0].limits.instructions, cases[1].limits.instructions), (50, 10));
+        assert_eq!(parse_spec("case x\ninput 18 f\n").unwrap_err().line, 2);
+        let e = parse_spec("case x\noutput 16 f\n").unwrap_err();
+        assert_eq!((e.line, e.message.as_str()), (2, "Unit 16 (card reader) cannot write"));
+        assert!(parse_spec("case x\noutput 20 f\n").is_err());
+        assert!(parse_spec("program p\n").is_err());
+        assert!(parse_spec("case x\nspeed 9\n").is_err());
+
+        // Read a card, print it, and leave its first word in rA.
+        let src = " ORIG 1000\nSTART IN 2000(16)\n OUT 2000(18)\n LDA 2000\n HLT\n \
+                   END START\n";
+        let program = mix_load::read_program("p.mixal", src.as_bytes()).unwrap();
+        let mut files = HashMap::new();
+        files.insert("cards".to_string(), b"ABC\n".to_vec());
+        files.insert("printed".to_string(), b"ABC  \n".to_vec());
+        let result = run_case(&cases[0], &program, &files);
+        assert!(!result.passed());
+        assert_eq!(result.failures, vec!["Check 'rA == 3' failed: the values are \
+                                          17313792 and 3".to_string()]);
+
+        files.insert("printed".to_string(), b"ABD\n".to_vec());
+        let mut case = cases[0].clone();
+        case.checks.clear();
+        let result = run_case(&case, &program, &files);
+        assert_eq!((result.end, result.stats.instructions), (StopReason::Halted, 4));
+        assert_eq!(result.failures, vec!["Unit 18 (line printer) differs from printed:\n  \
+                                          line 1: expected \"ABD\"\n               got \"ABC\""
+                                         .to_string()]);
+        files.remove("cards");
+        assert_eq!(run_case(&case, &program, &files).failures[0],
+                   "Ran out of input on unit 16 at instruction 0");
+    }
+}
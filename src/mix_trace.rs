@@ -0,0 +1,231 @@
+// Tracing execution: an entry for every instruction executed, giving the
+// cycle count when it started, its location, its disassembly, its
+// effective address and what it changed, written either as columns of
+// text or as JSON Lines.
+//
+// A trace in text looks like
+//        7  3003  STA 3008,1            M=3009  [3009]: 5 -> 1
+// and in JSON Lines, one object per instruction,
+//   {"cycles":7,"pc":3003,"instr":"STA 3008,1","m":3009,
+//    "changes":[{"mem":3009,"old":5,"new":1}]}
+// (on one line). Values are signed integers. A register change is given as
+// {"reg":"A","old":0,"new":1}, and changes to the overflow toggle and the
+// comparison indicator as {"overflow":true} and {"cmp":"less"}.
+
+use std::io::Write;
+use crate::mix_fmt;
+use crate::mix_types::*;
+use crate::mix_util;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraceFormat {
+    Text,
+    Json,
+}
+
+// Where trace entries go, and which instructions get them.
+pub struct Tracer {
+    pub format: TraceFormat,
+    // Inclusive ranges of locations to trace instructions at. Instructions
+    // anywhere are traced if there are none.
+    pub ranges: Vec<(MixAddr, MixAddr)>,
+    pub out: Box<dyn Write>,
+}
+
+impl Tracer {
+    pub fn new(format: TraceFormat, ranges: Vec<(MixAddr, MixAddr)>,
+               out: Box<dyn Write>) -> Tracer {
+        Tracer { format, ranges, out }
+    }
+
+    pub fn covers(&self, pc: MixAddr) -> bool {
+        self.ranges.is_empty() ||
+            self.ranges.iter().any(|(first, last)| (*first ..= *last).contains(&pc))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceChange {
+    // A register, named as the debugger names them, and its old and new
+    // values.
+    Register(&'static str, i64, i64),
+    Memory(MixAddr, i64, i64),
+    Overflow(bool),
+    Comparison(MixCompare),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEntry {
+    pub cycles: u64,
+    pub pc: MixAddr,
+    pub instr: MixWord,
+    // The effective address M, as a signed number.
+    pub m: i64,
+    pub changes: Vec<TraceChange>,
+}
+
+const INDEX_NAMES: [&str; 6] = ["I1", "I2", "I3", "I4", "I5", "I6"];
+
+fn word_value(w: MixWord) -> i64 {
+    mix_util::from_mix_word(w) as i64
+}
+
+fn addr_value(a: MixAddr) -> i64 {
+    mix_util::from_mix_addr(a) as i64
+}
+
+// Work out what an instruction changed, from the machine before it (as
+// recorded for undoing it, with its registers and the cycle count when it
+// started) and after it.
+pub fn trace_entry(st: &MixState, instr: MixWord, m: MixAddr,
+                   before: &UndoEntry) -> TraceEntry {
+    let mut changes = vec![];
+    if let Some(r) = &before.r {
+        if r.a != st.r.a {
+            changes.push(TraceChange::Register("A", word_value(r.a),
+                                               word_value(st.r.a)));
+        }
+        if r.x != st.r.x {
+            changes.push(TraceChange::Register("X", word_value(r.x),
+                                               word_value(st.r.x)));
+        }
+        for (name, (old, new)) in INDEX_NAMES.iter().zip(r.i.iter().zip(&st.r.i)) {
+            if old != new {
+                changes.push(TraceChange::Register(name, addr_value(*old),
+                                                   addr_value(*new)));
+            }
+        }
+        if r.j != st.r.j {
+            changes.push(TraceChange::Register("J", addr_value(r.j),
+                                               addr_value(st.r.j)));
+        }
+    }
+    for w in &before.writes {
+        changes.push(TraceChange::Memory(w.addr, word_value(w.old),
+                                         word_value(w.new)));
+    }
+    if before.overflow != st.overflow {
+        changes.push(TraceChange::Overflow(st.overflow));
+    }
+    if before.comparison != st.comparison {
+        changes.push(TraceChange::Comparison(st.comparison.clone()));
+    }
+    TraceEntry {
+        cycles: before.cycles,
+        pc: before.pc,
+        instr,
+        m: addr_value(m),
+        changes,
+    }
+}
+
+fn cmp_name(c: &MixCompare) -> &'static str {
+    match c {
+        MixCompare::Less => "less",
+        MixCompare::Equal => "equal",
+        MixCompare::Greater => "greater",
+    }
+}
+
+// Quote a string for JSON. Disassembly is plain ASCII, but be safe.
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// Format a trace entry as a line, without the newline.
+pub fn fmt_entry(e: &TraceEntry, format: TraceFormat) -> String {
+    let disasm = mix_fmt::disasm_word(e.instr);
+    match format {
+        TraceFormat::Text => {
+            let mut line = format!("{:8}  {:04}  {:20}  M={:<5}", e.cycles, e.pc,
+                                   disasm, e.m);
+            for c in &e.changes {
+                line.push_str("  ");
+                line.push_str(&match c {
+                    TraceChange::Register(name, old, new) =>
+                        format!("{}: {} -> {}", name, old, new),
+                    TraceChange::Memory(addr, old, new) =>
+                        format!("[{:04}]: {} -> {}", addr, old, new),
+                    TraceChange::Overflow(on) =>
+                        format!("OV: {}", if *on { "on" } else { "off" }),
+                    TraceChange::Comparison(c) => format!("CMP: {}", cmp_name(c)),
+                });
+            }
+            line.trim_end().to_string()
+        },
+        TraceFormat::Json => {
+            let changes: Vec<String> = e.changes.iter().map(|c| match c {
+                TraceChange::Register(name, old, new) =>
+                    format!("{{\"reg\":\"{}\",\"old\":{},\"new\":{}}}", name, old, new),
+                TraceChange::Memory(addr, old, new) =>
+                    format!("{{\"mem\":{},\"old\":{},\"new\":{}}}", addr, old, new),
+                TraceChange::Overflow(on) => format!("{{\"overflow\":{}}}", on),
+                TraceChange::Comparison(c) =>
+                    format!("{{\"cmp\":\"{}\"}}", cmp_name(c)),
+            }).collect();
+            format!("{{\"cycles\":{},\"pc\":{},\"instr\":{},\"m\":{},\"changes\":[{}]}}",
+                    e.cycles, e.pc, json_string(&disasm), e.m, changes.join(","))
+        },
+    }
+}
+
+// Write the trace entry for an instruction just executed, if the tracer
+// covers its location. Stop tracing if the output fails.
+pub fn trace_step(st: &mut MixState, instr: MixWord, m: MixAddr,
+                  before: &UndoEntry) {
+    let covered = match &st.tracer {
+        Some(t) => t.covers(before.pc),
+        None => false,
+    };
+    if !covered {
+        return;
+    }
+    let entry = trace_entry(st, instr, m, before);
+    let failed = match &mut st.tracer {
+        Some(t) => {
+            let line = fmt_entry(&entry, t.format);
+            writeln!(t.out, "{}", line).is_err()
+        },
+        None => false,
+    };
+    if failed {
+        st.tracer = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fmt_entry() {
+        let e = TraceEntry {
+            cycles: 7,
+            pc: 3003,
+            instr: mix_util::word_from_bytes(0, 46, 56, 1, 5, 24),
+            m: 3009,
+            changes: vec![
+                TraceChange::Memory(3009, 5, 1),
+                TraceChange::Register("I1", 0, -1),
+                TraceChange::Comparison(MixCompare::Less),
+            ],
+        };
+        assert_eq!(fmt_entry(&e, TraceFormat::Text),
+                   "       7  3003  STA 3000,1            M=3009   \
+                    [3009]: 5 -> 1  I1: 0 -> -1  CMP: less");
+        assert_eq!(fmt_entry(&e, TraceFormat::Json),
+                   "{\"cycles\":7,\"pc\":3003,\"instr\":\"STA 3000,1\",\"m\":3009,\
+                    \"changes\":[{\"mem\":3009,\"old\":5,\"new\":1},\
+                    {\"reg\":\"I1\",\"old\":0,\"new\":-1},{\"cmp\":\"less\"}]}");
+    }
+}
@@ -1,3 +1,8 @@
+use std::collections::VecDeque;
+use crate::mix_expr::Expr;
+use crate::mix_io::Device;
+use crate::mix_trace::Tracer;
+
 // Per the specification:
 // "Each byte holds an unspecified amount of information, but
 // must contain at least 64 distinct values and at most 100 distinct values."
@@ -22,7 +27,7 @@ pub type MixWord = u32;
 // [unused bits] [sign] [byte1] [byte2]
 pub type MixAddr = u16;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MixRegisters {
     pub a: MixWord,
     pub x: MixWord,
@@ -31,28 +36,270 @@ pub struct MixRegisters {
     pub j: MixAddr,
 }
 
+// Names for the registers, for code which picks one at run time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Register {
+    A,
+    X,
+    // The index registers I1 to I6, numbered from 1.
+    I(usize),
+    J,
+}
+
 pub const MEM_SIZE :usize = 4000;
 
 pub type MixMemory = [MixWord; MEM_SIZE];
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum MixCompare {
     Less,
     Equal,
     Greater,
 }
 
+// A location where running stops before the instruction there executes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakpoint {
+    // Numbers are assigned from 1, and shared with watchpoints.
+    pub id: usize,
+    pub addr: MixAddr,
+    pub enabled: bool,
+    // How many times execution has reached the breakpoint while enabled.
+    pub hits: usize,
+    // How many more hits to pass over before stopping.
+    pub ignore: usize,
+    // Only stop when this evaluates to nonzero. Hits are counted only then.
+    pub condition: Option<Expr>,
+}
+
+// The kinds of memory access a watchpoint stops on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchKind {
+    Write,
+    Read,
+    Access,
+}
+
+// A field of a memory word where running stops after an instruction reads
+// or writes it. Only accesses to fields overlapping the watched one count.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watchpoint {
+    pub id: usize,
+    pub addr: MixAddr,
+    pub field: MixByte,
+    pub kind: WatchKind,
+    pub enabled: bool,
+    pub hits: usize,
+}
+
+// An access which triggered a watchpoint: the word before and after it, and
+// the location of the instruction making it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchHit {
+    pub id: usize,
+    pub addr: MixAddr,
+    pub write: bool,
+    pub old: MixWord,
+    pub new: MixWord,
+    pub pc: MixAddr,
+}
+
+// A subroutine call, inferred from a JMP which set rJ. Until the
+// subroutine saves rJ with STJ, the call is unconfirmed: a jump might set
+// rJ again first, losing the way back, in which case the JMP was no call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallFrame {
+    // The location of the JMP.
+    pub call: MixAddr,
+    // The location jumped to.
+    pub entry: MixAddr,
+    // Where the subroutine returns to, the JMP's location plus one.
+    pub ret: MixAddr,
+    pub saved: bool,
+}
+
+// A write to memory by an instruction: the field written, and the word
+// before and after.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UndoWrite {
+    pub addr: MixAddr,
+    pub field: MixByte,
+    pub old: MixWord,
+    pub new: MixWord,
+}
+
+// What an input or output instruction did to a device, so that it can be
+// undone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceUndo {
+    pub unit: u8,
+    // The position and number of blocks before.
+    pub position: usize,
+    pub len: usize,
+    // A block overwritten, by index, and what it held.
+    pub old: Option<(usize, Vec<MixWord>)>,
+}
+
+// What executing one instruction changed, so that it can be undone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UndoEntry {
+    // The location of the instruction.
+    pub pc: MixAddr,
+    // The registers before, if the instruction changed them.
+    pub r: Option<MixRegisters>,
+    pub overflow: bool,
+    pub comparison: MixCompare,
+    // Each word written, in order.
+    pub writes: Vec<UndoWrite>,
+    // The time the instruction took.
+    pub cycles: u64,
+    // The subroutine calls before, if the instruction changed them.
+    pub calls: Option<Vec<CallFrame>>,
+    // Whether the instruction panicked the machine.
+    pub panicked: bool,
+    // What the instruction did to a device, if anything.
+    pub device: Option<DeviceUndo>,
+}
+
+// The whole state of a machine after some number of instructions, to go
+// back to when the undo log doesn't reach far enough.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub instructions: u64,
+    pub memory: Box<MixMemory>,
+    pub r: MixRegisters,
+    pub overflow: bool,
+    pub comparison: MixCompare,
+    pub pc: MixAddr,
+    pub cycles: u64,
+    pub calls: Vec<CallFrame>,
+    pub io: Vec<Device>,
+}
+
+// A record of execution for running backwards: an undo entry for each of
+// the latest instructions, and checkpoints taken every so often. Both are
+// bounded, dropping the oldest first; see mix_core for the limits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct History {
+    pub entries: VecDeque<UndoEntry>,
+    // In order of instruction count.
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+// When a run should stop, besides the program halting, faulting or waiting
+// for input, and the machine's breakpoints and watchpoints. Limits count
+// from the start of the run.
+#[derive(Default)]
+pub struct StopCondition<'c> {
+    pub instructions: Option<u64>,
+    pub time: Option<u64>,
+    // Locations to stop at as breakpoints do, just for this run.
+    pub breakpoints: Vec<MixAddr>,
+    // Called after each instruction; the run stops when it returns true.
+    pub done: Option<&'c dyn Fn(&MixState) -> bool>,
+}
+
+// Why a run stopped.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    Halted,
+    Fault(String),
+    // Before the instruction at the location. If the breakpoint is one of
+    // the machine's, breakpoint_hit says which.
+    Breakpoint(MixAddr),
+    Watchpoint(WatchHit),
+    // A limit on instructions or time in the stop condition.
+    LimitReached,
+    // An input instruction with nothing more to read on the unit.
+    WaitingForInput(u8),
+    // The stop condition's callback returned true.
+    Done,
+}
+
+// What a run did: the instructions it executed and the time they took.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct RunStats {
+    pub instructions: u64,
+    pub cycles: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunOutcome {
+    pub reason: StopReason,
+    pub stats: RunStats,
+}
+
 pub struct MixState<'a> {
     // Documented state features.
     pub r: MixRegisters,
     pub comparison: MixCompare,
     pub overflow: bool,
-    pub memory: &'a MixMemory,
-    pub io: (), // TODO
+    pub memory: &'a mut MixMemory,
+    // The devices attached, in order of unit.
+    pub io: Vec<Device>,
     // Undocumented "implementation detail" features.
     pub pc: MixAddr,
     pub is_running: bool,
     pub panic_msg: Option<String>,
+    // The unit the instruction under the program counter is waiting to read
+    // from, if its device has no more input. The instruction isn't executed
+    // until there is some.
+    pub waiting: Option<u8>,
+    // Time taken so far, in units of u, as given in TAOCP 1.3.1.
+    pub cycles: u64,
+    // The number of instructions executed so far.
+    pub instructions: u64,
+    // Debugging features.
+    pub breakpoints: Vec<Breakpoint>,
+    // The breakpoint that stopped the last run, if any.
+    pub breakpoint_hit: Option<usize>,
+    pub watchpoints: Vec<Watchpoint>,
+    // The watchpoint triggered by the last instruction, if any.
+    pub watch_hit: Option<WatchHit>,
+    // Subroutine calls in progress, outermost first.
+    pub calls: Vec<CallFrame>,
+    // The record of execution, if it's being recorded.
+    pub history: Option<History>,
+    // Where to trace each instruction executed, if anywhere.
+    pub tracer: Option<Tracer>,
+    // Words written by the instruction being executed, when the history or
+    // the trace needs them.
+    pub step_writes: Option<Vec<UndoWrite>>,
+    // What the instruction being executed did to a device, likewise.
+    pub step_device: Option<DeviceUndo>,
+}
+
+impl<'a> MixState<'a> {
+    // A machine with cleared registers and flags, using the given memory.
+    pub fn new(memory: &'a mut MixMemory) -> MixState<'a> {
+        MixState {
+            r: MixRegisters {
+                a: 0,
+                x: 0,
+                i: [0, 0, 0, 0, 0, 0],
+                j: 0,
+            },
+            comparison: MixCompare::Equal,
+            overflow: false,
+            memory,
+            io: vec![],
+            pc: 0,
+            is_running: false,
+            panic_msg: None,
+            waiting: None,
+            cycles: 0,
+            instructions: 0,
+            breakpoints: vec![],
+            breakpoint_hit: None,
+            watchpoints: vec![],
+            watch_hit: None,
+            calls: vec![],
+            history: None,
+            tracer: None,
+            step_writes: None,
+            step_device: None,
+        }
+    }
 }
 
 // For sanity checking various inputs
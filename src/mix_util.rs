@@ -116,10 +116,71 @@ pub fn get_field_word(word: MixWord, field_spec: MixByte) -> MixWord {
     return get_bytes(word, l, r) << (6 * (5 - r));
 }
 
+// The value of a field of a word as the load and arithmetic operators see
+// it: the bytes of the field shifted to the right, with the word's sign if
+// the field includes it and + otherwise.
+pub fn field_value(word: MixWord, field_spec: MixByte) -> MixWord {
+    let l = field_spec / 8;
+    let r = field_spec % 8;
+    let sign = if l == 0 { word & (1 << 30) } else { 0 };
+    let first = std::cmp::max(l, 1);
+    if first > r {
+        return sign;
+    }
+    sign | get_bytes(word, first, r)
+}
+
+// Store a value into the given field of a word, the way the MIX store
+// operators do. The rightmost bytes of the value replace the bytes of the
+// field, and the sign is replaced only if the field includes it. All other
+// parts of the word are retained.
+pub fn store_field(word: MixWord, value: MixWord, field_spec: MixByte) -> MixWord {
+    let l = field_spec / 8;
+    let r = field_spec % 8;
+    let mut new_word = word;
+    if l == 0 {
+        new_word = (new_word & !(1 << 30)) | (value & (1 << 30));
+    }
+    let first = std::cmp::max(l, 1);
+    if first <= r {
+        let nbytes = (r - first + 1) as u32;
+        let mask = (ONES >> (32 - 6 * nbytes)) << (6 * (5 - r));
+        let bytes = (value & (ONES >> (32 - 6 * nbytes))) << (6 * (5 - r));
+        new_word = (new_word & !mask) | bytes;
+    }
+    new_word
+}
+
+// The MIX character set, indexed by character code.
+// Codes 10, 20 and 21 are the Greek letters Delta, Sigma and Pi.
+pub const MIX_CHARS: [char; 56] = [
+    ' ', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I',
+    'Δ', 'J', 'K', 'L', 'M', 'N', 'O', 'P', 'Q', 'R',
+    'Σ', 'Π', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z',
+    '0', '1', '2', '3', '4', '5', '6', '7', '8', '9',
+    '.', ',', '(', ')', '+', '-', '*', '/', '=', '$',
+    '<', '>', '@', ';', ':', '\'',
+];
+
+// Convert a character to its MIX character code, if it has one.
+pub fn char_to_mix(c: char) -> Option<MixByte> {
+    MIX_CHARS.iter().position(|&m| m == c).map(|i| i as MixByte)
+}
+
+// Convert a MIX character code to a character. Codes outside the
+// character set are shown as '?'.
+pub fn mix_to_char(b: MixByte) -> char {
+    match MIX_CHARS.get(b as usize) {
+        Some(&c) => c,
+        None => '?',
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
     fn test_to_from_mix_word_addr() {
         assert_eq!(from_mix_word(0), 0);
         assert_eq!(from_mix_word(1), 1);
@@ -133,13 +194,13 @@ mod tests {
 
         assert_eq!(from_mix_addr(0), 0);
         assert_eq!(from_mix_addr(1), 1);
-        assert_eq!(from_mix_addr((1 << 30) | 1), -1);
-        assert_eq!(from_mix_addr((1 << 30) | 3), -3);
+        assert_eq!(from_mix_addr((1 << 12) | 1), -1);
+        assert_eq!(from_mix_addr((1 << 12) | 3), -3);
 
         assert_eq!(to_mix_addr(0), 0);
         assert_eq!(to_mix_addr(1), 1);
-        assert_eq!(to_mix_addr(-1), (1 << 30) | 1);
-        assert_eq!(to_mix_addr(-3), (1 << 30) | 3);
+        assert_eq!(to_mix_addr(-1), (1 << 12) | 1);
+        assert_eq!(to_mix_addr(-3), (1 << 12) | 3);
     }
 
     #[test]
@@ -185,4 +246,22 @@ mod tests {
         assert_eq!(get_field_word(test2, 10), get_field_word(test1, 10));
 
     }
+
+    #[test]
+    fn test_store_field_chars() {
+        let w = word_from_bytes(1, 1, 2, 3, 4, 5);
+        let v = word_from_bytes(0, 6, 7, 8, 9, 10);
+        assert_eq!(store_field(w, v, 5), v);
+        assert_eq!(store_field(w, v, 10), word_from_bytes(1, 9, 10, 3, 4, 5));
+        assert_eq!(store_field(w, v, 45), word_from_bytes(1, 1, 2, 3, 4, 10));
+        assert_eq!(store_field(w, v, 2), word_from_bytes(0, 9, 10, 3, 4, 5));
+        assert_eq!(store_field(w, v, 0), word_from_bytes(0, 1, 2, 3, 4, 5));
+
+        for (i, c) in MIX_CHARS.iter().enumerate() {
+            assert_eq!(char_to_mix(*c), Some(i as MixByte));
+            assert_eq!(mix_to_char(i as MixByte), *c);
+        }
+        assert_eq!(char_to_mix('a'), None);
+        assert_eq!(mix_to_char(63), '?');
+    }
 }
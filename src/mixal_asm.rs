@@ -0,0 +1,1076 @@
+// Two-pass MIXAL assembler.
+//
+// The first pass assigns a location to every statement and defines symbols.
+// EQU and ORIG change symbols and the location counter, so their operands
+// must only refer to symbols that are already defined. Everything else is
+// evaluated in the second pass, once every symbol is known.
+//
+// As in TAOCP 1.3.2, literal constants =W= and symbols which are used as
+// an address but never defined are placed after the program: each one
+// becomes a CON line inserted just before END, in order of first use.
+
+use std::collections::HashMap;
+use crate::mix_types::*;
+use crate::mix_util;
+use crate::mix_opcodes;
+use crate::mixal_parse::*;
+
+// A word placed in memory by the assembler, with the source line that
+// produced it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssembledWord {
+    pub addr: MixAddr,
+    pub word: MixWord,
+    pub line: usize,
+    // Whether the word is data, from CON, ALF or the constant pool, rather
+    // than an instruction.
+    pub data: bool,
+}
+
+pub struct MixalProgram {
+    // Memory image. Locations not assembled into are zero.
+    pub memory: Box<MixMemory>,
+    // The address given on the END line.
+    pub start: MixAddr,
+    // Every word assembled, in the order it was assembled.
+    pub words: Vec<AssembledWord>,
+    // Every symbol, in the order of definition.
+    pub symbols: Vec<MixalSymbol>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MixalSymbol {
+    pub name: String,
+    pub value: i32,
+    // The line defining the symbol. For a symbol which was never defined
+    // and so was given a place in the constant pool, this is the END line.
+    pub line: usize,
+    // Every line using the symbol, in order.
+    pub uses: Vec<usize>,
+}
+
+// 64^5, one more than the largest magnitude a word can hold.
+const WORD_MODULUS: i64 = 1 << 30;
+
+// Reduce a value to a MIX word the way the machine would, keeping the
+// sign and dropping anything that overflows.
+fn wrap(v: i64) -> i64 {
+    if v < 0 { -((-v) % WORD_MODULUS) } else { v % WORD_MODULUS }
+}
+
+enum PoolEntry {
+    // A literal constant used by the statement with this index.
+    Literal(usize),
+    // A symbol used as an address before it was defined.
+    Symbol(String),
+}
+
+struct Assembler<'a> {
+    stmts: &'a [Statement],
+    symbols: HashMap<String, i64>,
+    // Symbols and the lines defining them, in order of definition.
+    symbol_order: Vec<(String, usize)>,
+    // Definitions of each local symbol dH, as (statement index, value).
+    locals: Vec<Vec<(usize, i64)>>,
+    // The location counter at each statement.
+    locs: Vec<i64>,
+    // Candidates for the constant pool, in order of first use.
+    pool: Vec<PoolEntry>,
+    // The constant pool as finally placed before END.
+    placed: Vec<(i64, PoolEntry)>,
+    // Addresses of the literal constants, by statement index.
+    literals: HashMap<usize, i64>,
+}
+
+fn error(line: usize, column: usize, message: String) -> MixalError {
+    MixalError { line, column, message }
+}
+
+// Assemble MIXAL source into a memory image.
+pub fn assemble(src: &str) -> Result<MixalProgram, MixalError> {
+    let stmts = parse_program(src)?;
+    let mut asm = Assembler {
+        stmts: &stmts,
+        symbols: HashMap::new(),
+        symbol_order: vec![],
+        locals: vec![vec![]; 10],
+        locs: vec![],
+        pool: vec![],
+        placed: vec![],
+        literals: HashMap::new(),
+    };
+    asm.first_pass(src.lines().count())?;
+    asm.second_pass()
+}
+
+impl<'a> Assembler<'a> {
+    fn define(&mut self, label: &Option<Label>, ix: usize, value: i64)
+        -> Result<(), MixalError> {
+        match label {
+            Some(Label::Symbol(s)) => {
+                if self.symbols.contains_key(s) {
+                    return Err(error(self.stmts[ix].line, 1,
+                                     format!("Symbol {} is already defined", s)));
+                }
+                self.symbols.insert(s.clone(), value);
+                self.symbol_order.push((s.clone(), self.stmts[ix].line));
+            },
+            Some(Label::Local(d)) => self.locals[*d as usize].push((ix, value)),
+            None => {},
+        }
+        Ok(())
+    }
+
+    fn first_pass(&mut self, nlines: usize) -> Result<(), MixalError> {
+        let mut loc: i64 = 0;
+        let mut seen_end = false;
+        for (ix, s) in self.stmts.iter().enumerate() {
+            if seen_end {
+                return Err(error(s.line, s.op_column,
+                                 "Statement after END".to_string()));
+            }
+            self.locs.push(loc);
+            match (s.op.as_str(), &s.operand) {
+                ("EQU", Operand::WValue(w)) => {
+                    let v = self.eval_wvalue(w, ix, true)?;
+                    self.define(&s.label, ix, v)?;
+                },
+                ("ORIG", Operand::WValue(w)) => {
+                    self.define(&s.label, ix, loc)?;
+                    loc = self.eval_wvalue(w, ix, true)?;
+                },
+                ("CON", _) | ("ALF", _) => {
+                    self.define(&s.label, ix, loc)?;
+                    loc += 1;
+                },
+                ("END", _) => {
+                    let pool = std::mem::take(&mut self.pool);
+                    for entry in pool {
+                        match entry {
+                            PoolEntry::Literal(i) => {
+                                self.literals.insert(i, loc);
+                            },
+                            PoolEntry::Symbol(ref name) => {
+                                if self.symbols.contains_key(name) {
+                                    continue;
+                                }
+                                self.define(&Some(Label::Symbol(name.clone())),
+                                            ix, loc)?;
+                            },
+                        }
+                        self.placed.push((loc, entry));
+                        loc += 1;
+                    }
+                    self.define(&s.label, ix, loc)?;
+                    seen_end = true;
+                },
+                (_, Operand::Instruction(o)) => {
+                    if mix_opcodes::lookup_op(&s.op).is_none() {
+                        return Err(error(s.line, s.op_column,
+                                         format!("Unknown operation {}", s.op)));
+                    }
+                    self.define(&s.label, ix, loc)?;
+                    self.note_future_references(o, ix);
+                    loc += 1;
+                },
+                _ => panic!("Parser produced a bad operand for {}", s.op),
+            }
+        }
+        if !seen_end {
+            return Err(error(nlines + 1, 1, "Missing END".to_string()));
+        }
+        Ok(())
+    }
+
+    // Remember literals, and symbols used in the address before being
+    // defined, in case they need a place in the constant pool.
+    fn note_future_references(&mut self, o: &InstrOperand, ix: usize) {
+        match &o.a {
+            APart::Literal(_) => self.pool.push(PoolEntry::Literal(ix)),
+            APart::Expr(e) => {
+                let atoms = std::iter::once(&e.first)
+                    .chain(e.rest.iter().map(|(_, a)| a));
+                for a in atoms {
+                    if let Atom::Symbol(name) = a {
+                        let pooled = self.pool.iter().any(|p| match p {
+                            PoolEntry::Symbol(n) => n == name,
+                            _ => false,
+                        });
+                        if !self.symbols.contains_key(name) && !pooled {
+                            self.pool.push(PoolEntry::Symbol(name.clone()));
+                        }
+                    }
+                }
+            },
+            APart::Empty => {},
+        }
+    }
+
+    fn second_pass(&self) -> Result<MixalProgram, MixalError> {
+        let mut prog = MixalProgram {
+            memory: Box::new([0; MEM_SIZE]),
+            start: 0,
+            words: vec![],
+            symbols: self.symbol_table(),
+        };
+        for (ix, s) in self.stmts.iter().enumerate() {
+            let loc = self.locs[ix];
+            match (s.op.as_str(), &s.operand) {
+                ("EQU", _) | ("ORIG", _) => {},
+                ("CON", Operand::WValue(w)) => {
+                    let word = self.eval_wvalue_word(w, ix, false)?;
+                    emit(&mut prog, s, loc, word, true)?;
+                },
+                ("ALF", Operand::Alf(text)) => {
+                    emit(&mut prog, s, loc, alf_word(s, text)?, true)?;
+                },
+                ("END", Operand::WValue(w)) => {
+                    for (addr, entry) in &self.placed {
+                        let word = match entry {
+                            PoolEntry::Literal(i) => {
+                                let lit = match &self.stmts[*i].operand {
+                                    Operand::Instruction(InstrOperand {
+                                        a: APart::Literal(lit), ..
+                                    }) => lit,
+                                    _ => panic!("Pooled literal has no W-value"),
+                                };
+                                self.eval_wvalue_word(lit, *i, false)?
+                            },
+                            PoolEntry::Symbol(_) => 0,
+                        };
+                        emit(&mut prog, s, *addr, word, true)?;
+                    }
+                    let start = if w.parts.is_empty() {
+                        0
+                    } else {
+                        self.eval_wvalue(w, ix, false)?
+                    };
+                    if start < MIX_MEMORY_ADDR_MIN as i64 ||
+                        start > MIX_MEMORY_ADDR_MAX as i64 {
+                        return Err(error(s.line, w.parts[0].0.column, format!(
+                            "Start address {} is outside memory", start)));
+                    }
+                    prog.start = start as MixAddr;
+                },
+                (_, Operand::Instruction(o)) => {
+                    let word = self.instr_word(s, o, ix)?;
+                    emit(&mut prog, s, loc, word, false)?;
+                },
+                _ => panic!("Parser produced a bad operand for {}", s.op),
+            }
+        }
+        Ok(prog)
+    }
+
+    fn symbol_table(&self) -> Vec<MixalSymbol> {
+        let mut table: Vec<MixalSymbol> = self.symbol_order.iter()
+            .map(|(name, line)| MixalSymbol {
+                name: name.clone(),
+                value: self.symbols[name] as i32,
+                line: *line,
+                uses: vec![],
+            }).collect();
+        let index: HashMap<&str, usize> = self.symbol_order.iter().enumerate()
+            .map(|(i, (name, _))| (name.as_str(), i)).collect();
+        for s in self.stmts {
+            for name in s.symbols_used() {
+                if let Some(&i) = index.get(name) {
+                    if table[i].uses.last() != Some(&s.line) {
+                        table[i].uses.push(s.line);
+                    }
+                }
+            }
+        }
+        table
+    }
+
+    fn instr_word(&self, s: &Statement, o: &InstrOperand, ix: usize)
+        -> Result<MixWord, MixalError> {
+        // The first pass already checked the operation exists.
+        let op = mix_opcodes::lookup_op(&s.op).unwrap();
+        let a = match &o.a {
+            APart::Empty => 0,
+            APart::Expr(e) => {
+                let a = self.eval_expr(e, ix, false)?;
+                if a.abs() > MIX_ADDR_MAX as i64 {
+                    return Err(error(s.line, e.column, format!(
+                        "Address {} does not fit in two bytes", a)));
+                }
+                a
+            },
+            APart::Literal(_) => self.literals[&ix],
+        };
+        let i = match &o.index {
+            Some(e) => self.eval_byte(e, ix, "Index")?,
+            None => 0,
+        };
+        let f = match &o.field {
+            Some(e) => self.eval_byte(e, ix, "Field")?,
+            None => op.f,
+        };
+        let ua = a.unsigned_abs() as u32;
+        Ok(mix_util::word_from_bytes((a < 0) as u8,
+                                     (ua / 64) as u8, (ua % 64) as u8,
+                                     i, f, op.c))
+    }
+
+    fn eval_byte(&self, e: &Expr, ix: usize, what: &str)
+        -> Result<MixByte, MixalError> {
+        let v = self.eval_expr(e, ix, false)?;
+        if v < 0 || v > MIX_BYTE_MAX as i64 {
+            return Err(error(self.stmts[ix].line, e.column, format!(
+                "{} {} does not fit in a byte", what, v)));
+        }
+        Ok(v as MixByte)
+    }
+
+    // Evaluate a W-value. In the first pass (strict), every symbol must
+    // already be defined.
+    fn eval_wvalue(&self, w: &WValue, ix: usize, strict: bool)
+        -> Result<i64, MixalError> {
+        Ok(mix_util::from_mix_word(self.eval_wvalue_word(w, ix, strict)?) as i64)
+    }
+
+    // Evaluate a W-value as a word, for CON and literal constants. Unlike
+    // a number, a word can be -0, which a part written as -0 gives.
+    fn eval_wvalue_word(&self, w: &WValue, ix: usize, strict: bool)
+        -> Result<MixWord, MixalError> {
+        let mut word: MixWord = 0;
+        for (e, f) in &w.parts {
+            let v = self.eval_expr(e, ix, strict)?;
+            let field = match f {
+                Some(fe) => {
+                    let field = self.eval_expr(fe, ix, strict)?;
+                    if field < 0 || field / 8 > field % 8 || field % 8 > 5 {
+                        return Err(error(self.stmts[ix].line, fe.column,
+                            format!("Invalid field specification {}", field)));
+                    }
+                    field as MixByte
+                },
+                None => 5,
+            };
+            let part = if v == 0 && e.negate && e.rest.is_empty() {
+                1 << 30
+            } else {
+                mix_util::to_mix_word(v as i32)
+            };
+            word = mix_util::store_field(word, part, field);
+        }
+        Ok(word)
+    }
+
+    fn eval_expr(&self, e: &Expr, ix: usize, strict: bool)
+        -> Result<i64, MixalError> {
+        let line = self.stmts[ix].line;
+        let mut v = self.eval_atom(&e.first, e, ix, strict)?;
+        if e.negate {
+            v = -v;
+        }
+        for (op, atom) in &e.rest {
+            let b = self.eval_atom(atom, e, ix, strict)?;
+            v = match op {
+                BinOp::Add => wrap(v + b),
+                BinOp::Sub => wrap(v - b),
+                BinOp::Mul => wrap(v * b),
+                BinOp::Div | BinOp::FracDiv if b == 0 => {
+                    return Err(error(line, e.column,
+                                     "Division by zero".to_string()));
+                },
+                BinOp::Div => v / b,
+                BinOp::FracDiv => {
+                    let q = v * WORD_MODULUS / b;
+                    if q.abs() >= WORD_MODULUS {
+                        return Err(error(line, e.column, format!(
+                            "{}//{} does not fit in a word", v, b)));
+                    }
+                    q
+                },
+                BinOp::Field => wrap(8 * v + b),
+            };
+        }
+        Ok(v)
+    }
+
+    fn eval_atom(&self, a: &Atom, e: &Expr, ix: usize, strict: bool)
+        -> Result<i64, MixalError> {
+        let line = self.stmts[ix].line;
+        let undefined = |name: String| {
+            let message = if strict {
+                format!("{} must be defined before it is used here", name)
+            } else {
+                format!("Undefined symbol {}", name)
+            };
+            Err(error(line, e.column, message))
+        };
+        match a {
+            Atom::Number(n) => Ok(wrap(*n)),
+            Atom::Here => Ok(self.locs[ix]),
+            Atom::Symbol(s) => match self.symbols.get(s) {
+                Some(v) => Ok(*v),
+                None => undefined(s.clone()),
+            },
+            Atom::Backward(d) => {
+                match self.locals[*d as usize].iter().rev().find(|(i, _)| *i < ix) {
+                    Some((_, v)) => Ok(*v),
+                    None => undefined(format!("{}B", d)),
+                }
+            },
+            Atom::Forward(d) => {
+                match self.locals[*d as usize].iter().find(|(i, _)| *i > ix) {
+                    Some((_, v)) => Ok(*v),
+                    None => undefined(format!("{}F", d)),
+                }
+            },
+        }
+    }
+}
+
+fn emit(prog: &mut MixalProgram, s: &Statement, loc: i64, word: MixWord,
+        data: bool) -> Result<(), MixalError> {
+    if loc < MIX_MEMORY_ADDR_MIN as i64 || loc > MIX_MEMORY_ADDR_MAX as i64 {
+        return Err(error(s.line, s.op_column,
+                         format!("Location {} is outside memory", loc)));
+    }
+    prog.memory[loc as usize] = word;
+    prog.words.push(AssembledWord {
+        addr: loc as MixAddr, word, line: s.line, data,
+    });
+    Ok(())
+}
+
+fn alf_word(s: &Statement, text: &str) -> Result<MixWord, MixalError> {
+    let mut bytes = [0; ALF_LEN];
+    for (i, c) in text.chars().enumerate() {
+        bytes[i] = match mix_util::char_to_mix(c) {
+            Some(b) => b,
+            None => return Err(error(s.line, s.op_column, format!(
+                "'{}' is not in the MIX character set", c))),
+        };
+    }
+    Ok(mix_util::word_from_bytes(0, bytes[0], bytes[1], bytes[2],
+                                 bytes[3], bytes[4]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An instruction word, written the way TAOCP lists them.
+    fn instr(a: i32, i: u8, f: u8, c: u8) -> MixWord {
+        let ua = a.unsigned_abs();
+        mix_util::word_from_bytes((a < 0) as u8, (ua / 64) as u8, (ua % 64) as u8,
+                                  i, f, c)
+    }
+
+    // A source line in Knuth's card layout.
+    fn card(loc: &str, op: &str, address: &str) -> String {
+        format!("{:<10} {:<4} {}\n", loc, op, address)
+    }
+
+    fn check(prog: &MixalProgram, expected: &[(usize, MixWord)]) {
+        for (addr, w) in expected {
+            assert_eq!(prog.memory[*addr], *w, "word at {}", addr);
+        }
+        let nonzero = prog.memory.iter().filter(|&&w| w != 0).count();
+        assert_eq!(nonzero, expected.iter().filter(|(_, w)| *w != 0).count());
+    }
+
+    // Program M, TAOCP 1.3.2.
+    #[test]
+    fn test_program_m() {
+        let src = "\
+X        EQU  1000
+         ORIG 3000
+MAXIMUM  STJ  EXIT
+INIT     ENT3 0,1
+         JMP  CHANGEM
+LOOP     CMPA X,3
+         JGE  *+3
+CHANGEM  ENT2 0,3
+         LDA  X,3
+         DEC3 1
+         J3P  LOOP
+EXIT     JMP  *
+         END  MAXIMUM
+";
+        let prog = assemble(src).unwrap();
+        check(&prog, &[
+            (3000, instr(3009, 0, 2, 32)),
+            (3001, instr(0, 1, 2, 51)),
+            (3002, instr(3005, 0, 0, 39)),
+            (3003, instr(1000, 3, 5, 56)),
+            (3004, instr(3007, 0, 7, 39)),
+            (3005, instr(0, 3, 2, 50)),
+            (3006, instr(1000, 3, 5, 8)),
+            (3007, instr(1, 0, 1, 51)),
+            (3008, instr(3003, 0, 2, 43)),
+            (3009, instr(3009, 0, 0, 39)),
+        ]);
+        assert_eq!(prog.start, 3000);
+    }
+
+    // Program P, TAOCP 1.3.2, in the card layout of the book.
+    #[test]
+    fn test_program_p() {
+        let mut src = String::new();
+        src.push_str("* EXAMPLE PROGRAM ... TABLE OF PRIMES\n*\n");
+        for (loc, op, address) in &[
+            ("L", "EQU", "500"),
+            ("PRINTER", "EQU", "18"),
+            ("PRIME", "EQU", "-1"),
+            ("BUF0", "EQU", "2000"),
+            ("BUF1", "EQU", "BUF0+25"),
+            ("", "ORIG", "3000"),
+            ("START", "IOC", "0(PRINTER)"),
+            ("", "LD1", "=1-L="),
+            ("", "LD2", "=3="),
+            ("2H", "INC1", "1"),
+            ("", "ST2", "PRIME+L,1"),
+            ("", "J1Z", "2F"),
+            ("4H", "INC2", "2"),
+            ("", "ENT3", "2"),
+            ("6H", "ENTA", "0"),
+            ("", "ENTX", "0,2"),
+            ("", "DIV", "PRIME,3"),
+            ("", "JXZ", "4B"),
+            ("", "CMPA", "PRIME,3"),
+            ("", "INC3", "1"),
+            ("", "JG", "6B"),
+            ("", "JMP", "2B"),
+            ("2H", "OUT", "TITLE(PRINTER)"),
+            ("", "ENT4", "BUF1+10"),
+            ("", "ENT5", "-50"),
+            ("2H", "INC5", "L+1"),
+            ("4H", "LDA", "PRIME,5"),
+            ("", "CHAR", ""),
+            ("", "STX", "0,4(1:4)"),
+            ("", "DEC4", "1"),
+            ("", "DEC5", "50"),
+            ("", "J5P", "4B"),
+            ("", "OUT", "0,4(PRINTER)"),
+            ("", "LD4", "24,4"),
+            ("", "J5N", "2B"),
+            ("", "HLT", ""),
+            ("*", "", ""),
+            ("", "ORIG", "PRIME+1"),
+            ("", "CON", "2"),
+            ("", "ORIG", "BUF0-5"),
+            ("TITLE", "ALF", "FIRST"),
+            ("", "ALF", " FIVE"),
+            ("", "ALF", " HUND"),
+            ("", "ALF", "RED P"),
+            ("", "ALF", "RIMES"),
+            ("", "ORIG", "BUF0+24"),
+            ("", "CON", "BUF1+10"),
+            ("", "ORIG", "BUF1+24"),
+            ("", "CON", "BUF0+10"),
+            ("", "END", "START"),
+        ] {
+            src.push_str(&card(loc, op, address));
+        }
+        let prog = assemble(&src).unwrap();
+        check(&prog, &[
+            (3000, instr(0, 0, 18, 35)),
+            (3001, instr(2050, 0, 5, 9)),
+            (3002, instr(2051, 0, 5, 10)),
+            (3003, instr(1, 0, 0, 49)),
+            (3004, instr(499, 1, 5, 26)),
+            (3005, instr(3016, 0, 1, 41)),
+            (3006, instr(2, 0, 0, 50)),
+            (3007, instr(2, 0, 2, 51)),
+            (3008, instr(0, 0, 2, 48)),
+            (3009, instr(0, 2, 2, 55)),
+            (3010, instr(-1, 3, 5, 4)),
+            (3011, instr(3006, 0, 1, 47)),
+            (3012, instr(-1, 3, 5, 56)),
+            (3013, instr(1, 0, 0, 51)),
+            (3014, instr(3008, 0, 6, 39)),
+            (3015, instr(3003, 0, 0, 39)),
+            (3016, instr(1995, 0, 18, 37)),
+            (3017, instr(2035, 0, 2, 52)),
+            (3018, instr(-50, 0, 2, 53)),
+            (3019, instr(501, 0, 0, 53)),
+            (3020, instr(-1, 5, 5, 8)),
+            (3021, instr(0, 0, 1, 5)),
+            (3022, instr(0, 4, 12, 31)),
+            (3023, instr(1, 0, 1, 52)),
+            (3024, instr(50, 0, 1, 53)),
+            (3025, instr(3020, 0, 2, 45)),
+            (3026, instr(0, 4, 18, 37)),
+            (3027, instr(24, 4, 5, 12)),
+            (3028, instr(3019, 0, 0, 45)),
+            (3029, instr(0, 0, 2, 5)),
+            (0, 2),
+            (1995, alf("FIRST")),
+            (1996, alf(" FIVE")),
+            (1997, alf(" HUND")),
+            (1998, alf("RED P")),
+            (1999, alf("RIMES")),
+            (2024, 2035),
+            (2049, 2010),
+            (2050, mix_util::to_mix_word(-499)),
+            (2051, 3),
+        ]);
+        assert_eq!(prog.start, 3000);
+        let data: Vec<MixAddr> = prog.words.iter().filter(|w| w.data)
+            .map(|w| w.addr).collect();
+        assert_eq!(data, vec![0, 1995, 1996, 1997, 1998, 1999, 2024, 2049,
+                              2050, 2051]);
+    }
+
+    fn alf(s: &str) -> MixWord {
+        let b: Vec<u8> = s.chars().map(|c| mix_util::char_to_mix(c).unwrap()).collect();
+        mix_util::word_from_bytes(0, b[0], b[1], b[2], b[3], b[4])
+    }
+
+    // Program A, TAOCP 1.3.3: multiplying permutations in cycle form.
+    #[test]
+    fn test_program_a() {
+        let mut src = String::new();
+        for (loc, op, address) in &[
+            ("MAXWDS", "EQU", "1200"),
+            ("PERM", "ORIG", "*+MAXWDS"),
+            ("ANS", "ORIG", "*+MAXWDS"),
+            ("OUTBUF", "ORIG", "*+24"),
+            ("CARDS", "EQU", "16"),
+            ("PRINTER", "EQU", "18"),
+            ("BEGIN", "IN", "PERM(CARDS)"),
+            ("", "ENT2", "0"),
+            ("", "LDA", "EQUALS"),
+            ("1H", "JBUS", "*(CARDS)"),
+            ("", "CMPA", "PERM+15,2"),
+            ("", "JE", "*+2"),
+            ("", "IN", "PERM+16,2(CARDS)"),
+            ("", "ENT1", "OUTBUF"),
+            ("", "JBUS", "*(PRINTER)"),
+            ("", "MOVE", "PERM,2(16)"),
+            ("", "OUT", "OUTBUF(PRINTER)"),
+            ("", "JE", "1F"),
+            ("", "INC2", "16"),
+            ("", "CMP2", "=MAXWDS-16="),
+            ("", "JLE", "1B"),
+            ("", "HLT", "666"),
+            ("1H", "INC2", "15"),
+            ("", "ST2", "SIZE"),
+            ("", "ENT3", "0"),
+            ("2H", "LDAN", "PERM,3"),
+            ("", "CMPA", "LPREN(1:5)"),
+            ("", "JNE", "1F"),
+            ("", "STA", "PERM,3"),
+            ("", "INC3", "1"),
+            ("", "LDXN", "PERM,3"),
+            ("", "JXZ", "*-2"),
+            ("1H", "CMPA", "RPREN(1:5)"),
+            ("", "JNE", "*+2"),
+            ("", "STX", "PERM,3"),
+            ("", "INC3", "1"),
+            ("", "CMP3", "SIZE"),
+            ("", "JL", "2B"),
+            ("", "LDA", "LPREN"),
+            ("", "ENT1", "ANS"),
+            ("OPEN", "ENT3", "0"),
+            ("1H", "LDXN", "PERM,3"),
+            ("", "JXN", "GO"),
+            ("", "INC3", "1"),
+            ("", "CMP3", "SIZE"),
+            ("", "JL", "1B"),
+            ("*", "", ""),
+            ("DONE", "CMP1", "=ANS="),
+            ("", "JNE", "*+2"),
+            ("", "MOVE", "LPREN(2)"),
+            ("", "MOVE", "=0="),
+            ("", "MOVE", "-1,1(22)"),
+            ("", "ENT3", "0"),
+            ("", "OUT", "ANS,3(PRINTER)"),
+            ("", "INC3", "24"),
+            ("", "LDX", "ANS,3"),
+            ("", "JXNZ", "*-3"),
+            ("", "HLT", ""),
+            ("LPREN", "ALF", "    ("),
+            ("RPREN", "ALF", "    )"),
+            ("EQUALS", "ALF", "    ="),
+            ("GO", "MOVE", "LPREN"),
+            ("", "MOVE", "PERM,3"),
+            ("", "STX", "START"),
+            ("SUCC", "STX", "PERM,3"),
+            ("", "INC3", "1"),
+            ("", "LDXN", "PERM,3(1:5)"),
+            ("", "JXN", "1F"),
+            ("", "JMP", "*-3"),
+            ("5H", "STX", "0,1"),
+            ("", "INC1", "1"),
+            ("", "ENT3", "0"),
+            ("4H", "CMPX", "PERM,3(1:5)"),
+            ("", "JE", "SUCC"),
+            ("1H", "INC3", "1"),
+            ("", "CMP3", "SIZE"),
+            ("", "JL", "4B"),
+            ("", "CMPX", "START(1:5)"),
+            ("", "JNE", "5B"),
+            ("CLOSE", "MOVE", "RPREN"),
+            ("", "CMPA", "-3,1"),
+            ("", "JNE", "OPEN"),
+            ("", "INC1", "-3"),
+            ("", "JMP", "OPEN"),
+            ("", "END", "BEGIN"),
+        ] {
+            src.push_str(&card(loc, op, address));
+        }
+        let prog = assemble(&src).unwrap();
+        check(&prog, &[
+            (2424, instr(0, 0, 16, 36)),
+            (2425, instr(0, 0, 2, 50)),
+            (2426, instr(2477, 0, 5, 8)),
+            (2427, instr(2427, 0, 16, 34)),
+            (2428, instr(15, 2, 5, 56)),
+            (2429, instr(2431, 0, 5, 39)),
+            (2430, instr(16, 2, 16, 36)),
+            (2431, instr(2400, 0, 2, 49)),
+            (2432, instr(2432, 0, 18, 34)),
+            (2433, instr(0, 2, 16, 7)),
+            (2434, instr(2400, 0, 18, 37)),
+            (2435, instr(2440, 0, 5, 39)),
+            (2436, instr(16, 0, 0, 50)),
+            (2437, instr(2501, 0, 5, 58)),
+            (2438, instr(2427, 0, 9, 39)),
+            (2439, instr(666, 0, 2, 5)),
+            (2440, instr(15, 0, 0, 50)),
+            (2441, instr(2502, 0, 5, 26)),
+            (2442, instr(0, 0, 2, 51)),
+            (2443, instr(0, 3, 5, 16)),
+            (2444, instr(2475, 0, 13, 56)),
+            (2445, instr(2450, 0, 8, 39)),
+            (2446, instr(0, 3, 5, 24)),
+            (2447, instr(1, 0, 0, 51)),
+            (2448, instr(0, 3, 5, 23)),
+            (2449, instr(2447, 0, 1, 47)),
+            (2450, instr(2476, 0, 13, 56)),
+            (2451, instr(2453, 0, 8, 39)),
+            (2452, instr(0, 3, 5, 31)),
+            (2453, instr(1, 0, 0, 51)),
+            (2454, instr(2502, 0, 5, 59)),
+            (2455, instr(2443, 0, 4, 39)),
+            (2456, instr(2475, 0, 5, 8)),
+            (2457, instr(1200, 0, 2, 49)),
+            (2458, instr(0, 0, 2, 51)),
+            (2459, instr(0, 3, 5, 23)),
+            (2460, instr(2478, 0, 0, 47)),
+            (2461, instr(1, 0, 0, 51)),
+            (2462, instr(2502, 0, 5, 59)),
+            (2463, instr(2459, 0, 4, 39)),
+            (2464, instr(2503, 0, 5, 57)),
+            (2465, instr(2467, 0, 8, 39)),
+            (2466, instr(2475, 0, 2, 7)),
+            (2467, instr(2504, 0, 1, 7)),
+            (2468, instr(-1, 1, 22, 7)),
+            (2469, instr(0, 0, 2, 51)),
+            (2470, instr(1200, 3, 18, 37)),
+            (2471, instr(24, 0, 0, 51)),
+            (2472, instr(1200, 3, 5, 15)),
+            (2473, instr(2470, 0, 4, 47)),
+            (2474, instr(0, 0, 2, 5)),
+            (2475, alf("    (")),
+            (2476, alf("    )")),
+            (2477, alf("    =")),
+            (2478, instr(2475, 0, 1, 7)),
+            (2479, instr(0, 3, 1, 7)),
+            (2480, instr(2505, 0, 5, 31)),
+            (2481, instr(0, 3, 5, 31)),
+            (2482, instr(1, 0, 0, 51)),
+            (2483, instr(0, 3, 13, 23)),
+            (2484, instr(2491, 0, 0, 47)),
+            (2485, instr(2482, 0, 0, 39)),
+            (2486, instr(0, 1, 5, 31)),
+            (2487, instr(1, 0, 0, 49)),
+            (2488, instr(0, 0, 2, 51)),
+            (2489, instr(0, 3, 13, 63)),
+            (2490, instr(2481, 0, 5, 39)),
+            (2491, instr(1, 0, 0, 51)),
+            (2492, instr(2502, 0, 5, 59)),
+            (2493, instr(2489, 0, 4, 39)),
+            (2494, instr(2505, 0, 13, 63)),
+            (2495, instr(2486, 0, 8, 39)),
+            (2496, instr(2476, 0, 1, 7)),
+            (2497, instr(-3, 1, 5, 56)),
+            (2498, instr(2458, 0, 8, 39)),
+            (2499, instr(-3, 0, 0, 49)),
+            (2500, instr(2458, 0, 0, 39)),
+            // The pool: =MAXWDS-16=, SIZE, =ANS=, =0= and START.
+            (2501, 1184),
+            (2502, 0),
+            (2503, 1200),
+            (2504, 0),
+            (2505, 0),
+        ]);
+        assert_eq!(prog.start, 2424);
+    }
+
+    // Program I, TAOCP 1.3.3: the inverse of a permutation, in place.
+    #[test]
+    fn test_program_i() {
+        let src = "\
+X        EQU  1000
+N        EQU  100
+         ORIG 3000
+INVERT   ENT1 N
+         ENT3 -1
+2H       LD2N X,1
+         J2P  5F
+3H       ST3  X,1
+         ENN3 0,1
+         ENN1 0,2
+         LD2N X,1
+4H       J2N  3B
+         ENN2 0,3
+5H       ST2  X,1
+6H       DEC1 1
+         J1P  2B
+         END  INVERT
+";
+        let prog = assemble(src).unwrap();
+        check(&prog, &[
+            (3000, instr(100, 0, 2, 49)),
+            (3001, instr(-1, 0, 2, 51)),
+            (3002, instr(1000, 1, 5, 18)),
+            (3003, instr(3010, 0, 2, 42)),
+            (3004, instr(1000, 1, 5, 27)),
+            (3005, instr(0, 1, 3, 51)),
+            (3006, instr(0, 2, 3, 49)),
+            (3007, instr(1000, 1, 5, 18)),
+            (3008, instr(3004, 0, 0, 42)),
+            (3009, instr(0, 3, 3, 50)),
+            (3010, instr(1000, 1, 5, 26)),
+            (3011, instr(1, 0, 1, 49)),
+            (3012, instr(3002, 0, 2, 41)),
+        ]);
+    }
+
+    // The maximum subroutine of TAOCP 1.4.1, with entrances for 100
+    // elements and for n.
+    #[test]
+    fn test_subroutine_maxn() {
+        let src = "\
+X        EQU  1000
+         ORIG 3000
+MAX100   ENT3 100
+MAXN     STJ  EXIT
+         JMP  2F
+1H       CMPA X,3
+         JGE  *+3
+2H       ENT2 0,3
+         LDA  X,3
+         DEC3 1
+         J3P  1B
+EXIT     JMP  *
+         END  MAX100
+";
+        let prog = assemble(src).unwrap();
+        check(&prog, &[
+            (3000, instr(100, 0, 2, 51)),
+            (3001, instr(3009, 0, 2, 32)),
+            (3002, instr(3005, 0, 0, 39)),
+            (3003, instr(1000, 3, 5, 56)),
+            (3004, instr(3007, 0, 7, 39)),
+            (3005, instr(0, 3, 2, 50)),
+            (3006, instr(1000, 3, 5, 8)),
+            (3007, instr(1, 0, 1, 51)),
+            (3008, instr(3003, 0, 2, 43)),
+            (3009, instr(3009, 0, 0, 39)),
+        ]);
+        let maxn = prog.symbols.iter().find(|s| s.name == "MAXN").unwrap();
+        assert_eq!(maxn.value, 3001);
+    }
+
+    // The coroutines of TAOCP 1.4.2, translating cards of characters and
+    // digit repeat counts onto punched cards.
+    #[test]
+    fn test_coroutines() {
+        let mut src = String::new();
+        for (loc, op, address) in &[
+            ("*", "SUBROUTINE", "FOR CHARACTER INPUT"),
+            ("READER", "EQU", "16"),
+            ("INPUT", "ORIG", "*+16"),
+            ("NEXTCHAR", "STJ", "9F"),
+            ("", "JXNZ", "3F"),
+            ("1H", "J6N", "2F"),
+            ("", "IN", "INPUT(READER)"),
+            ("", "JBUS", "*(READER)"),
+            ("", "ENN6", "16"),
+            ("2H", "LDX", "INPUT+16,6"),
+            ("", "INC6", "1"),
+            ("3H", "ENTA", "0"),
+            ("", "SLAX", "1"),
+            ("9H", "JANZ", "*"),
+            ("", "JMP", "NEXTCHAR+1"),
+            ("*", "FIRST", "COROUTINE"),
+            ("2H", "INCA", "30"),
+            ("", "JMP", "OUT"),
+            ("IN1", "JMP", "NEXTCHAR"),
+            ("", "DECA", "30"),
+            ("", "JAN", "2B"),
+            ("", "CMPA", "=10="),
+            ("", "JGE", "2B"),
+            ("", "STA", "*+1(0:2)"),
+            ("", "ENT5", "*"),
+            ("", "JMP", "NEXTCHAR"),
+            ("", "JMP", "OUT"),
+            ("", "DEC5", "1"),
+            ("", "J5NN", "*-2"),
+            ("", "JMP", "IN1"),
+            ("*", "SECOND", "COROUTINE"),
+            ("", "ALF", ""),
+            ("OUTPUT", "ORIG", "*+16"),
+            ("PUNCH", "EQU", "17"),
+            ("OUT1", "ENT4", "-16"),
+            ("", "ENT1", "OUTPUT"),
+            ("", "MOVE", "-1,1(16)"),
+            ("1H", "JMP", "IN"),
+            ("", "STA", "OUTPUT+16,4(1:1)"),
+            ("", "CMPA", "PERIOD"),
+            ("", "JE", "9F"),
+            ("", "JMP", "IN"),
+            ("", "STA", "OUTPUT+16,4(2:2)"),
+            ("", "CMPA", "PERIOD"),
+            ("", "JE", "9F"),
+            ("", "JMP", "IN"),
+            ("", "STA", "OUTPUT+16,4(3:3)"),
+            ("", "CMPA", "PERIOD"),
+            ("", "JE", "9F"),
+            ("", "INC4", "1"),
+            ("", "J4N", "1B"),
+            ("9H", "OUT", "OUTPUT(PUNCH)"),
+            ("", "JBUS", "*(PUNCH)"),
+            ("", "JNE", "OUT1"),
+            ("", "HLT", ""),
+            ("PERIOD", "ALF", "    ."),
+            ("*", "INITIALIZATION", "AND LINKAGE"),
+            ("START", "ENT6", "0"),
+            ("", "ENTX", "0"),
+            ("", "JMP", "OUT1"),
+            ("OUT", "STJ", "INX"),
+            ("OUTX", "JMP", "OUT1"),
+            ("IN", "STJ", "OUTX"),
+            ("INX", "JMP", "IN1"),
+            ("", "END", "START"),
+        ] {
+            src.push_str(&card(loc, op, address));
+        }
+        let prog = assemble(&src).unwrap();
+        check(&prog, &[
+            (16, instr(26, 0, 2, 32)),
+            (17, instr(24, 0, 4, 47)),
+            (18, instr(22, 0, 0, 46)),
+            (19, instr(0, 0, 16, 36)),
+            (20, instr(20, 0, 16, 34)),
+            (21, instr(16, 0, 3, 54)),
+            (22, instr(16, 6, 5, 15)),
+            (23, instr(1, 0, 0, 54)),
+            (24, instr(0, 0, 2, 48)),
+            (25, instr(1, 0, 2, 6)),
+            (26, instr(26, 0, 4, 40)),
+            (27, instr(17, 0, 0, 39)),
+            (28, instr(30, 0, 0, 48)),
+            (29, instr(84, 0, 0, 39)),
+            (30, instr(16, 0, 0, 39)),
+            (31, instr(30, 0, 1, 48)),
+            (32, instr(28, 0, 0, 40)),
+            (33, instr(88, 0, 5, 56)),
+            (34, instr(28, 0, 7, 39)),
+            (35, instr(36, 0, 2, 24)),
+            (36, instr(36, 0, 2, 53)),
+            (37, instr(16, 0, 0, 39)),
+            (38, instr(84, 0, 0, 39)),
+            (39, instr(1, 0, 1, 53)),
+            (40, instr(38, 0, 3, 45)),
+            (41, instr(30, 0, 0, 39)),
+            (42, 0),
+            (59, instr(-16, 0, 2, 52)),
+            (60, instr(43, 0, 2, 49)),
+            (61, instr(-1, 1, 16, 7)),
+            (62, instr(86, 0, 0, 39)),
+            (63, instr(59, 4, 9, 24)),
+            (64, instr(80, 0, 5, 56)),
+            (65, instr(76, 0, 5, 39)),
+            (66, instr(86, 0, 0, 39)),
+            (67, instr(59, 4, 18, 24)),
+            (68, instr(80, 0, 5, 56)),
+            (69, instr(76, 0, 5, 39)),
+            (70, instr(86, 0, 0, 39)),
+            (71, instr(59, 4, 27, 24)),
+            (72, instr(80, 0, 5, 56)),
+            (73, instr(76, 0, 5, 39)),
+            (74, instr(1, 0, 0, 52)),
+            (75, instr(62, 0, 0, 44)),
+            (76, instr(43, 0, 17, 37)),
+            (77, instr(77, 0, 17, 34)),
+            (78, instr(59, 0, 8, 39)),
+            (79, instr(0, 0, 2, 5)),
+            (80, alf("    .")),
+            (81, instr(0, 0, 2, 54)),
+            (82, instr(0, 0, 2, 55)),
+            (83, instr(59, 0, 0, 39)),
+            (84, instr(87, 0, 2, 32)),
+            (85, instr(59, 0, 0, 39)),
+            (86, instr(85, 0, 2, 32)),
+            (87, instr(30, 0, 0, 39)),
+            (88, 10),
+        ]);
+        assert_eq!(prog.start, 81);
+    }
+
+    #[test]
+    fn test_expressions_and_wvalues() {
+        let src = "\
+* Examples from the MIXAL rules in TAOCP 1.3.2
+         ORIG 3000
+A        CON  -1+5*20/6
+B        CON  1//3
+C        CON  1:3
+D        CON  *-3
+E        CON  ***
+F        CON  1,-1000(0:2)
+G        CON  -1000(0:2),1
+H        CON  UNDEF
+         LDA  UNDEF
+         LDA  LATER
+LATER    LDX  =5(1:1)=
+         END  3000
+";
+        let prog = assemble(src).unwrap();
+        let m = &prog.memory;
+        assert_eq!(mix_util::from_mix_word(m[3000]), 13);
+        assert_eq!(mix_util::from_mix_word(m[3001]), (1 << 30) / 3);
+        assert_eq!(mix_util::from_mix_word(m[3002]), 11);
+        assert_eq!(mix_util::from_mix_word(m[3003]), 3000);
+        assert_eq!(mix_util::from_mix_word(m[3004]), 3004 * 3004);
+        assert_eq!(m[3005], mix_util::word_from_bytes(1, 15, 40, 0, 0, 1));
+        // A later part of a W-value overrides the whole word.
+        assert_eq!(m[3006], 1);
+        // UNDEF is used as an address but never defined, so it gets a CON 0
+        // in the pool. LATER is defined after its first use, so it doesn't.
+        assert_eq!(m[3007], 3011);
+        assert_eq!(m[3008], instr(3011, 0, 5, 8));
+        assert_eq!(m[3009], instr(3010, 0, 5, 8));
+        assert_eq!(m[3010], instr(3012, 0, 5, 15));
+        assert_eq!(m[3011], 0);
+        assert_eq!(m[3012], mix_util::word_from_bytes(0, 5, 0, 0, 0, 0));
+
+        // A word keeps the sign of -0, in a literal too.
+        let prog = assemble(" ORIG 100\n CON -0\n LDA =-0=\n END 100\n").unwrap();
+        assert_eq!((prog.memory[100], prog.memory[102]), (1 << 30, 1 << 30));
+    }
+
+    #[test]
+    fn test_errors() {
+        let e = assemble(" ORIG X\nX EQU 5\n END 0\n").err().unwrap();
+        assert_eq!((e.line, e.column), (1, 7));
+        let e = assemble("A NOP\nA NOP\n END 0\n").err().unwrap();
+        assert_eq!((e.line, e.column), (2, 1));
+        let e = assemble(" FOO 1\n END 0\n").err().unwrap();
+        assert_eq!((e.line, e.column), (1, 2));
+        let e = assemble(" LDA 5000\n END 0\n").err().unwrap();
+        assert_eq!((e.line, e.column), (1, 6));
+        let e = assemble(" NOP\n").err().unwrap();
+        assert_eq!(e.line, 2);
+    }
+}
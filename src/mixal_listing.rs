@@ -0,0 +1,73 @@
+use crate::mix_fmt;
+use crate::mixal_asm::{AssembledWord, MixalProgram};
+
+// Format an assembly listing for a program assembled from the given source.
+// Each source line is shown with its line number, preceded by the location
+// and contents of every word it assembled (formatted with fmt_word). The
+// constants placed before END are listed on the END line.
+// After the program comes the symbol table, sorted by name, giving each
+// symbol's value, the line defining it and every line using it.
+pub fn fmt_listing(src: &str, prog: &MixalProgram) -> String {
+    let nlines = src.lines().count();
+    let mut by_line: Vec<Vec<&AssembledWord>> = vec![vec![]; nlines + 1];
+    for w in &prog.words {
+        if w.line <= nlines {
+            by_line[w.line].push(w);
+        }
+    }
+
+    let mut out = String::new();
+    out.push_str(" LOC  WORD               LINE  SOURCE\n");
+    for (i, text) in src.lines().enumerate() {
+        let words = &by_line[i + 1];
+        if words.is_empty() {
+            push_line(&mut out, format!("{:23} {:5}  {}", "", i + 1, text));
+        }
+        for (k, w) in words.iter().enumerate() {
+            let text = if k == 0 { text } else { "" };
+            push_line(&mut out, format!("{:04}:{} {:5}  {}",
+                                        w.addr, mix_fmt::fmt_word(w.word),
+                                        i + 1, text));
+        }
+    }
+
+    let mut symbols: Vec<_> = prog.symbols.iter().collect();
+    symbols.sort_by(|a, b| a.name.cmp(&b.name));
+    out.push_str("\nSYMBOL          VALUE  DEFINED  USED\n");
+    for s in symbols {
+        let uses: Vec<String> = s.uses.iter().map(|u| u.to_string()).collect();
+        push_line(&mut out, format!("{:<10} {:>10}  {:>7}  {}",
+                                    s.name, s.value, s.line, uses.join(" ")));
+    }
+    out
+}
+
+fn push_line(out: &mut String, line: String) {
+    out.push_str(line.trim_end());
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mixal_asm;
+
+    #[test]
+    fn test_listing() {
+        let src = "\
+N        EQU  2
+         ORIG 100
+START    LDA  =N=,N
+         JMP  START
+         END  START
+";
+        let prog = mixal_asm::assemble(src).unwrap();
+        let listing = fmt_listing(src, &prog);
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!(lines[1], "                            1  N        EQU  2");
+        assert_eq!(lines[3], "0100: +  1 38  2  5  8     3  START    LDA  =N=,N");
+        assert_eq!(lines[5], "0102: +  0  0  0  0  2     5           END  START");
+        assert_eq!(lines[8], "N                   2        1  3");
+        assert_eq!(lines[9], "START             100        3  4 5");
+    }
+}
@@ -0,0 +1,673 @@
+// Front end for MIXAL, the MIX assembly language described in TAOCP 1.3.2.
+//
+// Source is processed one line at a time. Each line has up to three
+// columns: LOC (the label), OP (the operation) and ADDRESS (the operand).
+// Anything after the address is a remark. Lines starting with '*' are
+// comments, and blank lines are ignored.
+//
+// Both Knuth's fixed card layout (LOC in columns 1-10, OP in 12-15, ADDRESS
+// from 17) and free format (fields separated by whitespace, with a line
+// starting in whitespace having no LOC) are accepted. The only place where
+// the difference matters is ALF, whose operand is exactly five characters
+// and may itself contain spaces.
+//
+// As in GNU MDK's mixasm, an ALF operand may instead be written in double
+// quotes, anywhere after OP, for example ALF "RED P". That is the only one
+// of mixasm's extensions to MIXAL accepted here. The others are left out on
+// purpose until they can be checked against mixasm itself, so source using
+// them fails to assemble rather than assembling to something else.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MixalError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for MixalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for MixalError {}
+
+fn error(line: usize, column: usize, message: String) -> MixalError {
+    MixalError { line, column, message }
+}
+
+// A single column of a source line. Columns are 1-based, counted in chars.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub text: String,
+    pub column: usize,
+}
+
+// A source line split into its LOC, OP and ADDRESS columns.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MixalLine {
+    pub line: usize,
+    pub loc: Option<Field>,
+    pub op: Field,
+    pub address: Option<Field>,
+}
+
+// Column (1-based) where OP starts and ADDRESS starts in Knuth's card layout.
+const FIXED_OP_COLUMN: usize = 12;
+const FIXED_ADDRESS_COLUMN: usize = 17;
+
+// The number of characters in an ALF operand.
+pub const ALF_LEN: usize = 5;
+
+// Split a source line into columns. Returns None for comments and blank
+// lines.
+pub fn lex_line(line: usize, s: &str) -> Result<Option<MixalLine>, MixalError> {
+    let chars: Vec<char> = s.trim_end().chars().collect();
+    if chars.is_empty() || chars[0] == '*' {
+        return Ok(None);
+    }
+    let mut ix = 0;
+    let loc = if chars[0].is_whitespace() {
+        None
+    } else {
+        Some(next_field(&chars, &mut ix))
+    };
+    skip_whitespace(&chars, &mut ix);
+    if ix >= chars.len() {
+        return match loc {
+            Some(l) => Err(error(line, l.column,
+                                 format!("Missing OP after label {}", l.text))),
+            None => Ok(None),
+        };
+    }
+    let op = next_field(&chars, &mut ix);
+    let address = if op.text == "ALF" {
+        alf_field(&chars, &op, ix)
+    } else {
+        skip_whitespace(&chars, &mut ix);
+        if ix < chars.len() {
+            Some(next_field(&chars, &mut ix))
+        } else {
+            None
+        }
+    };
+    Ok(Some(MixalLine { line, loc, op, address }))
+}
+
+fn skip_whitespace(chars: &[char], ix: &mut usize) {
+    while *ix < chars.len() && chars[*ix].is_whitespace() {
+        *ix += 1;
+    }
+}
+
+fn next_field(chars: &[char], ix: &mut usize) -> Field {
+    let start = *ix;
+    while *ix < chars.len() && !chars[*ix].is_whitespace() {
+        *ix += 1;
+    }
+    Field {
+        text: chars[start .. *ix].iter().collect(),
+        column: start + 1,
+    }
+}
+
+// The ALF operand is the five characters starting in column 17 when the
+// line follows the card layout, and otherwise the five characters after the
+// single separator following OP. Short operands are padded with blanks.
+// A quoted operand is the characters between the quotes, also padded. The
+// parser checks that there are at most five of them.
+fn alf_field(chars: &[char], op: &Field, op_end: usize) -> Option<Field> {
+    let mut ix = op_end;
+    skip_whitespace(chars, &mut ix);
+    if ix < chars.len() && chars[ix] == '"' {
+        let end = chars[ix + 1 ..].iter().position(|&c| c == '"')
+            .map_or(chars.len(), |n| ix + 1 + n);
+        let mut text: String = chars[ix .. end].iter().collect();
+        if end < chars.len() {
+            text.push('"');
+        }
+        return Some(Field { text, column: ix + 1 });
+    }
+    let start = if op.column == FIXED_OP_COLUMN &&
+        op_end < FIXED_ADDRESS_COLUMN {
+        FIXED_ADDRESS_COLUMN - 1
+    } else {
+        op_end + 1
+    };
+    let mut text = String::new();
+    for i in start .. start + ALF_LEN {
+        text.push(if i < chars.len() { chars[i] } else { ' ' });
+    }
+    Some(Field { text, column: start + 1 })
+}
+
+// The five characters of an ALF operand, unquoting a quoted one.
+fn alf_text(line: usize, f: &Field) -> Result<String, MixalError> {
+    if !f.text.starts_with('"') {
+        return Ok(f.text.clone());
+    }
+    let chars: Vec<char> = f.text.chars().collect();
+    if chars.len() < 2 || chars[chars.len() - 1] != '"' {
+        return Err(error(line, f.column,
+                         "Missing closing quote in ALF operand".to_string()));
+    }
+    if chars.len() - 2 > ALF_LEN {
+        return Err(error(line, f.column, format!(
+            "ALF operand {} is longer than {} characters", f.text, ALF_LEN)));
+    }
+    Ok(format!("{:1$}", chars[1 .. chars.len() - 1].iter().collect::<String>(),
+               ALF_LEN))
+}
+
+// An atomic expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Atom {
+    Number(i64),
+    Symbol(String),
+    // Local symbol references dB and dF.
+    Backward(u8),
+    Forward(u8),
+    // The location counter, written '*'.
+    Here,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    // a//b, the fraction (a * 64^5) / b
+    FracDiv,
+    // a:b, the field specification 8a + b
+    Field,
+}
+
+// An expression. Operators have no precedence in MIXAL; they are applied
+// strictly from left to right.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub negate: bool,
+    pub first: Atom,
+    pub rest: Vec<(BinOp, Atom)>,
+    pub column: usize,
+}
+
+// A W-value: a list of expressions, each with an optional field spec,
+// as used by CON, EQU, ORIG and END, and inside literal constants.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WValue {
+    pub parts: Vec<(Expr, Option<Expr>)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum APart {
+    Empty,
+    Expr(Expr),
+    // A literal constant =W-value=
+    Literal(WValue),
+}
+
+// The operand of a MIX instruction: A,I(F)
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstrOperand {
+    pub a: APart,
+    pub index: Option<Expr>,
+    pub field: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Instruction(InstrOperand),
+    WValue(WValue),
+    Alf(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Label {
+    Symbol(String),
+    // Local symbol definition dH
+    Local(u8),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub line: usize,
+    pub label: Option<Label>,
+    pub op: String,
+    pub op_column: usize,
+    pub operand: Operand,
+}
+
+impl Expr {
+    fn symbols_used<'a>(&'a self, out: &mut Vec<&'a str>) {
+        let atoms = std::iter::once(&self.first)
+            .chain(self.rest.iter().map(|(_, a)| a));
+        for a in atoms {
+            if let Atom::Symbol(s) = a {
+                out.push(s);
+            }
+        }
+    }
+}
+
+impl WValue {
+    fn symbols_used<'a>(&'a self, out: &mut Vec<&'a str>) {
+        for (e, f) in &self.parts {
+            e.symbols_used(out);
+            if let Some(f) = f {
+                f.symbols_used(out);
+            }
+        }
+    }
+}
+
+impl Statement {
+    // Every symbol used in the operand, in order of appearance.
+    pub fn symbols_used(&self) -> Vec<&str> {
+        let mut out = vec![];
+        match &self.operand {
+            Operand::Instruction(o) => {
+                match &o.a {
+                    APart::Expr(e) => e.symbols_used(&mut out),
+                    APart::Literal(w) => w.symbols_used(&mut out),
+                    APart::Empty => {},
+                }
+                for e in o.index.iter().chain(o.field.iter()) {
+                    e.symbols_used(&mut out);
+                }
+            },
+            Operand::WValue(w) => w.symbols_used(&mut out),
+            Operand::Alf(_) => {},
+        }
+        out
+    }
+}
+
+// Pseudo-operations whose operand is a W-value.
+fn takes_wvalue(op: &str) -> bool {
+    op == "EQU" || op == "ORIG" || op == "CON" || op == "END"
+}
+
+// Parse a whole MIXAL program into statements, one per non-comment line.
+// Stops at the first error.
+pub fn parse_program(src: &str) -> Result<Vec<Statement>, MixalError> {
+    let mut stmts = vec![];
+    for (i, s) in src.lines().enumerate() {
+        if let Some(stmt) = parse_line(i + 1, s)? {
+            stmts.push(stmt);
+        }
+    }
+    Ok(stmts)
+}
+
+// Parse a single source line. Returns None for comments and blank lines.
+pub fn parse_line(line: usize, s: &str) -> Result<Option<Statement>, MixalError> {
+    let l = match lex_line(line, s)? {
+        Some(l) => l,
+        None => return Ok(None),
+    };
+    let label = match &l.loc {
+        Some(f) => Some(parse_label(line, f)?),
+        None => None,
+    };
+    if !is_symbol_text(&l.op.text) {
+        return Err(error(line, l.op.column,
+                         format!("Invalid operation {}", l.op.text)));
+    }
+    let operand = if l.op.text == "ALF" {
+        // The lexer always produces an ALF field.
+        let f = l.address.unwrap_or(Field { text: String::new(), column: 0 });
+        Operand::Alf(alf_text(line, &f)?)
+    } else {
+        let (text, column) = match &l.address {
+            Some(f) => (f.text.as_str(), f.column),
+            None => ("", l.op.column + l.op.text.chars().count()),
+        };
+        let mut p = Parser::new(line, text, column)?;
+        let operand = if takes_wvalue(&l.op.text) {
+            if p.at_end() && l.op.text == "END" {
+                Operand::WValue(WValue { parts: vec![] })
+            } else {
+                Operand::WValue(p.wvalue()?)
+            }
+        } else {
+            Operand::Instruction(p.instr_operand()?)
+        };
+        p.expect_end()?;
+        operand
+    };
+    Ok(Some(Statement {
+        line,
+        label,
+        op: l.op.text,
+        op_column: l.op.column,
+        operand,
+    }))
+}
+
+// Symbols are 1 to 10 letters and digits, at least one of them a letter.
+fn is_symbol_text(s: &str) -> bool {
+    let n = s.chars().count();
+    (1..=10).contains(&n) &&
+        s.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()) &&
+        s.chars().any(|c| c.is_ascii_uppercase())
+}
+
+// Recognize dH, dB and dF.
+fn local_symbol(s: &str) -> Option<(u8, char)> {
+    let cs: Vec<char> = s.chars().collect();
+    if cs.len() == 2 && cs[0].is_ascii_digit() &&
+        (cs[1] == 'H' || cs[1] == 'B' || cs[1] == 'F') {
+        Some((cs[0] as u8 - b'0', cs[1]))
+    } else {
+        None
+    }
+}
+
+fn parse_label(line: usize, f: &Field) -> Result<Label, MixalError> {
+    match local_symbol(&f.text) {
+        Some((d, 'H')) => Ok(Label::Local(d)),
+        Some(_) => Err(error(line, f.column, format!(
+            "Local reference {} cannot be used as a label", f.text))),
+        None if is_symbol_text(&f.text) => Ok(Label::Symbol(f.text.clone())),
+        None => Err(error(line, f.column,
+                          format!("Invalid symbol {}", f.text))),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Word(String),
+    Star,
+    Plus,
+    Minus,
+    Slash,
+    SlashSlash,
+    Colon,
+    Comma,
+    LParen,
+    RParen,
+    Equals,
+}
+
+// Recursive descent parser over the tokens of an ADDRESS field.
+struct Parser {
+    line: usize,
+    toks: Vec<(Tok, usize)>,
+    pos: usize,
+    end_column: usize,
+}
+
+impl Parser {
+    fn new(line: usize, s: &str, column: usize) -> Result<Parser, MixalError> {
+        let chars: Vec<char> = s.chars().collect();
+        let mut toks = vec![];
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            let col = column + i;
+            let tok = match c {
+                '*' => Tok::Star,
+                '+' => Tok::Plus,
+                '-' => Tok::Minus,
+                '/' if i + 1 < chars.len() && chars[i + 1] == '/' => {
+                    i += 1;
+                    Tok::SlashSlash
+                },
+                '/' => Tok::Slash,
+                ':' => Tok::Colon,
+                ',' => Tok::Comma,
+                '(' => Tok::LParen,
+                ')' => Tok::RParen,
+                '=' => Tok::Equals,
+                _ if c.is_ascii_alphanumeric() => {
+                    let start = i;
+                    while i + 1 < chars.len() && chars[i + 1].is_ascii_alphanumeric() {
+                        i += 1;
+                    }
+                    Tok::Word(chars[start ..= i].iter().collect())
+                },
+                _ => return Err(error(line, col,
+                                      format!("Unexpected character '{}'", c))),
+            };
+            toks.push((tok, col));
+            i += 1;
+        }
+        Ok(Parser { line, toks, pos: 0, end_column: column + chars.len() })
+    }
+
+    fn peek(&self) -> Option<&Tok> {
+        self.toks.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn column(&self) -> usize {
+        self.toks.get(self.pos).map(|(_, c)| *c).unwrap_or(self.end_column)
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.toks.len()
+    }
+
+    fn err<T>(&self, message: String) -> Result<T, MixalError> {
+        Err(error(self.line, self.column(), message))
+    }
+
+    fn expect(&mut self, t: Tok, what: &str) -> Result<(), MixalError> {
+        if self.peek() == Some(&t) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            self.err(format!("Expected {}", what))
+        }
+    }
+
+    fn expect_end(&self) -> Result<(), MixalError> {
+        if self.at_end() {
+            Ok(())
+        } else {
+            self.err("Unexpected trailing characters in address".to_string())
+        }
+    }
+
+    fn atom(&mut self) -> Result<Atom, MixalError> {
+        let atom = match self.peek() {
+            Some(Tok::Star) => Atom::Here,
+            Some(Tok::Word(w)) => {
+                if w.chars().all(|c| c.is_ascii_digit()) {
+                    if w.len() > 10 {
+                        return self.err(format!(
+                            "Number {} has more than 10 digits", w));
+                    }
+                    // At most 10 digits, so this always fits.
+                    Atom::Number(w.parse::<i64>().unwrap())
+                } else {
+                    match local_symbol(w) {
+                        Some((d, 'B')) => Atom::Backward(d),
+                        Some((d, 'F')) => Atom::Forward(d),
+                        Some(_) => return self.err(format!(
+                            "Local label {} cannot be used in an expression; \
+                             use {}B or {}F", w, &w[..1], &w[..1])),
+                        None if is_symbol_text(w) => Atom::Symbol(w.clone()),
+                        None => return self.err(format!("Invalid symbol {}", w)),
+                    }
+                }
+            },
+            _ => return self.err("Expected a number, symbol or *".to_string()),
+        };
+        self.pos += 1;
+        Ok(atom)
+    }
+
+    fn binop(&self) -> Option<BinOp> {
+        match self.peek() {
+            Some(Tok::Plus) => Some(BinOp::Add),
+            Some(Tok::Minus) => Some(BinOp::Sub),
+            Some(Tok::Star) => Some(BinOp::Mul),
+            Some(Tok::Slash) => Some(BinOp::Div),
+            Some(Tok::SlashSlash) => Some(BinOp::FracDiv),
+            Some(Tok::Colon) => Some(BinOp::Field),
+            _ => None,
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, MixalError> {
+        let column = self.column();
+        let mut negate = false;
+        match self.peek() {
+            Some(Tok::Plus) => self.pos += 1,
+            Some(Tok::Minus) => {
+                negate = true;
+                self.pos += 1;
+            },
+            _ => {},
+        }
+        let first = self.atom()?;
+        let mut rest = vec![];
+        while let Some(op) = self.binop() {
+            self.pos += 1;
+            rest.push((op, self.atom()?));
+        }
+        Ok(Expr { negate, first, rest, column })
+    }
+
+    fn field_part(&mut self) -> Result<Option<Expr>, MixalError> {
+        if self.peek() != Some(&Tok::LParen) {
+            return Ok(None);
+        }
+        self.pos += 1;
+        let e = self.expr()?;
+        self.expect(Tok::RParen, "')' after field specification")?;
+        Ok(Some(e))
+    }
+
+    fn wvalue(&mut self) -> Result<WValue, MixalError> {
+        let mut parts = vec![];
+        loop {
+            let e = self.expr()?;
+            let f = self.field_part()?;
+            parts.push((e, f));
+            if self.peek() == Some(&Tok::Comma) {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        Ok(WValue { parts })
+    }
+
+    fn instr_operand(&mut self) -> Result<InstrOperand, MixalError> {
+        let a = match self.peek() {
+            None | Some(Tok::Comma) | Some(Tok::LParen) => APart::Empty,
+            Some(Tok::Equals) => {
+                self.pos += 1;
+                let w = self.wvalue()?;
+                self.expect(Tok::Equals, "'=' closing literal constant")?;
+                APart::Literal(w)
+            },
+            _ => APart::Expr(self.expr()?),
+        };
+        let index = if self.peek() == Some(&Tok::Comma) {
+            self.pos += 1;
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        let field = self.field_part()?;
+        Ok(InstrOperand { a, index, field })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sym(s: &str) -> Atom {
+        Atom::Symbol(s.to_string())
+    }
+
+    #[test]
+    fn test_lex_columns() {
+        let l = lex_line(1, "START      IOC  0(PRINTER)  Skip page").unwrap().unwrap();
+        assert_eq!(l.loc.unwrap().text, "START");
+        assert_eq!(l.op.text, "IOC");
+        assert_eq!(l.address.unwrap().text, "0(PRINTER)");
+        assert_eq!(lex_line(2, "* A comment").unwrap(), None);
+        assert_eq!(lex_line(3, "   ").unwrap(), None);
+
+        // ALF in card layout and in free format
+        let l = lex_line(4, "TITLE      ALF  FIRST").unwrap().unwrap();
+        assert_eq!(l.address.unwrap().text, "FIRST");
+        let l = lex_line(5, "           ALF   FIVE").unwrap().unwrap();
+        assert_eq!(l.address.unwrap().text, " FIVE");
+        let l = lex_line(6, " ALF RED P").unwrap().unwrap();
+        assert_eq!(l.address.unwrap().text, "RED P");
+
+        // Quoted ALF, as in mixasm
+        let s = parse_line(7, "MSG\tALF\t\"AB C\" Remark").unwrap().unwrap();
+        assert_eq!(s.operand, Operand::Alf("AB C ".to_string()));
+        assert!(parse_line(8, " ALF \"SIXSIX\"").is_err());
+        assert!(parse_line(9, " ALF \"OPEN").is_err());
+    }
+
+    #[test]
+    fn test_parse_instruction() {
+        let s = parse_line(1, "2H LDA PRIME+L,1(1:3)").unwrap().unwrap();
+        assert_eq!(s.label, Some(Label::Local(2)));
+        assert_eq!(s.op, "LDA");
+        match s.operand {
+            Operand::Instruction(i) => {
+                assert_eq!(i.a, APart::Expr(Expr {
+                    negate: false,
+                    first: sym("PRIME"),
+                    rest: vec![(BinOp::Add, sym("L"))],
+                    column: 8,
+                }));
+                assert_eq!(i.index.unwrap().first, Atom::Number(1));
+                let f = i.field.unwrap();
+                assert_eq!(f.first, Atom::Number(1));
+                assert_eq!(f.rest, vec![(BinOp::Field, Atom::Number(3))]);
+            },
+            _ => panic!("expected an instruction operand"),
+        }
+
+        let s = parse_line(2, " JMP ***+2B").unwrap().unwrap();
+        match s.operand {
+            Operand::Instruction(i) => assert_eq!(i.a, APart::Expr(Expr {
+                negate: false,
+                first: Atom::Here,
+                rest: vec![(BinOp::Mul, Atom::Here), (BinOp::Add, Atom::Backward(2))],
+                column: 6,
+            })),
+            _ => panic!("expected an instruction operand"),
+        }
+
+        let s = parse_line(3, " LD1 =1-L=").unwrap().unwrap();
+        match s.operand {
+            Operand::Instruction(i) => match i.a {
+                APart::Literal(w) => assert_eq!(w.parts.len(), 1),
+                _ => panic!("expected a literal"),
+            },
+            _ => panic!("expected an instruction operand"),
+        }
+    }
+
+    #[test]
+    fn test_parse_wvalue_and_errors() {
+        let s = parse_line(1, " CON 1(1:1),-1000(2:3)").unwrap().unwrap();
+        match s.operand {
+            Operand::WValue(w) => {
+                assert_eq!(w.parts.len(), 2);
+                assert!(w.parts[1].0.negate);
+            },
+            _ => panic!("expected a W-value"),
+        }
+
+        let e = parse_line(7, " LDA 2000,1)").unwrap_err();
+        assert_eq!((e.line, e.column), (7, 12));
+        let e = parse_line(8, "3F LDA 0").unwrap_err();
+        assert_eq!((e.line, e.column), (8, 1));
+        let e = parse_line(9, " LDA 2H").unwrap_err();
+        assert_eq!((e.line, e.column), (9, 6));
+    }
+}
@@ -0,0 +1,655 @@
+// Command language of the mixdb debugger.
+//
+// A command is a line of whitespace separated words. Words and addresses
+// given as values are read with mix_parse::parse_word, so any notation it
+// understands may be used, for example
+//   set a -123
+//   set x + 1 2 3 4 5
+//   set mem 2000 "HELLO"
+
+use crate::mix_parse;
+use crate::mix_trace::TraceFormat;
+use crate::mix_types::*;
+use crate::mix_util;
+
+// Where to put a breakpoint.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Location {
+    Address(usize),
+    Label(String),
+    // A line of a source file.
+    Line(String, usize),
+}
+
+// How print shows a value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PrintFormat {
+    Integer,
+    Word,
+    Text,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Help,
+    Run,
+    Continue,
+    Step,
+    Next,
+    Finish,
+    Backtrace,
+    ReverseStep,
+    ReverseContinue,
+    Goto(u64),
+    // Trace instructions in the given inclusive ranges of locations, or
+    // everywhere if none are given, to a file or to the terminal.
+    TraceOn(TraceFormat, Vec<(usize, usize)>, Option<String>),
+    TraceOff,
+    // Record execution, so that it can be run backwards, or stop.
+    Record(bool),
+    // A breakpoint, with the text of its condition if it has one.
+    Break(Location, Option<String>),
+    Print(PrintFormat, String),
+    // Watch a field, given as a field specification, of a word.
+    Watch(WatchKind, Location, MixByte),
+    // Delete the given breakpoint or watchpoint, or all of them.
+    Delete(Option<usize>),
+    Disable(usize),
+    Enable(usize),
+    Ignore(usize, usize),
+    InfoBreakpoints,
+    List(Option<usize>),
+    // A file, and where to put its words if not over the whole machine.
+    Load(String, Option<usize>),
+    Reload,
+    Dump(String),
+    Write(String),
+    Save(String),
+    Restore(String),
+    ShowRegisters,
+    ShowState,
+    ShowInstructions,
+    // An inclusive range of memory locations.
+    ShowMemory(usize, usize),
+    // A value for an index register or J has been checked to fit.
+    SetRegister(Register, MixWord),
+    SetMemory(usize, MixWord),
+    SetOverflow(bool),
+    SetComparison(MixCompare),
+    SetPc(MixAddr),
+}
+
+// The syntax of each command, for help and error messages.
+pub const USAGE: &[(&str, &str)] = &[
+    ("help", "show this list of commands"),
+    ("run", "run the program from the start, as reload leaves it"),
+    ("continue", "run on until the machine halts or reaches a breakpoint"),
+    ("step", "execute a single instruction"),
+    ("next", "execute an instruction, running a subroutine it calls to completion"),
+    ("finish", "run until the current subroutine returns"),
+    ("backtrace", "show the subroutine calls in progress"),
+    ("rstep", "undo the last instruction"),
+    ("rcontinue", "run backwards to a breakpoint or a write to a watched word"),
+    ("goto <instruction count>", "go back or forward to after that many instructions"),
+    ("trace on [text|json] [<address>[-<address>]]... [to <file>]",
+     "log each instruction executed, in the given ranges"),
+    ("trace off", "stop logging instructions"),
+    ("record on|off", "record execution for rstep, rcontinue and goto (on at start)"),
+    ("break <address|label|file:line> [if <expr>]",
+     "stop before executing a location"),
+    ("watch <address|label>[(L:R)]", "stop after an instruction writes a word"),
+    ("rwatch <address|label>[(L:R)]", "stop after an instruction reads a word"),
+    ("awatch <address|label>[(L:R)]", "stop after an instruction accesses a word"),
+    ("delete [<breakpoint>]", "delete a breakpoint, or all of them"),
+    ("disable <breakpoint>", "stop a breakpoint from stopping execution"),
+    ("enable <breakpoint>", "let a disabled breakpoint stop execution again"),
+    ("ignore <breakpoint> <count>", "pass over the next count hits"),
+    ("info breakpoints", "list the breakpoints and watchpoints"),
+    ("list [<line>]", "show the source around the pc, or around a line"),
+    ("print[/d|/w|/t] <expr>", "show a value as an integer, word or text"),
+    ("load <file> [at <address>]", "load a program: an image, an MDK program, a dump or MIXAL source"),
+    ("reload", "restore the machine as it was after the last load"),
+    ("dump <file>", "write memory to a file as a dump"),
+    ("write <file>", "write the machine to a file as an image (an MDK program for *.mix)"),
+    ("save <file>", "save the whole state of the machine to a file"),
+    ("restore <file>", "put the machine back in a state saved to a file"),
+    ("show registers", "show the registers"),
+    ("show state", "show the registers, flags and program counter"),
+    ("show instructions", "disassemble the instructions around the pc"),
+    ("show mem <address> [<address>]", "show memory, or a range of it"),
+    ("set <register> <value>", "set a, x, i1-i6 or j"),
+    ("set mem <address> <word>", "set a word of memory"),
+    ("set overflow on|off", "set the overflow toggle"),
+    ("set cmp less|equal|greater", "set the comparison indicator"),
+    ("set pc <address>", "set the program counter"),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandError {
+    pub message: String,
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+// An error explaining how the command starting with the given syntax
+// should be used.
+fn usage_error(syntax: &str, problem: &str) -> CommandError {
+    let usages: Vec<String> = USAGE.iter()
+        .filter(|(u, _)| u.starts_with(syntax))
+        .map(|(u, _)| format!("  {}", u)).collect();
+    CommandError {
+        message: format!("{}. Usage:\n{}", problem, usages.join("\n")),
+    }
+}
+
+// Split off the first word of s, returning it and the rest of s.
+fn next_word(s: &str) -> (&str, &str) {
+    let s = s.trim_start();
+    let end = s.find(char::is_whitespace).unwrap_or(s.len());
+    (&s[.. end], s[end ..].trim_start())
+}
+
+fn parse_location(s: &str, syntax: &str) -> Result<usize, CommandError> {
+    match s.parse::<usize>() {
+        Ok(a) if a < MEM_SIZE => Ok(a),
+        Ok(a) => Err(usage_error(syntax, &format!(
+            "Address {} is outside memory (0 to {})", a, MEM_SIZE - 1))),
+        Err(_) => Err(usage_error(syntax, &format!("Bad address '{}'", s))),
+    }
+}
+
+fn parse_value(s: &str, syntax: &str) -> Result<MixWord, CommandError> {
+    if s.is_empty() {
+        return Err(usage_error(syntax, "Missing value"));
+    }
+    mix_parse::parse_word(s).map_err(|e| usage_error(syntax, &e.message()))
+}
+
+fn parse_register(s: &str) -> Option<Register> {
+    match s {
+        "a" => Some(Register::A),
+        "x" => Some(Register::X),
+        "j" => Some(Register::J),
+        _ => match s.strip_prefix('i').map(|n| n.parse::<usize>()) {
+            Some(Ok(n)) if (1 ..= 6).contains(&n) => Some(Register::I(n)),
+            _ => None,
+        },
+    }
+}
+
+// Convert a word to the value of an index register or J, if it fits.
+pub fn word_to_addr(w: MixWord) -> Option<MixAddr> {
+    if mix_util::get_bytes(w, 1, 3) != 0 {
+        return None;
+    }
+    let sign = mix_util::get_bytes(w, 0, 0) as MixAddr;
+    Some((sign << 12) | mix_util::get_bytes(w, 4, 5) as MixAddr)
+}
+
+fn parse_set(args: &str) -> Result<Command, CommandError> {
+    let (what, rest) = next_word(args);
+    match what {
+        "mem" | "m" => {
+            let (addr, value) = next_word(rest);
+            let syntax = "set mem";
+            let addr = parse_location(addr, syntax)?;
+            Ok(Command::SetMemory(addr, parse_value(value, syntax)?))
+        },
+        "overflow" | "ov" => match rest {
+            "on" => Ok(Command::SetOverflow(true)),
+            "off" => Ok(Command::SetOverflow(false)),
+            _ => Err(usage_error("set overflow", "Overflow is on or off")),
+        },
+        "cmp" => match rest {
+            "less" | "l" => Ok(Command::SetComparison(MixCompare::Less)),
+            "equal" | "e" => Ok(Command::SetComparison(MixCompare::Equal)),
+            "greater" | "g" => Ok(Command::SetComparison(MixCompare::Greater)),
+            _ => Err(usage_error("set cmp",
+                                 "Comparison is less, equal or greater")),
+        },
+        "pc" => {
+            let addr = parse_location(rest, "set pc")?;
+            Ok(Command::SetPc(addr as MixAddr))
+        },
+        _ => {
+            let syntax = "set <register>";
+            let reg = match parse_register(what) {
+                Some(r) => r,
+                None => {
+                    let problem = if what.is_empty() {
+                        "Missing what to set".to_string()
+                    } else {
+                        format!("Cannot set '{}'", what)
+                    };
+                    return Err(usage_error("set", &problem));
+                },
+            };
+            let w = parse_value(rest, syntax)?;
+            match (reg, word_to_addr(w)) {
+                (Register::A, _) | (Register::X, _) => {},
+                (Register::I(_), Some(_)) => {},
+                (Register::J, Some(a)) if a >> 12 == 0 => {},
+                (Register::J, _) => {
+                    return Err(usage_error(syntax, "J must be from 0 to 4095"));
+                },
+                _ => {
+                    return Err(usage_error(
+                        syntax, "Index registers must be from -4095 to 4095"));
+                },
+            }
+            Ok(Command::SetRegister(reg, w))
+        },
+    }
+}
+
+fn parse_show(args: &str) -> Result<Command, CommandError> {
+    let (what, rest) = next_word(args);
+    match (what, rest) {
+        ("registers", "") | ("r", "") => Ok(Command::ShowRegisters),
+        ("state", "") | ("st", "") => Ok(Command::ShowState),
+        ("instructions", "") | ("i", "") => Ok(Command::ShowInstructions),
+        ("mem", _) | ("m", _) => {
+            let syntax = "show mem";
+            let (first, rest) = next_word(rest);
+            let (last, rest) = next_word(rest);
+            if first.is_empty() || !rest.is_empty() {
+                return Err(usage_error(syntax, "Give one or two addresses"));
+            }
+            let first = parse_location(first, syntax)?;
+            let last = if last.is_empty() {
+                first
+            } else {
+                parse_location(last, syntax)?
+            };
+            if last < first {
+                return Err(usage_error(syntax, "The range is backwards"));
+            }
+            Ok(Command::ShowMemory(first, last))
+        },
+        ("", _) => Err(usage_error("show", "Missing what to show")),
+        _ => Err(usage_error("show", &format!("Cannot show '{}'", args))),
+    }
+}
+
+fn parse_number(s: &str, syntax: &str, what: &str)
+    -> Result<usize, CommandError> {
+    if s.is_empty() {
+        return Err(usage_error(syntax, &format!("Missing {}", what)));
+    }
+    s.parse::<usize>().map_err(|_| usage_error(
+        syntax, &format!("Bad {} '{}'", what, s)))
+}
+
+fn parse_location_or_label(s: &str, syntax: &str)
+    -> Result<Location, CommandError> {
+    if s.is_empty() {
+        return Err(usage_error(syntax, "Missing address or label"));
+    }
+    if let Some(ix) = s.rfind(':') {
+        let line = parse_number(&s[ix + 1 ..], syntax, "line number")?;
+        Ok(Location::Line(s[.. ix].to_string(), line))
+    } else if s.starts_with(|c: char| c.is_ascii_digit()) {
+        Ok(Location::Address(parse_location(s, syntax)?))
+    } else {
+        Ok(Location::Label(s.to_string()))
+    }
+}
+
+// Parse the target of a watchpoint: a location with an optional field
+// specification, which defaults to the whole word.
+fn parse_watch(args: &str, syntax: &str)
+    -> Result<(Location, MixByte), CommandError> {
+    let (target, rest) = next_word(args);
+    if !rest.is_empty() {
+        return Err(usage_error(syntax, "Give a single location"));
+    }
+    let (loc, field) = match target.find('(') {
+        Some(ix) => (&target[.. ix], &target[ix ..]),
+        None => (target, ""),
+    };
+    let f = if field.is_empty() {
+        5
+    } else {
+        let spec: Vec<&str> = field.trim_start_matches('(')
+            .trim_end_matches(')').split(':').collect();
+        match (field.ends_with(')'), spec.as_slice()) {
+            (true, [l, r]) => match (l.parse::<u8>(), r.parse::<u8>()) {
+                (Ok(l), Ok(r)) if l <= r && r <= 5 => l * 8 + r,
+                _ => return Err(usage_error(
+                    syntax, &format!("Bad field {}", field))),
+            },
+            _ => return Err(usage_error(syntax, &format!("Bad field {}", field))),
+        }
+    };
+    Ok((parse_location_or_label(loc, syntax)?, f))
+}
+
+// Parse the single breakpoint number taken by a command.
+fn parse_breakpoint(args: &str, syntax: &str) -> Result<usize, CommandError> {
+    let (n, rest) = next_word(args);
+    if !rest.is_empty() {
+        return Err(usage_error(syntax, "Give a single breakpoint number"));
+    }
+    parse_number(n, syntax, "breakpoint number")
+}
+
+fn parse_file(args: &str, syntax: &str) -> Result<String, CommandError> {
+    let (file, rest) = next_word(args);
+    if file.is_empty() || !rest.is_empty() {
+        return Err(usage_error(syntax, "Give a single file name"));
+    }
+    Ok(file.to_string())
+}
+
+fn parse_trace(args: &str) -> Result<Command, CommandError> {
+    let (what, mut rest) = next_word(args);
+    match what {
+        "on" => {},
+        "off" if rest.is_empty() => return Ok(Command::TraceOff),
+        "off" => {
+            return Err(usage_error("trace off", "'trace off' takes no arguments"));
+        },
+        _ => return Err(usage_error("trace", "Trace is on or off")),
+    }
+    let syntax = "trace on";
+    let mut format = TraceFormat::Text;
+    let mut ranges = vec![];
+    let mut file = None;
+    loop {
+        let (word, after) = next_word(rest);
+        rest = after;
+        match word {
+            "" => break,
+            "text" => format = TraceFormat::Text,
+            "json" => format = TraceFormat::Json,
+            "to" => {
+                file = Some(parse_file(rest, syntax)?);
+                break;
+            },
+            _ => {
+                let (first, last) = match word.find('-') {
+                    Some(ix) => (&word[.. ix], &word[ix + 1 ..]),
+                    None => (word, word),
+                };
+                let first = parse_location(first, syntax)?;
+                let last = parse_location(last, syntax)?;
+                if last < first {
+                    return Err(usage_error(syntax, "The range is backwards"));
+                }
+                ranges.push((first, last));
+            },
+        }
+    }
+    Ok(Command::TraceOn(format, ranges, file))
+}
+
+// Parse a command line. Returns None for a blank line.
+pub fn parse_command(line: &str) -> Result<Option<Command>, CommandError> {
+    let (cmd, args) = next_word(line.trim());
+    let no_args = |name: &str, c: Command| if args.is_empty() {
+        Ok(Some(c))
+    } else {
+        Err(usage_error(name, &format!("'{}' takes no arguments", name)))
+    };
+    match cmd {
+        "" => Ok(None),
+        "help" | "h" => no_args("help", Command::Help),
+        "run" | "r" => no_args("run", Command::Run),
+        "continue" | "c" => no_args("continue", Command::Continue),
+        "step" | "s" => no_args("step", Command::Step),
+        "next" | "n" => no_args("next", Command::Next),
+        "finish" => no_args("finish", Command::Finish),
+        "backtrace" | "bt" => no_args("backtrace", Command::Backtrace),
+        "rstep" | "rs" => no_args("rstep", Command::ReverseStep),
+        "rcontinue" | "rc" => no_args("rcontinue", Command::ReverseContinue),
+        "goto" => match args.parse::<u64>() {
+            Ok(n) => Ok(Some(Command::Goto(n))),
+            Err(_) => Err(usage_error("goto", &format!(
+                "Bad instruction count '{}'", args))),
+        },
+        "trace" => Ok(Some(parse_trace(args)?)),
+        "record" => match args {
+            "on" => Ok(Some(Command::Record(true))),
+            "off" => Ok(Some(Command::Record(false))),
+            _ => Err(usage_error("record", "Give on or off")),
+        },
+        "break" | "b" => {
+            let (loc, rest) = next_word(args);
+            let (word, cond) = next_word(rest);
+            let cond = match word {
+                "" => None,
+                "if" if !cond.is_empty() => Some(cond.to_string()),
+                "if" => return Err(usage_error("break", "Missing condition")),
+                _ => return Err(usage_error("break", "Give a single location")),
+            };
+            let loc = parse_location_or_label(loc, "break")?;
+            Ok(Some(Command::Break(loc, cond)))
+        },
+        "print" | "p" | "print/d" | "p/d" | "print/w" | "p/w" |
+        "print/t" | "p/t" => {
+            let format = match cmd.find('/').map(|ix| &cmd[ix ..]) {
+                Some("/w") => PrintFormat::Word,
+                Some("/t") => PrintFormat::Text,
+                _ => PrintFormat::Integer,
+            };
+            if args.is_empty() {
+                return Err(usage_error("print", "Missing expression"));
+            }
+            Ok(Some(Command::Print(format, args.to_string())))
+        },
+        "watch" | "rwatch" | "awatch" => {
+            let kind = match cmd {
+                "watch" => WatchKind::Write,
+                "rwatch" => WatchKind::Read,
+                _ => WatchKind::Access,
+            };
+            let (loc, f) = parse_watch(args, cmd)?;
+            Ok(Some(Command::Watch(kind, loc, f)))
+        },
+        "delete" => if args.is_empty() {
+            Ok(Some(Command::Delete(None)))
+        } else {
+            Ok(Some(Command::Delete(Some(parse_breakpoint(args, "delete")?))))
+        },
+        "disable" => Ok(Some(Command::Disable(parse_breakpoint(args, "disable")?))),
+        "enable" => Ok(Some(Command::Enable(parse_breakpoint(args, "enable")?))),
+        "ignore" => {
+            let (n, rest) = next_word(args);
+            let (count, rest) = next_word(rest);
+            if !rest.is_empty() {
+                return Err(usage_error("ignore", "Too many arguments"));
+            }
+            let n = parse_number(n, "ignore", "breakpoint number")?;
+            let count = parse_number(count, "ignore", "count")?;
+            Ok(Some(Command::Ignore(n, count)))
+        },
+        "info" | "i" => match args {
+            "breakpoints" | "break" | "b" => Ok(Some(Command::InfoBreakpoints)),
+            _ => Err(usage_error("info", "Unknown info")),
+        },
+        "list" => if args.is_empty() {
+            Ok(Some(Command::List(None)))
+        } else {
+            Ok(Some(Command::List(Some(parse_number(args, "list", "line number")?))))
+        },
+        "load" | "l" => {
+            let (file, rest) = next_word(args);
+            let (word, addr) = next_word(rest);
+            let at = match word {
+                "" => None,
+                "at" => Some(parse_location(addr, "load")?),
+                _ => return Err(usage_error("load", "Give a single file name")),
+            };
+            Ok(Some(Command::Load(parse_file(file, "load")?, at)))
+        },
+        "reload" => no_args("reload", Command::Reload),
+        "dump" | "d" => Ok(Some(Command::Dump(parse_file(args, "dump")?))),
+        "write" | "w" => Ok(Some(Command::Write(parse_file(args, "write")?))),
+        "save" => Ok(Some(Command::Save(parse_file(args, "save")?))),
+        "restore" => Ok(Some(Command::Restore(parse_file(args, "restore")?))),
+        "show" => Ok(Some(parse_show(args)?)),
+        "set" => Ok(Some(parse_set(args)?)),
+        _ => Err(CommandError {
+            message: format!("Unknown command '{}'. Type 'help' for a list \
+                              of commands.", cmd),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Command {
+        parse_command(s).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_parse_blank_and_unknown() {
+        assert_eq!(parse_command("   ").unwrap(), None);
+        assert!(parse_command("frobnicate").is_err());
+        assert!(parse_command("help me").is_err());
+    }
+
+    #[test]
+    fn test_parse_set_show() {
+        assert_eq!(parse("set a -123"),
+                   Command::SetRegister(Register::A, mix_util::to_mix_word(-123)));
+        assert_eq!(parse("set i3 + 0 0 0 1 2"),
+                   Command::SetRegister(Register::I(3), 66));
+        assert_eq!(parse("set mem 2000 \"RED P\""),
+                   Command::SetMemory(2000, mix_util::word_from_bytes(
+                       0, 19, 5, 4, 0, 17)));
+        assert_eq!(parse("set overflow on"), Command::SetOverflow(true));
+        assert_eq!(parse("set cmp less"), Command::SetComparison(MixCompare::Less));
+        assert_eq!(parse("set pc 3000"), Command::SetPc(3000));
+        assert_eq!(parse("show mem 2000 2040"), Command::ShowMemory(2000, 2040));
+        assert_eq!(parse("show mem 7"), Command::ShowMemory(7, 7));
+        assert_eq!(parse(" show  state "), Command::ShowState);
+        assert_eq!(word_to_addr(mix_util::to_mix_word(-5)), Some((1 << 12) | 5));
+
+        assert!(parse_command("set i7 1").is_err());
+        assert!(parse_command("set i1 4096").is_err());
+        assert!(parse_command("set j -1").is_err());
+        assert!(parse_command("set mem 4000 1").is_err());
+        assert!(parse_command("show mem 20 10").is_err());
+        let e = parse_command("set cmp sideways").unwrap_err();
+        assert!(e.message.contains("set cmp less|equal|greater"));
+    }
+
+    #[test]
+    fn test_parse_break() {
+        assert_eq!(parse("break 3000"), Command::Break(Location::Address(3000), None));
+        assert_eq!(parse("b LOOP if rI1 > 10"),
+                   Command::Break(Location::Label("LOOP".to_string()),
+                                  Some("rI1 > 10".to_string())));
+        assert!(parse_command("break 3000 if").is_err());
+        assert_eq!(parse("break prog.mixal:12"),
+                   Command::Break(Location::Line("prog.mixal".to_string(), 12),
+                                  None));
+        assert!(parse_command("break prog.mixal:x").is_err());
+        assert!(parse_command("break 4000").is_err());
+        assert_eq!(parse("delete"), Command::Delete(None));
+        assert_eq!(parse("delete 2"), Command::Delete(Some(2)));
+        assert_eq!(parse("ignore 1 5"), Command::Ignore(1, 5));
+        assert_eq!(parse("info breakpoints"), Command::InfoBreakpoints);
+        assert!(parse_command("disable").is_err());
+    }
+
+    #[test]
+    fn test_parse_watch() {
+        assert_eq!(parse("watch 2000"),
+                   Command::Watch(WatchKind::Write, Location::Address(2000), 5));
+        assert_eq!(parse("awatch TABLE(1:2)"),
+                   Command::Watch(WatchKind::Access,
+                                  Location::Label("TABLE".to_string()), 10));
+        assert_eq!(parse("rwatch 0(0:0)"),
+                   Command::Watch(WatchKind::Read, Location::Address(0), 0));
+        assert!(parse_command("rwatch 2000(3:2)").is_err());
+        assert!(parse_command("rwatch 2000(1:2").is_err());
+    }
+
+    #[test]
+    fn test_parse_print() {
+        assert_eq!(parse("print/w rA + rX"),
+                   Command::Print(PrintFormat::Word, "rA + rX".to_string()));
+        assert_eq!(parse("p/t 2000"),
+                   Command::Print(PrintFormat::Text, "2000".to_string()));
+        assert_eq!(parse("p rI1"),
+                   Command::Print(PrintFormat::Integer, "rI1".to_string()));
+        assert!(parse_command("print").is_err());
+    }
+
+    #[test]
+    fn test_parse_load() {
+        assert_eq!(parse("load prog.mixal"),
+                   Command::Load("prog.mixal".to_string(), None));
+        assert_eq!(parse("load prog.bin at 3000"),
+                   Command::Load("prog.bin".to_string(), Some(3000)));
+        assert!(parse_command("load prog.bin at").is_err());
+        assert!(parse_command("load prog.bin 3000").is_err());
+        assert_eq!(parse("reload"), Command::Reload);
+        assert!(parse_command("reload prog.bin").is_err());
+    }
+
+    #[test]
+    fn test_parse_list() {
+        assert_eq!(parse("list"), Command::List(None));
+        assert_eq!(parse("list 7"), Command::List(Some(7)));
+        assert!(parse_command("list seven").is_err());
+    }
+
+    #[test]
+    fn test_parse_next_finish() {
+        assert_eq!(parse("next"), Command::Next);
+        assert_eq!(parse("n"), Command::Next);
+        assert_eq!(parse("finish"), Command::Finish);
+        assert_eq!(parse("bt"), Command::Backtrace);
+        assert!(parse_command("next 2").is_err());
+        assert!(parse_command("finish 2").is_err());
+    }
+
+    #[test]
+    fn test_parse_rstep_goto() {
+        assert_eq!(parse("rstep"), Command::ReverseStep);
+        assert_eq!(parse("rc"), Command::ReverseContinue);
+        assert_eq!(parse("goto 12345"), Command::Goto(12345));
+        assert!(parse_command("goto").is_err());
+        assert!(parse_command("goto -1").is_err());
+        assert!(parse_command("rstep 2").is_err());
+    }
+
+    #[test]
+    fn test_parse_record() {
+        assert_eq!(parse("record on"), Command::Record(true));
+        assert_eq!(parse("record  off"), Command::Record(false));
+        assert!(parse_command("record").is_err());
+        assert!(parse_command("record off now").is_err());
+    }
+
+    #[test]
+    fn test_parse_save_restore() {
+        assert_eq!(parse("save run.snap"), Command::Save("run.snap".to_string()));
+        assert_eq!(parse("restore run.snap"),
+                   Command::Restore("run.snap".to_string()));
+        assert!(parse_command("save").is_err());
+        assert!(parse_command("restore").is_err());
+    }
+
+    #[test]
+    fn test_parse_trace() {
+        assert_eq!(parse("trace on"), Command::TraceOn(TraceFormat::Text, vec![], None));
+        assert_eq!(parse("trace on json 3000-3010 3020 to t.jsonl"),
+                   Command::TraceOn(TraceFormat::Json, vec![(3000, 3010), (3020, 3020)],
+                                    Some("t.jsonl".to_string())));
+        assert_eq!(parse("trace off"), Command::TraceOff);
+        assert!(parse_command("trace on 3010-3000").is_err());
+        assert!(parse_command("trace on to").is_err());
+    }
+}